@@ -5,19 +5,54 @@ use axum::{
 };
 use serde_json::json;
 use thiserror::Error;
+use crate::infra::fonts::FontError;
+use crate::infra::signing::SigningError;
 
 #[derive(Error, Debug)]
 pub enum AppError {
     #[error("Template error: {0}")]
     TemplateError(#[from] tera::Error),
+    #[error("Validation error: {0}")]
+    ValidationError(String),
+    #[error("URL not allowed: {0}")]
+    UrlNotAllowed(String),
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
     #[error("Browser error: {0}")]
     BrowserError(String),
     #[error("Ghostscript error: {0}")]
     GhostscriptError(String),
+    #[error("PDF processing error: {0}")]
+    PdfProcessingError(String),
+    #[error("Font coverage error: {0}")]
+    FontCoverageError(String),
+    #[error("PDF/A validation error: {0}")]
+    PdfaValidationError(String),
+    #[error("Signing error: {0}")]
+    SigningError(String),
     #[error("Internal error: {0}")]
     Anyhow(#[from] anyhow::Error),
 }
 
+impl From<FontError> for AppError {
+    fn from(e: FontError) -> Self {
+        match e {
+            FontError::Invalid(message) => AppError::ValidationError(message),
+            FontError::Forbidden(message) => AppError::Forbidden(message),
+            FontError::Internal(e) => AppError::Anyhow(e),
+        }
+    }
+}
+
+impl From<SigningError> for AppError {
+    fn from(e: SigningError) -> Self {
+        match e {
+            SigningError::Invalid(message) => AppError::ValidationError(message),
+            SigningError::Internal(e) => AppError::SigningError(format!("{:#}", e)),
+        }
+    }
+}
+
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
         let (status, error_message, error_type, error_details) = match &self {
@@ -26,8 +61,15 @@ impl IntoResponse for AppError {
                 let details = format!("{:#}", e);
                 (StatusCode::BAD_REQUEST, e.to_string(), "template_error", details)
             },
+            AppError::ValidationError(e) => (StatusCode::BAD_REQUEST, e.clone(), "validation_error", e.clone()),
+            AppError::UrlNotAllowed(e) => (StatusCode::FORBIDDEN, e.clone(), "url_not_allowed", e.clone()),
+            AppError::Forbidden(e) => (StatusCode::FORBIDDEN, e.clone(), "forbidden", e.clone()),
             AppError::BrowserError(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.clone(), "browser_error", e.clone()),
             AppError::GhostscriptError(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.clone(), "ghostscript_error", e.clone()),
+            AppError::PdfProcessingError(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.clone(), "pdf_processing_error", e.clone()),
+            AppError::FontCoverageError(e) => (StatusCode::UNPROCESSABLE_ENTITY, e.clone(), "font_coverage_error", e.clone()),
+            AppError::PdfaValidationError(e) => (StatusCode::UNPROCESSABLE_ENTITY, e.clone(), "pdfa_validation_error", e.clone()),
+            AppError::SigningError(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.clone(), "signing_error", e.clone()),
             AppError::Anyhow(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string(), "internal_error", format!("{:#}", e)),
         };
 
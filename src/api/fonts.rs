@@ -0,0 +1,94 @@
+use axum::{
+    body::Bytes,
+    extract::{Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use serde::Deserialize;
+use serde_json::json;
+use std::time::Instant;
+use crate::api::error::AppError;
+use crate::api::state::AppState;
+use crate::infra::fonts::FontStore;
+
+/// Header that scopes font uploads, listings and renders to one tenant.
+pub const TENANT_HEADER: &str = "x-tenant-id";
+
+#[derive(Deserialize, Debug)]
+pub struct UploadParams {
+    pub filename: String,
+}
+
+/// Token sent as `Authorization: Bearer <token>`.
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+}
+
+/// Reads the tenant header, if present, and checks the tenant's token.
+pub fn tenant_from_headers(fonts: &FontStore, headers: &HeaderMap) -> Result<Option<String>, AppError> {
+    let Some(value) = headers.get(TENANT_HEADER) else {
+        return Ok(None);
+    };
+    let tenant = value
+        .to_str()
+        .map_err(|_| AppError::ValidationError("Tenant id must be ASCII".to_string()))?;
+    FontStore::check_tenant(tenant)?;
+    fonts.authorize_tenant(tenant, bearer_token(headers))?;
+    Ok(Some(tenant.to_string()))
+}
+
+pub async fn list_fonts(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, AppError> {
+    let tenant = tenant_from_headers(&state.fonts, &headers)?;
+    let fonts = state.fonts.list(tenant.as_deref()).await?;
+
+    tracing::debug!(
+        event = "fonts_listed",
+        tenant = ?tenant,
+        font_count = fonts.len(),
+        "Font inventory requested"
+    );
+
+    Ok((StatusCode::OK, Json(json!({ "fonts": fonts }))))
+}
+
+pub async fn upload_font(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(params): Query<UploadParams>,
+    body: Bytes,
+) -> Result<impl IntoResponse, AppError> {
+    let start = Instant::now();
+    let tenant = tenant_from_headers(&state.fonts, &headers)?;
+    // Fontes compartilhadas valem para todos os tenants: só o operador envia
+    if tenant.is_none() {
+        state.fonts.authorize_shared(bearer_token(&headers))?;
+    }
+
+    tracing::info!(
+        event = "font_upload_started",
+        tenant = ?tenant,
+        file = %params.filename,
+        size_bytes = body.len(),
+        "Starting font upload"
+    );
+
+    let installed = state.fonts.install(tenant.as_deref(), &params.filename, body.to_vec()).await?;
+
+    tracing::info!(
+        event = "font_upload_success",
+        tenant = ?tenant,
+        file = %params.filename,
+        face_count = installed.len(),
+        duration_ms = start.elapsed().as_millis() as u64,
+        "Font upload completed successfully"
+    );
+
+    Ok((StatusCode::CREATED, Json(json!({ "installed": installed }))))
+}
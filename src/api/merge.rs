@@ -0,0 +1,213 @@
+use axum::{Json, response::IntoResponse, http::{HeaderMap, HeaderValue, StatusCode, header}, extract::State};
+use base64::Engine as _;
+use serde::{Deserialize, Serialize};
+use std::time::Instant;
+use crate::api::error::AppError;
+use crate::api::render::{insert_validation_headers, render_document, validate_pdfa, RenderRequest};
+use crate::api::state::AppState;
+use crate::infra::color::ColorMode;
+use crate::infra::merge::{MergeError, MergeInput, PdfMerger};
+use crate::infra::metadata::DocumentMetadata;
+use crate::infra::pdfa::PdfaLevel;
+use crate::infra::validation::ValidationMode;
+
+/// Where the pages of a part come from.
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum PartSource {
+    /// A base64-encoded PDF.
+    Pdf(String),
+    /// A `/render` request body.
+    Render(Box<RenderRequest>),
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct MergePart {
+    #[serde(flatten)]
+    pub source: PartSource,
+    /// Bookmark for the part's first page; its own bookmarks go under it.
+    #[serde(default)]
+    pub bookmark: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Default)]
+pub struct MergeOptions {
+    /// Legacy switch for PDF/A-1b; `pdf_a_level` wins when set.
+    #[serde(default)]
+    pub pdf_a: bool,
+    /// Converts the merged document; level A is not available.
+    #[serde(default)]
+    pub pdf_a_level: Option<PdfaLevel>,
+    #[serde(default)]
+    pub pdf_a_validation: ValidationMode,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct MergeRequest {
+    pub parts: Vec<MergePart>,
+    #[serde(default)]
+    pub options: MergeOptions,
+    #[serde(default)]
+    pub metadata: Option<DocumentMetadata>,
+}
+
+pub async fn merge_pdf(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(payload): Json<MergeRequest>,
+) -> Result<impl IntoResponse, AppError> {
+    let start = Instant::now();
+    let options = &payload.options;
+    let pdfa_level = PdfaLevel::resolve(options.pdf_a_level, options.pdf_a, false);
+
+    tracing::info!(
+        event = "merge_started",
+        part_count = payload.parts.len(),
+        pdf_a_level = pdfa_level.map(|l| l.as_str()),
+        "Starting PDF merge"
+    );
+
+    if payload.parts.is_empty() {
+        return Err(AppError::ValidationError("'parts' must not be empty".to_string()));
+    }
+    // A árvore de estrutura das partes não é mantida
+    if pdfa_level.map(|l| l.requires_tagging()).unwrap_or(false) {
+        return Err(AppError::ValidationError(
+            "Merged documents are not tagged, so PDF/A level A is not available".to_string(),
+        ));
+    }
+    if options.pdf_a_validation != ValidationMode::Off && pdfa_level.is_none() {
+        return Err(AppError::ValidationError(
+            "'pdf_a_validation' requires 'pdf_a' or 'pdf_a_level'".to_string(),
+        ));
+    }
+    if let Some(metadata) = &payload.metadata {
+        metadata.validate().map_err(|e| AppError::ValidationError(e.to_string()))?;
+    }
+
+    // Renderizar ou decodificar cada parte, na ordem pedida
+    let mut documents = Vec::with_capacity(payload.parts.len());
+    for (index, part) in payload.parts.iter().enumerate() {
+        let data = match &part.source {
+            PartSource::Pdf(encoded) => base64::engine::general_purpose::STANDARD
+                .decode(encoded.trim())
+                .map_err(|e| AppError::ValidationError(format!("Part {}: invalid base64: {}", index + 1, e)))?,
+            PartSource::Render(request) => {
+                // A junção reescreve o arquivo e invalidaria a assinatura
+                if request.options.as_ref().map(|o| o.signature.is_some()).unwrap_or(false) {
+                    return Err(AppError::ValidationError(format!(
+                        "Part {}: signed parts cannot be merged; their signatures would break",
+                        index + 1
+                    )));
+                }
+                // O catálogo de cada parte é descartado, e com ele os anexos
+                if !request.attachments.is_empty() {
+                    return Err(AppError::ValidationError(format!(
+                        "Part {}: attachments are not kept by /merge; attach them to a single /render",
+                        index + 1
+                    )));
+                }
+                if request.options.as_ref().map(|o| o.security.is_some()).unwrap_or(false) {
+                    return Err(AppError::ValidationError(format!(
+                        "Part {}: encrypted parts cannot be merged; leave out 'security'",
+                        index + 1
+                    )));
+                }
+                // O catálogo de cada parte é descartado, e com ele a OutputIntent
+                if request.options.as_ref().map(|o| o.color_mode == ColorMode::Cmyk).unwrap_or(false) {
+                    return Err(AppError::ValidationError(format!(
+                        "Part {}: 'color_mode' cmyk is not kept by /merge; convert a single /render instead",
+                        index + 1
+                    )));
+                }
+                // A junção reescreve o arquivo e desfaz a linearização
+                if request.options.as_ref().map(|o| o.linearize).unwrap_or(false) {
+                    return Err(AppError::ValidationError(format!(
+                        "Part {}: 'linearize' is lost when parts are merged; leave it out",
+                        index + 1
+                    )));
+                }
+                let rendered = render_document(&state, &headers, request).await.map_err(|e| {
+                    tracing::error!(
+                        event = "merge_error",
+                        stage = "render_part",
+                        part = index + 1,
+                        error = %e,
+                        "Merge failed while rendering a part"
+                    );
+                    e
+                })?;
+                rendered.data
+            }
+        };
+        documents.push(data);
+    }
+
+    let inputs: Vec<MergeInput<'_>> = documents
+        .iter()
+        .zip(&payload.parts)
+        .map(|(data, part)| MergeInput { data, bookmark: part.bookmark.as_deref() })
+        .collect();
+    let merged = PdfMerger::merge(&inputs).map_err(|e| {
+        tracing::error!(
+            event = "merge_error",
+            stage = "merge",
+            error = %e,
+            "Merge failed while combining parts"
+        );
+        match e {
+            MergeError::Invalid(message) => AppError::ValidationError(message),
+            MergeError::Internal(e) => AppError::PdfProcessingError(e.to_string()),
+        }
+    })?;
+
+    let merged = match pdfa_level {
+        Some(level) => state.ghostscript.convert_to_pdfa(&merged, level, ColorMode::Rgb, None, None).await.map_err(|e| {
+            tracing::error!(
+                event = "merge_error",
+                stage = "pdfa_conversion",
+                error = %e,
+                "Merge failed at PDF/A conversion stage"
+            );
+            AppError::GhostscriptError(e.to_string())
+        })?,
+        None => merged,
+    };
+
+    let merged = match &payload.metadata {
+        Some(metadata) => metadata.apply(&merged, metadata.language.as_deref(), pdfa_level).map_err(|e| {
+            tracing::error!(
+                event = "merge_error",
+                stage = "metadata",
+                error = %e,
+                "Merge failed while writing document metadata"
+            );
+            AppError::PdfProcessingError(e.to_string())
+        })?,
+        None => merged,
+    };
+
+    let validation_report = match (pdfa_level, options.pdf_a_validation) {
+        (Some(level), ValidationMode::Report | ValidationMode::Strict) => {
+            Some(validate_pdfa(&state, &merged, level, options.pdf_a_validation).await?)
+        }
+        _ => None,
+    };
+
+    tracing::info!(
+        event = "merge_success",
+        duration_ms = start.elapsed().as_millis() as u64,
+        part_count = payload.parts.len(),
+        output_size_bytes = merged.len(),
+        pdf_a_level = pdfa_level.map(|l| l.as_str()),
+        "PDF merge completed successfully"
+    );
+
+    let mut response_headers = HeaderMap::new();
+    response_headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("application/pdf"));
+    if let Some(report) = &validation_report {
+        insert_validation_headers(&mut response_headers, report);
+    }
+
+    Ok((StatusCode::OK, response_headers, merged))
+}
@@ -2,3 +2,6 @@ pub mod health;
 pub mod render;
 pub mod state;
 pub mod error;
+pub mod fonts;
+pub mod merge;
+pub mod split;
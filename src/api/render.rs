@@ -1,25 +1,127 @@
-use axum::{Json, response::IntoResponse, http::{StatusCode, header}, extract::State};
+use axum::{Json, response::IntoResponse, http::{HeaderMap, HeaderValue, StatusCode, header}, extract::State};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use chrono::{DateTime, FixedOffset, Utc};
 use std::time::Instant;
 use crate::api::state::AppState;
 use crate::api::error::AppError;
+use crate::api::fonts::tenant_from_headers;
+use crate::core::renderer::Engine;
+use crate::infra::attachments::{Attachment, EmbeddedFiles};
+use crate::infra::browser::{EmulationOptions, PageSource, PrintOptions, UrlSource};
+use crate::infra::color::{ColorMode, IccProfile};
+use crate::infra::deterministic::Deterministic;
+use crate::infra::diagnostics::PageDiagnostics;
+use crate::infra::font_coverage::{FontCheck, FontCoverage, FontFallback, FontReport};
+use crate::infra::fonts::FontStore;
+use crate::infra::metadata::DocumentMetadata;
+use crate::infra::optimize::Optimize;
+use crate::infra::outline::Outline;
+use crate::infra::pdfa::PdfaLevel;
+use crate::infra::security::Security;
+use crate::infra::signing::SignatureOptions;
+use crate::infra::tagging::{TaggedPdf, TaggingError};
+use crate::infra::validation::{ValidationMode, ValidationReport};
+use crate::infra::watermark::Watermark;
+
+/// Longest `X-PdfA-Report` value sent with full rule descriptions.
+const MAX_REPORT_HEADER_BYTES: usize = 6 * 1024;
+
+/// Why Ghostscript stages are refused for tagged output.
+const TAGGED_WITHOUT_GHOSTSCRIPT: &str =
+    "tagged PDFs skip Ghostscript, which would drop the structure tree";
 
 #[derive(Deserialize, Serialize, Debug)]
 pub struct RenderOptions {
+    /// Legacy switch for PDF/A-1b (1a when tagged); `pdf_a_level` wins when set.
+    #[serde(default)]
     pub pdf_a: bool,
+    /// PDF/A part and conformance: 1b, 2b, 2u, 3b, 1a or 2a.
+    #[serde(default)]
+    pub pdf_a_level: Option<PdfaLevel>,
+    /// Validate the PDF/A output: off, report or strict.
+    #[serde(default)]
+    pub pdf_a_validation: ValidationMode,
     pub paper_format: String,
+    /// Size optimization: screen, ebook, printer, prepress or custom settings.
+    #[serde(default)]
+    pub optimize: Option<Optimize>,
+    /// Output color space: rgb (default), cmyk with `icc_profile`, gray or mono.
+    #[serde(default)]
+    pub color_mode: ColorMode,
+    /// CMYK output profile: fogra39, gracol or an uploaded profile.
+    #[serde(default)]
+    pub icc_profile: Option<IccProfile>,
+    /// Text or image stamped on the pages before PDF/A conversion.
+    #[serde(default)]
+    pub watermark: Option<Watermark>,
+    /// PAdES signature, applied to the final PDF after PDF/A conversion.
+    #[serde(default)]
+    pub signature: Option<SignatureOptions>,
+    /// Password protection and permissions, applied after every rewrite.
+    #[serde(default)]
+    pub security: Option<Security>,
+    /// Linearized ("fast web view") output, written after encryption and
+    /// before signing.
+    #[serde(default)]
+    pub linearize: bool,
+    /// Rendering engine; `chrome` unless the request asks otherwise.
+    #[serde(default)]
+    pub engine: Engine,
+    #[serde(flatten)]
+    pub emulation: EmulationOptions,
+    #[serde(flatten)]
+    pub print: PrintOptions,
+    /// Document language (BCP 47) written to `/Lang`; defaults to the html `lang` attribute.
+    #[serde(default)]
+    pub lang: Option<String>,
+    /// Byte-reproducible output: pins the template and page clocks and
+    /// normalizes dates, IDs and producer strings in the PDF.
+    #[serde(default)]
+    pub deterministic: bool,
+    /// RFC 3339 instant used by deterministic renders; defaults to the Unix epoch.
+    #[serde(default)]
+    pub timestamp: Option<String>,
 }
 
 #[derive(Deserialize, Serialize, Debug)]
 pub struct RenderRequest {
+    #[serde(default)]
     pub template_html: String,
+    /// Navigate to a URL instead of rendering `template_html`.
+    #[serde(default)]
+    pub source: Option<UrlSource>,
+    #[serde(default)]
     pub data: Value,
     pub options: Option<RenderOptions>,
+    /// Title, author and other properties for `/Info` and XMP.
+    #[serde(default)]
+    pub metadata: Option<DocumentMetadata>,
+    /// Files embedded in the PDF, such as the XML of a Factur-X invoice.
+    #[serde(default)]
+    pub attachments: Vec<Attachment>,
+}
+
+/// Output of [`render_document`].
+pub(crate) struct RenderedPdf {
+    pub data: Vec<u8>,
+    pub font_report: Option<FontReport>,
+    pub validation_report: Option<ValidationReport>,
+}
+
+/// Response of `/render/debug`: the rendered HTML plus what the browser
+/// reported while loading it.
+#[derive(Serialize, Debug)]
+pub struct DebugReport {
+    /// `None` in URL mode.
+    pub html: Option<String>,
+    #[serde(flatten)]
+    pub diagnostics: PageDiagnostics,
 }
 
 pub async fn render_html(
     State(state): State<AppState>,
+    headers: HeaderMap,
     Json(payload): Json<RenderRequest>,
 ) -> Result<impl IntoResponse, AppError> {
     let start = Instant::now();
@@ -37,52 +139,132 @@ pub async fn render_html(
         "Starting HTML render"
     );
 
-    // Criar contexto e verificar se foi criado corretamente
-    let context = match tera::Context::from_value(payload.data.clone()) {
-        Ok(ctx) => ctx,
-        Err(e) => {
-            tracing::error!(
-                event = "render_html_error",
-                stage = "context_creation",
-                error = %e,
-                data_keys = ?data_keys,
-                "Failed to create Tera context from JSON data"
-            );
-            return Err(AppError::TemplateError(tera::Error::msg(format!("Invalid context data: {}", e))));
+    let clock = frozen_clock(&payload)?;
+    let mut emulation = payload.options.as_ref()
+        .map(|o| o.emulation.clone())
+        .unwrap_or_default();
+    emulation.validate().map_err(|e| AppError::ValidationError(e.to_string()))?;
+    emulation.frozen_time_ms = clock.map(|c| c.timestamp_millis());
+    validate_source(&state, &payload)?;
+    let tenant = tenant_from_headers(&state.fonts, &headers)?;
+
+    let html = match &payload.source {
+        Some(_) => None,
+        None => {
+            // Criar contexto e verificar se foi criado corretamente
+            let context = match tera::Context::from_value(payload.data.clone()) {
+                Ok(ctx) => ctx,
+                Err(e) => {
+                    tracing::error!(
+                        event = "render_html_error",
+                        stage = "context_creation",
+                        error = %e,
+                        data_keys = ?data_keys,
+                        "Failed to create Tera context from JSON data"
+                    );
+                    return Err(AppError::TemplateError(tera::Error::msg(format!("Invalid context data: {}", e))));
+                }
+            };
+
+            match state.template_engine.render(&payload.template_html, &context, clock) {
+                Ok(html) => Some(html),
+                Err(e) => {
+                    let duration = start.elapsed();
+                    tracing::error!(
+                        event = "render_html_error",
+                        duration_ms = duration.as_millis() as u64,
+                        error = %e,
+                        "HTML render failed"
+                    );
+                    return Err(AppError::from(e));
+                }
+            }
         }
     };
-    
-    match state.template_engine.render(&payload.template_html, &context) {
-        Ok(html) => {
-            let duration = start.elapsed();
-            tracing::info!(
-                event = "render_html_success",
-                duration_ms = duration.as_millis() as u64,
-                output_size_bytes = html.len(),
-                "HTML render completed successfully"
-            );
-            Ok((StatusCode::OK, html))
-        }
-        Err(e) => {
-            let duration = start.elapsed();
-            tracing::error!(
-                event = "render_html_error",
-                duration_ms = duration.as_millis() as u64,
-                error = %e,
-                "HTML render failed"
-            );
-            Err(AppError::from(e))
-        }
-    }
+
+    // O relatório devolve o HTML do template, sem as fontes embutidas
+    let page_html = match &html {
+        Some(html) => with_tenant_fonts(&state, tenant.as_deref(), html.clone()).await?,
+        None => String::new(),
+    };
+    let page_source = match &payload.source {
+        Some(source) => PageSource::Url { source, allowlist: &state.url_allowlist },
+        None => PageSource::Html(&page_html),
+    };
+
+    // Carregar no browser para capturar console, exceções e falhas de rede
+    let diagnostics = state.browser.inspect(page_source, &emulation).map_err(|e| {
+        let duration = start.elapsed();
+        tracing::error!(
+            event = "render_html_error",
+            stage = "browser_inspect",
+            duration_ms = duration.as_millis() as u64,
+            error = %e,
+            "HTML render failed at browser stage"
+        );
+        AppError::BrowserError(e.to_string())
+    })?;
+
+    let duration = start.elapsed();
+    tracing::info!(
+        event = "render_html_success",
+        duration_ms = duration.as_millis() as u64,
+        output_size_bytes = html.as_ref().map(|h| h.len()).unwrap_or(0),
+        console_count = diagnostics.console.len(),
+        exception_count = diagnostics.exceptions.len(),
+        failed_request_count = diagnostics.failed_requests.len(),
+        "HTML render completed successfully"
+    );
+
+    Ok((StatusCode::OK, Json(DebugReport { html, diagnostics })))
 }
 
 pub async fn render_pdf(
     State(state): State<AppState>,
+    headers: HeaderMap,
     Json(payload): Json<RenderRequest>,
 ) -> Result<impl IntoResponse, AppError> {
+    let rendered = render_document(&state, &headers, &payload).await?;
+
+    let mut response_headers = HeaderMap::new();
+    response_headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("application/pdf"));
+    if let Some(report) = &rendered.font_report {
+        insert_font_headers(&mut response_headers, report);
+    }
+    if let Some(report) = &rendered.validation_report {
+        insert_validation_headers(&mut response_headers, report);
+    }
+
+    Ok((
+        StatusCode::OK,
+        response_headers,
+        rendered.data
+    ))
+}
+
+/// Runs the whole `/render` pipeline for one request.
+pub(crate) async fn render_document(
+    state: &AppState,
+    headers: &HeaderMap,
+    payload: &RenderRequest,
+) -> Result<RenderedPdf, AppError> {
     let start = Instant::now();
     let template_size = payload.template_html.len();
-    let pdf_a_enabled = payload.options.as_ref().map(|o| o.pdf_a).unwrap_or(false);
+    let mut print = payload.options.as_ref()
+        .map(|o| o.print.clone())
+        .unwrap_or_default();
+    let requested_level = payload.options.as_ref().and_then(|o| o.pdf_a_level);
+    // Nível A exige a árvore de estrutura
+    if requested_level.map(|l| l.requires_tagging()).unwrap_or(false) {
+        print.tagged = true;
+    }
+    // O sumário é gerado a partir da árvore de estrutura
+    let tagged = print.tagged || print.outline.is_some();
+    let pdfa_level = PdfaLevel::resolve(
+        requested_level,
+        payload.options.as_ref().map(|o| o.pdf_a).unwrap_or(false),
+        tagged,
+    );
     
     // Extrair chaves dos dados para log
     let data_keys: Vec<&str> = payload.data.as_object()
@@ -92,102 +274,424 @@ pub async fn render_pdf(
     tracing::info!(
         event = "render_pdf_started",
         template_size_bytes = template_size,
-        pdf_a = pdf_a_enabled,
+        pdf_a_level = pdfa_level.map(|l| l.as_str()),
         data_keys = ?data_keys,
         "Starting PDF render"
     );
 
-    // Criar contexto e verificar se foi criado corretamente
-    let context = match tera::Context::from_value(payload.data.clone()) {
-        Ok(ctx) => ctx,
-        Err(e) => {
-            tracing::error!(
-                event = "render_pdf_error",
-                stage = "context_creation",
-                error = %e,
-                data_keys = ?data_keys,
-                "Failed to create Tera context from JSON data"
-            );
-            return Err(AppError::TemplateError(tera::Error::msg(format!("Invalid context data: {}", e))));
+    let clock = frozen_clock(payload)?;
+    let mut emulation = payload.options.as_ref()
+        .map(|o| o.emulation.clone())
+        .unwrap_or_default();
+    emulation.validate().map_err(|e| AppError::ValidationError(e.to_string()))?;
+    emulation.frozen_time_ms = clock.map(|c| c.timestamp_millis());
+    let engine = payload.options.as_ref().map(|o| o.engine).unwrap_or_default();
+    let validation = payload.options.as_ref().map(|o| o.pdf_a_validation).unwrap_or_default();
+    if validation != ValidationMode::Off && pdfa_level.is_none() {
+        return Err(AppError::ValidationError(
+            "'pdf_a_validation' requires 'pdf_a' or 'pdf_a_level'".to_string(),
+        ));
+    }
+    let optimize = payload.options.as_ref().and_then(|o| o.optimize.clone());
+    if let Some(optimize) = &optimize {
+        optimize.validate().map_err(|e| AppError::ValidationError(e.to_string()))?;
+        // O pdfwrite do Ghostscript descarta a árvore de estrutura
+        if tagged {
+            return Err(AppError::ValidationError(format!(
+                "'optimize' cannot be combined with tagged output ('tagged', 'outline' or a level A 'pdf_a_level'): {}",
+                TAGGED_WITHOUT_GHOSTSCRIPT
+            )));
         }
+    }
+    // Conferir o perfil ICC antes de renderizar
+    let color_mode = payload.options.as_ref().map(|o| o.color_mode).unwrap_or_default();
+    let intent = state.icc_profiles
+        .resolve(color_mode, payload.options.as_ref().and_then(|o| o.icc_profile.as_ref()))
+        .map_err(|e| AppError::ValidationError(e.to_string()))?;
+    // A conversão de cores passa pelo Ghostscript, que descarta a árvore de estrutura
+    if color_mode != ColorMode::Rgb && tagged {
+        return Err(AppError::ValidationError(format!(
+            "'color_mode' {} cannot be combined with tagged output ('tagged', 'outline' or a level A 'pdf_a_level'): {}",
+            color_mode.as_str(),
+            TAGGED_WITHOUT_GHOSTSCRIPT
+        )));
+    }
+    // Carregar a fonte ou a imagem antes de renderizar, para falhar cedo
+    let stamp = match payload.options.as_ref().and_then(|o| o.watermark.as_ref()) {
+        Some(watermark) => {
+            watermark.validate().map_err(|e| AppError::ValidationError(e.to_string()))?;
+            let transparency = pdfa_level.map(|l| l.allows_transparency()).unwrap_or(true);
+            // fc-match e a decodificação da imagem são bloqueantes
+            let watermark = watermark.clone();
+            let prepared = tokio::task::spawn_blocking(move || watermark.prepare(transparency))
+                .await
+                .map_err(|e| AppError::PdfProcessingError(format!("Watermark task failed: {}", e)))?;
+            Some(prepared.map_err(|e| AppError::ValidationError(e.to_string()))?)
+        }
+        None => None,
     };
+    // Conferir a chave antes de renderizar; a assinatura é a última etapa
+    let signature = payload.options.as_ref().and_then(|o| o.signature.as_ref());
+    if let Some(signature) = signature {
+        signature.validate().map_err(|e| AppError::ValidationError(e.to_string()))?;
+        state.signer.key(signature)?;
+    }
+    // Decodificar os anexos antes de renderizar
+    let attachments = EmbeddedFiles::prepare(&payload.attachments)
+        .map_err(|e| AppError::ValidationError(e.to_string()))?;
+    match (pdfa_level, attachments.invoice()) {
+        (Some(level), _) if !attachments.is_empty() && level.part() != 3 => {
+            return Err(AppError::ValidationError(format!(
+                "'attachments' need 'pdf_a_level' 3b: PDF/A-{} does not allow embedded files",
+                level.as_str().to_uppercase()
+            )));
+        }
+        (None, Some(invoice)) => {
+            return Err(AppError::ValidationError(format!(
+                "'{}' makes a Factur-X e-invoice, which must be PDF/A-3: set 'pdf_a_level' to 3b",
+                invoice.filename
+            )));
+        }
+        _ => {}
+    }
+    let security = payload.options.as_ref().and_then(|o| o.security.as_ref());
+    if let Some(security) = security {
+        security.validate().map_err(|e| AppError::ValidationError(e.to_string()))?;
+        // Todas as partes do PDF/A proíbem o dicionário /Encrypt
+        if let Some(level) = pdfa_level {
+            return Err(AppError::ValidationError(format!(
+                "'security' cannot be combined with PDF/A: PDF/A-{} forbids encryption",
+                level.as_str().to_uppercase()
+            )));
+        }
+        if signature.is_some() {
+            return Err(AppError::ValidationError(
+                "'security' cannot be combined with 'signature': encrypt and sign the document in separate steps".to_string(),
+            ));
+        }
+    }
+    // A linearização é feita pelo qpdf
+    let linearize = payload.options.as_ref().map(|o| o.linearize).unwrap_or(false);
+    if linearize {
+        if state.linearizer.is_none() {
+            return Err(AppError::ValidationError(
+                "'linearize' is not available: qpdf is not installed on this server".to_string(),
+            ));
+        }
+        // O qpdf recriptografa com vetores aleatórios
+        if security.is_some() && clock.is_some() {
+            return Err(AppError::ValidationError(
+                "'linearize' with 'security' cannot be deterministic: the encrypted output is not reproducible".to_string(),
+            ));
+        }
+    }
+    let renderer = state.renderers
+        .get(engine)
+        .map_err(|e| AppError::ValidationError(e.to_string()))?;
 
-    // Renderizar template HTML
-    let html = match state.template_engine.render(&payload.template_html, &context) {
-        Ok(html) => {
+    validate_source(state, payload)?;
+    if let Some(metadata) = &payload.metadata {
+        metadata.validate().map_err(|e| AppError::ValidationError(e.to_string()))?;
+    }
+    let tenant = tenant_from_headers(&state.fonts, headers)?;
+
+    let html = match &payload.source {
+        Some(_) => String::new(),
+        None => {
+            let html = render_template(state, payload, &data_keys, clock, start)?;
+            with_tenant_fonts(state, tenant.as_deref(), html).await?
+        }
+    };
+    let page_source = match &payload.source {
+        Some(source) => PageSource::Url { source, allowlist: &state.url_allowlist },
+        None => PageSource::Html(&html),
+    };
+    renderer
+        .check_support(&page_source, &emulation, &print)
+        .map_err(|e| AppError::ValidationError(e.to_string()))?;
+
+    // Gerar PDF com o motor escolhido
+    let printed = match renderer.render(page_source, &emulation, &print) {
+        Ok(printed) => {
             tracing::debug!(
-                event = "template_rendered",
-                html_size_bytes = html.len(),
-                "Template rendered to HTML"
+                event = "pdf_generated",
+                engine = engine.as_str(),
+                pdf_size_bytes = printed.data.len(),
+                "PDF generated from HTML"
             );
-            html
+            printed
         }
         Err(e) => {
             let duration = start.elapsed();
             tracing::error!(
                 event = "render_pdf_error",
-                stage = "template_rendering",
+                stage = "browser_pdf_generation",
+                engine = engine.as_str(),
                 duration_ms = duration.as_millis() as u64,
                 error = %e,
-                "PDF render failed at template stage"
+                "PDF render failed at browser stage"
             );
-            return Err(AppError::from(e));
+            return Err(AppError::BrowserError(e.to_string()));
         }
     };
 
-    // Gerar PDF via browser
-    let pdf_bytes = match state.browser.print_to_pdf(&html) {
-        Ok(bytes) => {
+    // Verificar a cobertura de fontes antes da conversão, que reescreve as fontes
+    let font_report = match print.font_check {
+        FontCheck::Off => None,
+        check => Some(check_fonts(&printed.data, printed.font_fallbacks.clone(), check)?),
+    };
+
+    // Guardar o sumário antes da conversão, que pode renumerar as páginas
+    let outline_entries = match &print.outline {
+        Some(outline) => {
+            let entries = Outline::extract(&printed.data)
+                .map(|entries| Outline::filter(entries, &printed.headings, outline))
+                .map_err(|e| AppError::PdfProcessingError(e.to_string()))?;
             tracing::debug!(
-                event = "pdf_generated",
-                pdf_size_bytes = bytes.len(),
-                "PDF generated from HTML"
+                event = "outline_extracted",
+                entry_count = entries.len(),
+                max_depth = outline.max_depth,
+                "Document outline extracted"
             );
-            bytes
+            Some(entries)
         }
-        Err(e) => {
-            let duration = start.elapsed();
+        None => None,
+    };
+    let pdf_bytes = match &stamp {
+        Some(stamp) => stamp.apply(&printed.data).map_err(|e| {
             tracing::error!(
                 event = "render_pdf_error",
-                stage = "browser_pdf_generation",
-                duration_ms = duration.as_millis() as u64,
+                stage = "watermark",
                 error = %e,
-                "PDF render failed at browser stage"
+                "PDF render failed while applying the watermark"
             );
-            return Err(AppError::BrowserError(e.to_string()));
-        }
+            AppError::PdfProcessingError(e.to_string())
+        })?,
+        None => printed.data,
     };
 
+    // Monocromático: cada página vira uma imagem de 1 bit antes das outras conversões
+    let pdf_bytes = if color_mode == ColorMode::Mono {
+        state.ghostscript.rasterize_mono(&pdf_bytes).await.map_err(|e| {
+            tracing::error!(
+                event = "render_pdf_error",
+                stage = "mono_conversion",
+                error = %e,
+                "PDF render failed while converting to monochrome"
+            );
+            AppError::GhostscriptError(e.to_string())
+        })?
+    } else {
+        pdf_bytes
+    };
+
+    let lang = payload.options.as_ref()
+        .and_then(|o| o.lang.clone())
+        .or_else(|| payload.metadata.as_ref().and_then(|m| m.language.clone()))
+        .or_else(|| TaggedPdf::html_lang(&html));
+
     // Converter para PDF/A se necessário
-    let final_pdf = if let Some(opts) = payload.options {
-        if opts.pdf_a {
-            match crate::infra::ghostscript::Ghostscript::convert_to_pdfa(&pdf_bytes) {
-                Ok(pdfa_bytes) => {
-                    tracing::debug!(
-                        event = "pdfa_converted",
-                        original_size_bytes = pdf_bytes.len(),
-                        pdfa_size_bytes = pdfa_bytes.len(),
-                        "PDF converted to PDF/A"
-                    );
-                    pdfa_bytes
-                }
-                Err(e) => {
-                    let duration = start.elapsed();
+    let final_pdf = if let (Some(level), true) = (pdfa_level, tagged) {
+        // O Ghostscript descarta a árvore de estrutura, então PDFs marcados
+        // recebem os metadados PDF/A sem reescrever o conteúdo
+        match TaggedPdf::convert_to_pdfa(&pdf_bytes, lang.as_deref(), level, &state.assets.srgb_icc()) {
+            Ok(pdfa_bytes) => {
+                tracing::debug!(
+                    event = "pdfa_converted",
+                    original_size_bytes = pdf_bytes.len(),
+                    pdfa_size_bytes = pdfa_bytes.len(),
+                    tagged = true,
+                    pdf_a_level = level.as_str(),
+                    "Tagged PDF converted to PDF/A"
+                );
+                pdfa_bytes
+            }
+            Err(e) => {
+                let duration = start.elapsed();
+                tracing::error!(
+                    event = "render_pdf_error",
+                    stage = "tagged_pdfa_conversion",
+                    duration_ms = duration.as_millis() as u64,
+                    error = %e,
+                    "PDF render failed at tagged PDF/A conversion stage"
+                );
+                return Err(match e {
+                    TaggingError::Invalid(message) => AppError::ValidationError(message),
+                    TaggingError::Internal(e) => AppError::PdfProcessingError(e.to_string()),
+                });
+            }
+        }
+    } else if let Some(level) = pdfa_level {
+        match state.ghostscript.convert_to_pdfa(&pdf_bytes, level, color_mode, intent.as_ref(), optimize.as_ref()).await {
+            Ok(pdfa_bytes) => {
+                tracing::debug!(
+                    event = "pdfa_converted",
+                    original_size_bytes = pdf_bytes.len(),
+                    pdfa_size_bytes = pdfa_bytes.len(),
+                    pdf_a_level = level.as_str(),
+                    "PDF converted to PDF/A"
+                );
+                pdfa_bytes
+            }
+            Err(e) => {
+                let duration = start.elapsed();
+                tracing::error!(
+                    event = "render_pdf_error",
+                    stage = "pdfa_conversion",
+                    duration_ms = duration.as_millis() as u64,
+                    error = %e,
+                    "PDF render failed at PDF/A conversion stage"
+                );
+                return Err(AppError::GhostscriptError(e.to_string()));
+            }
+        }
+    } else if tagged {
+        TaggedPdf::finalize(&pdf_bytes, lang.as_deref()).map_err(|e| {
+            tracing::error!(
+                event = "render_pdf_error",
+                stage = "tagged_finalize",
+                error = %e,
+                "PDF render failed while finalizing tagged PDF"
+            );
+            AppError::PdfProcessingError(e.to_string())
+        })?
+    } else if matches!(color_mode, ColorMode::Cmyk | ColorMode::Gray) {
+        state.ghostscript.convert_colors(&pdf_bytes, color_mode, intent.as_ref(), optimize.as_ref()).await.map_err(|e| {
+            tracing::error!(
+                event = "render_pdf_error",
+                stage = "color_conversion",
+                error = %e,
+                "PDF render failed while converting colors"
+            );
+            AppError::GhostscriptError(e.to_string())
+        })?
+    } else if let Some(optimize) = &optimize {
+        state.ghostscript.optimize(&pdf_bytes, optimize).await.map_err(|e| {
+            tracing::error!(
+                event = "render_pdf_error",
+                stage = "optimize",
+                error = %e,
+                "PDF render failed while optimizing output"
+            );
+            AppError::GhostscriptError(e.to_string())
+        })?
+    } else {
+        pdf_bytes
+    };
+
+    let final_pdf = match &outline_entries {
+        Some(entries) => Outline::apply(&final_pdf, entries).map_err(|e| {
+            tracing::error!(
+                event = "render_pdf_error",
+                stage = "outline",
+                error = %e,
+                "PDF render failed while writing the document outline"
+            );
+            AppError::PdfProcessingError(e.to_string())
+        })?,
+        None => final_pdf,
+    };
+
+    let final_pdf = match &payload.metadata {
+        Some(metadata) => metadata.apply(&final_pdf, lang.as_deref(), pdfa_level).map_err(|e| {
+            tracing::error!(
+                event = "render_pdf_error",
+                stage = "metadata",
+                error = %e,
+                "PDF render failed while writing document metadata"
+            );
+            AppError::PdfProcessingError(e.to_string())
+        })?,
+        None => final_pdf,
+    };
+
+    // Depois dos metadados, que substituem o XMP com as propriedades Factur-X
+    let final_pdf = if attachments.is_empty() {
+        final_pdf
+    } else {
+        let modified = clock.unwrap_or_else(|| Utc::now().fixed_offset());
+        attachments.apply(&final_pdf, modified).map_err(|e| {
+            tracing::error!(
+                event = "render_pdf_error",
+                stage = "attachments",
+                error = %e,
+                "PDF render failed while embedding attachments"
+            );
+            AppError::PdfProcessingError(e.to_string())
+        })?
+    };
+
+    // Normalizar datas e identificadores para saída reproduzível
+    let final_pdf = match clock {
+        Some(timestamp) => Deterministic::normalize(&final_pdf, timestamp).map_err(|e| {
+            tracing::error!(
+                event = "render_pdf_error",
+                stage = "deterministic",
+                error = %e,
+                "PDF render failed while normalizing output"
+            );
+            AppError::PdfProcessingError(e.to_string())
+        })?,
+        None => final_pdf,
+    };
+
+    // Criptografar depois das outras reescritas: cada string e stream é reescrito
+    let final_pdf = match security {
+        Some(security) => security.apply(&final_pdf, clock.is_some()).map_err(|e| {
+            tracing::error!(
+                event = "render_pdf_error",
+                stage = "encryption",
+                error = %e,
+                "PDF render failed while encrypting"
+            );
+            AppError::PdfProcessingError(e.to_string())
+        })?,
+        None => final_pdf,
+    };
+
+    // Linearizar depois de todas as reescritas; a assinatura é anexada ao final
+    let final_pdf = match (&state.linearizer, linearize) {
+        (Some(linearizer), true) => {
+            let password = security.and_then(|s| s.owner_password.as_deref().or(s.user_password.as_deref()));
+            linearizer
+                .linearize(&final_pdf, password, clock.is_some(), pdfa_level.is_some())
+                .await
+                .map_err(|e| {
                     tracing::error!(
                         event = "render_pdf_error",
-                        stage = "pdfa_conversion",
-                        duration_ms = duration.as_millis() as u64,
+                        stage = "linearize",
                         error = %e,
-                        "PDF render failed at PDF/A conversion stage"
+                        "PDF render failed while linearizing"
                     );
-                    return Err(AppError::GhostscriptError(e.to_string()));
-                }
-            }
-        } else {
-            pdf_bytes
+                    AppError::PdfProcessingError(e.to_string())
+                })?
         }
-    } else {
-        pdf_bytes
+        _ => final_pdf,
+    };
+
+    // Assinar por último: qualquer reescrita depois invalidaria a assinatura
+    let final_pdf = match signature {
+        Some(signature) => {
+            let signing_time = clock.unwrap_or_else(|| Utc::now().fixed_offset());
+            state.signer.sign(&final_pdf, signature, signing_time).await.map_err(|e| {
+                tracing::error!(
+                    event = "render_pdf_error",
+                    stage = "signing",
+                    error = %e,
+                    "PDF render failed while signing"
+                );
+                AppError::from(e)
+            })?
+        }
+        None => final_pdf,
+    };
+
+    // Validar o arquivo final, depois de todas as etapas que o reescrevem
+    let validation_report = match (pdfa_level, validation) {
+        (Some(level), ValidationMode::Report | ValidationMode::Strict) => {
+            Some(validate_pdfa(state, &final_pdf, level, validation).await?)
+        }
+        _ => None,
     };
 
     let duration = start.elapsed();
@@ -196,13 +700,201 @@ pub async fn render_pdf(
         duration_ms = duration.as_millis() as u64,
         template_size_bytes = template_size,
         output_size_bytes = final_pdf.len(),
-        pdf_a = pdf_a_enabled,
+        pdf_a_level = pdfa_level.map(|l| l.as_str()),
+        engine = engine.as_str(),
+        optimize = optimize.as_ref().map(|o| o.name()),
+        color_mode = color_mode.as_str(),
+        icc_profile = payload.options.as_ref().and_then(|o| o.icc_profile.as_ref()).map(IccProfile::name),
+        watermark = stamp.is_some(),
+        attachments = payload.attachments.len(),
+        signature_level = signature.map(|s| s.level.as_str()),
+        encrypted = security.is_some(),
+        linearized = linearize,
+        tagged = tagged,
+        deterministic = clock.is_some(),
         "PDF render completed successfully"
     );
 
-    Ok((
-        StatusCode::OK,
-        [(header::CONTENT_TYPE, "application/pdf")],
-        final_pdf
-    ))
+    Ok(RenderedPdf { data: final_pdf, font_report, validation_report })
+}
+
+fn render_template(
+    state: &AppState,
+    payload: &RenderRequest,
+    data_keys: &[&str],
+    clock: Option<DateTime<FixedOffset>>,
+    start: Instant,
+) -> Result<String, AppError> {
+    // Criar contexto e verificar se foi criado corretamente
+    let context = match tera::Context::from_value(payload.data.clone()) {
+        Ok(ctx) => ctx,
+        Err(e) => {
+            tracing::error!(
+                event = "render_pdf_error",
+                stage = "context_creation",
+                error = %e,
+                data_keys = ?data_keys,
+                "Failed to create Tera context from JSON data"
+            );
+            return Err(AppError::TemplateError(tera::Error::msg(format!("Invalid context data: {}", e))));
+        }
+    };
+
+    // Renderizar template HTML
+    match state.template_engine.render(&payload.template_html, &context, clock) {
+        Ok(html) => {
+            tracing::debug!(
+                event = "template_rendered",
+                html_size_bytes = html.len(),
+                "Template rendered to HTML"
+            );
+            Ok(html)
+        }
+        Err(e) => {
+            let duration = start.elapsed();
+            tracing::error!(
+                event = "render_pdf_error",
+                stage = "template_rendering",
+                duration_ms = duration.as_millis() as u64,
+                error = %e,
+                "PDF render failed at template stage"
+            );
+            Err(AppError::from(e))
+        }
+    }
+
+}
+
+fn validate_source(state: &AppState, payload: &RenderRequest) -> Result<(), AppError> {
+    if let Some(source) = &payload.source {
+        if !payload.template_html.is_empty() {
+            return Err(AppError::ValidationError(
+                "'source' and 'template_html' are mutually exclusive".to_string(),
+            ));
+        }
+        state.url_allowlist.check(&source.url).map_err(AppError::UrlNotAllowed)?;
+        for domain in source.cookies.iter().filter_map(|c| c.domain.as_deref()) {
+            state.url_allowlist.check_cookie_domain(domain).map_err(AppError::UrlNotAllowed)?;
+        }
+    }
+    Ok(())
+}
+
+/// Builds the font report of a printed PDF; strict mode fails on any gap.
+fn check_fonts(pdf: &[u8], fallbacks: Vec<FontFallback>, check: FontCheck) -> Result<FontReport, AppError> {
+    let (fonts, missing_glyphs) = FontCoverage::scan_pdf(pdf).map_err(|e| {
+        tracing::error!(
+            event = "render_pdf_error",
+            stage = "font_check",
+            error = %e,
+            "PDF render failed while checking fonts"
+        );
+        AppError::PdfProcessingError(e.to_string())
+    })?;
+    let report = FontReport { fonts, fallbacks, missing_glyphs };
+
+    tracing::info!(
+        event = "font_coverage_checked",
+        font_count = report.fonts.len(),
+        fallback_count = report.fallbacks.len(),
+        missing_glyph_count = report.missing_glyph_count(),
+        strict = check == FontCheck::Strict,
+        "Font coverage checked"
+    );
+
+    if check == FontCheck::Strict && !report.is_clean() {
+        return Err(AppError::FontCoverageError(format!(
+            "{} font fallback(s) and {} missing glyph(s): {}",
+            report.fallbacks.len(),
+            report.missing_glyph_count(),
+            serde_json::to_string(&report).unwrap_or_default()
+        )));
+    }
+    Ok(report)
+}
+
+/// Summarizes the font report in `X-Fonts-Used`, `X-Font-Fallbacks` and `X-Missing-Glyphs`.
+fn insert_font_headers(headers: &mut HeaderMap, report: &FontReport) {
+    let used = report.fonts
+        .iter()
+        .map(|f| f.name.as_str())
+        .collect::<Vec<_>>()
+        .join(", ");
+    // Font names are PDF names, but keep the header valid whatever they contain
+    let used: String = used.chars().filter(|c| c.is_ascii_graphic() || *c == ' ').collect();
+    if let Ok(value) = HeaderValue::from_str(&used) {
+        headers.insert("x-fonts-used", value);
+    }
+    headers.insert("x-font-fallbacks", HeaderValue::from(report.fallbacks.len()));
+    headers.insert("x-missing-glyphs", HeaderValue::from(report.missing_glyph_count()));
+}
+
+/// Validates the final PDF/A output; strict mode fails when it is not compliant.
+pub(crate) async fn validate_pdfa(state: &AppState, pdf: &[u8], level: PdfaLevel, mode: ValidationMode) -> Result<ValidationReport, AppError> {
+    let report = state.pdfa_validator.validate(pdf, level).await.map_err(|e| {
+        tracing::error!(
+            event = "render_pdf_error",
+            stage = "pdfa_validation",
+            error = %e,
+            "PDF render failed while validating PDF/A output"
+        );
+        AppError::PdfProcessingError(e.to_string())
+    })?;
+
+    if mode == ValidationMode::Strict && !report.compliant {
+        return Err(AppError::PdfaValidationError(format!(
+            "Output is not PDF/A-{} compliant ({} rule(s) failed): {}",
+            report.profile,
+            report.failures.len(),
+            serde_json::to_string(&report).unwrap_or_default()
+        )));
+    }
+    Ok(report)
+}
+
+/// Sets `X-PdfA-Compliant`, `X-PdfA-Validator` and `X-PdfA-Report`, the
+/// report as compact JSON.
+pub(crate) fn insert_validation_headers(headers: &mut HeaderMap, report: &ValidationReport) {
+    headers.insert("x-pdfa-compliant", HeaderValue::from_static(if report.compliant { "true" } else { "false" }));
+    headers.insert("x-pdfa-validator", HeaderValue::from_static(report.validator));
+
+    let mut json = serde_json::to_string(report).unwrap_or_default();
+    // Proxies commonly cap headers at 8 KB; keep the rule ids and counts
+    if json.len() > MAX_REPORT_HEADER_BYTES {
+        let mut compact = report.clone();
+        compact.failures.iter_mut().for_each(|f| f.description.clear());
+        json = serde_json::to_string(&compact).unwrap_or_default();
+    }
+    // veraPDF descriptions are English, but keep the header valid whatever they contain
+    let json: String = json.chars().filter(|c| c.is_ascii_graphic() || *c == ' ').collect();
+    if let Ok(value) = HeaderValue::from_str(&json) {
+        headers.insert("x-pdfa-report", value);
+    }
+}
+
+/// Embeds the tenant's uploaded fonts as `@font-face` rules.
+async fn with_tenant_fonts(state: &AppState, tenant: Option<&str>, html: String) -> Result<String, AppError> {
+    let Some(tenant) = tenant else {
+        return Ok(html);
+    };
+    let css = state.fonts.font_face_css(tenant).await?;
+    if !css.is_empty() {
+        tracing::debug!(
+            event = "tenant_fonts_injected",
+            tenant = %tenant,
+            css_size_bytes = css.len(),
+            "Tenant fonts embedded in page"
+        );
+    }
+    Ok(FontStore::inject_css(&html, &css))
+}
+
+/// Instant pinned for deterministic renders, `None` otherwise.
+fn frozen_clock(payload: &RenderRequest) -> Result<Option<DateTime<FixedOffset>>, AppError> {
+    match &payload.options {
+        Some(opts) if opts.deterministic => Deterministic::timestamp(opts.timestamp.as_deref())
+            .map(Some)
+            .map_err(|e| AppError::ValidationError(e.to_string())),
+        _ => Ok(None),
+    }
 }
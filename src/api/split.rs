@@ -0,0 +1,146 @@
+use axum::{Json, response::{IntoResponse, Response}, http::{HeaderMap, HeaderValue, StatusCode, header}, extract::State};
+use base64::Engine as _;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::time::Instant;
+use zip::write::SimpleFileOptions;
+use crate::api::error::AppError;
+use crate::api::merge::PartSource;
+use crate::api::render::render_document;
+use crate::api::state::AppState;
+use crate::infra::split::{PdfSplitter, SplitError, SplitMode, SplitPart};
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SplitFormat {
+    /// One zip archive holding every part.
+    #[default]
+    Zip,
+    /// A JSON list with each part base64-encoded.
+    Json,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct SplitRequest {
+    #[serde(flatten)]
+    pub source: PartSource,
+    pub mode: SplitMode,
+    #[serde(default)]
+    pub format: SplitFormat,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SplitDocument {
+    pub name: String,
+    pub first_page: usize,
+    pub last_page: usize,
+    pub page_count: usize,
+    /// Base64-encoded PDF.
+    pub pdf: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SplitResponse {
+    pub documents: Vec<SplitDocument>,
+}
+
+pub async fn split_pdf(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(payload): Json<SplitRequest>,
+) -> Result<Response, AppError> {
+    let start = Instant::now();
+
+    tracing::info!(
+        event = "split_started",
+        mode = ?payload.mode,
+        format = ?payload.format,
+        "Starting PDF split"
+    );
+
+    // Obter o documento de origem
+    let source = match &payload.source {
+        PartSource::Pdf(encoded) => base64::engine::general_purpose::STANDARD
+            .decode(encoded.trim())
+            .map_err(|e| AppError::ValidationError(format!("Invalid base64 PDF: {}", e)))?,
+        PartSource::Render(request) => {
+            let rendered = render_document(&state, &headers, request).await.map_err(|e| {
+                tracing::error!(
+                    event = "split_error",
+                    stage = "render",
+                    error = %e,
+                    "Split failed while rendering the source document"
+                );
+                e
+            })?;
+            rendered.data
+        }
+    };
+
+    let parts = PdfSplitter::split(&source, &payload.mode).map_err(|e| {
+        tracing::error!(
+            event = "split_error",
+            stage = "split",
+            error = %e,
+            "Split failed while extracting pages"
+        );
+        match e {
+            SplitError::Invalid(message) => AppError::ValidationError(message),
+            SplitError::Internal(e) => AppError::PdfProcessingError(e.to_string()),
+        }
+    })?;
+
+    tracing::info!(
+        event = "split_success",
+        duration_ms = start.elapsed().as_millis() as u64,
+        part_count = parts.len(),
+        input_size_bytes = source.len(),
+        "PDF split completed successfully"
+    );
+
+    match payload.format {
+        SplitFormat::Json => {
+            let documents = parts
+                .into_iter()
+                .map(|part| SplitDocument {
+                    name: part.name,
+                    first_page: part.pages.start + 1,
+                    last_page: part.pages.end,
+                    page_count: part.pages.len(),
+                    pdf: base64::engine::general_purpose::STANDARD.encode(&part.data),
+                })
+                .collect();
+            Ok(Json(SplitResponse { documents }).into_response())
+        }
+        SplitFormat::Zip => {
+            let archive = zip_parts(&parts).map_err(|e| {
+                tracing::error!(
+                    event = "split_error",
+                    stage = "zip",
+                    error = %e,
+                    "Split failed while building the archive"
+                );
+                AppError::PdfProcessingError(e.to_string())
+            })?;
+
+            let mut response_headers = HeaderMap::new();
+            response_headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("application/zip"));
+            response_headers.insert(
+                header::CONTENT_DISPOSITION,
+                HeaderValue::from_static("attachment; filename=\"split.zip\""),
+            );
+            Ok((StatusCode::OK, response_headers, archive).into_response())
+        }
+    }
+}
+
+/// PDFs are already compressed, so parts are stored as they are.
+fn zip_parts(parts: &[SplitPart]) -> anyhow::Result<Vec<u8>> {
+    let mut archive = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
+    for part in parts {
+        archive.start_file(part.name.as_str(), options)?;
+        archive.write_all(&part.data)?;
+    }
+    Ok(archive.finish()?.into_inner())
+}
@@ -1,10 +1,20 @@
 use axum::extract::FromRef;
-use crate::infra::{browser::BrowserManager, templates::TemplateEngine};
+use crate::core::renderer::Renderers;
+use crate::infra::{assets::Assets, browser::BrowserManager, color::IccProfiles, fonts::FontStore, ghostscript::Ghostscript, linearize::Linearizer, signing::Signer, templates::TemplateEngine, url_allowlist::UrlAllowlist, validation::PdfaValidator};
 
 #[derive(Clone)]
 pub struct AppState {
     pub browser: BrowserManager,
+    pub renderers: Renderers,
     pub template_engine: TemplateEngine,
+    pub url_allowlist: UrlAllowlist,
+    pub fonts: FontStore,
+    pub assets: Assets,
+    pub ghostscript: Ghostscript,
+    pub icc_profiles: IccProfiles,
+    pub pdfa_validator: PdfaValidator,
+    pub signer: Signer,
+    pub linearizer: Option<Linearizer>,
 }
 
 impl FromRef<AppState> for BrowserManager {
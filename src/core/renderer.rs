@@ -0,0 +1,60 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use crate::infra::browser::{EmulationOptions, PageSource, PrintOptions, PrintedPdf};
+
+/// HTML-to-PDF engine selected per request with the `engine` option.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Engine {
+    /// Headless Chrome via CDP.
+    #[default]
+    Chrome,
+    /// WeasyPrint run as a subprocess. No JavaScript, stronger paged-media CSS.
+    Weasyprint,
+}
+
+impl Engine {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Engine::Chrome => "chrome",
+            Engine::Weasyprint => "weasyprint",
+        }
+    }
+}
+
+/// Turns a page into a PDF.
+pub trait Renderer: Send + Sync {
+    fn engine(&self) -> Engine;
+
+    /// Rejects options the engine cannot honor, before any work is done.
+    fn check_support(&self, _source: &PageSource<'_>, _emulation: &EmulationOptions, _print: &PrintOptions) -> Result<()> {
+        Ok(())
+    }
+
+    fn render(&self, source: PageSource<'_>, emulation: &EmulationOptions, print: &PrintOptions) -> Result<PrintedPdf>;
+}
+
+/// The engines installed in this process.
+#[derive(Clone)]
+pub struct Renderers {
+    engines: Vec<Arc<dyn Renderer>>,
+}
+
+impl Renderers {
+    pub fn new(engines: Vec<Arc<dyn Renderer>>) -> Self {
+        Self { engines }
+    }
+
+    pub fn get(&self, engine: Engine) -> Result<Arc<dyn Renderer>> {
+        self.engines
+            .iter()
+            .find(|r| r.engine() == engine)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("Engine '{}' is not available on this server", engine.as_str()))
+    }
+
+    pub fn available(&self) -> Vec<Engine> {
+        self.engines.iter().map(|r| r.engine()).collect()
+    }
+}
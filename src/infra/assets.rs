@@ -0,0 +1,94 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use anyhow::Result;
+use tempfile::TempDir;
+
+/// Ghostscript prefix file that adds the OutputIntent of PDF/A and CMYK output.
+pub const PDFA_DEF: &str = "PDFA_def.ps";
+/// sRGB profile used as the PDF/A output intent.
+pub const SRGB_ICC: &str = "srgb.icc";
+
+/// Default assets, compiled into the binary.
+const EMBEDDED: [(&str, &[u8]); 2] = [
+    (PDFA_DEF, include_bytes!("../../assets/PDFA_def.ps")),
+    (SRGB_ICC, include_bytes!("../../assets/srgb.icc")),
+];
+
+/// Files that external tools read from disk.
+///
+/// By default the embedded copies are written to a private temporary
+/// directory that lives as long as the process; `ASSETS_DIR` points to a
+/// directory with replacements instead. Either way every path is absolute,
+/// so the working directory does not matter.
+#[derive(Clone, Debug)]
+pub struct Assets {
+    dir: PathBuf,
+    /// Keeps the managed directory alive; `None` when `ASSETS_DIR` is used.
+    managed: Option<Arc<TempDir>>,
+}
+
+impl Assets {
+    pub fn from_env() -> Result<Self> {
+        match std::env::var("ASSETS_DIR") {
+            Ok(dir) if !dir.is_empty() => Self::from_dir(PathBuf::from(dir)),
+            _ => Self::extract(),
+        }
+    }
+
+    /// Uses the assets in `dir`, which must contain every file.
+    pub fn from_dir(dir: PathBuf) -> Result<Self> {
+        let dir = dir
+            .canonicalize()
+            .map_err(|e| anyhow::anyhow!("Assets directory {} is not usable: {}", dir.display(), e))?;
+        let assets = Self { dir, managed: None };
+        assets.verify()?;
+        Ok(assets)
+    }
+
+    /// Writes the embedded assets to a new temporary directory.
+    pub fn extract() -> Result<Self> {
+        let managed = tempfile::Builder::new().prefix("pdfsynth-assets-").tempdir()?;
+        for (name, data) in EMBEDDED {
+            std::fs::write(managed.path().join(name), data)
+                .map_err(|e| anyhow::anyhow!("Failed to write asset {}: {}", name, e))?;
+        }
+        let assets = Self { dir: managed.path().to_path_buf(), managed: Some(Arc::new(managed)) };
+        assets.verify()?;
+        Ok(assets)
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn is_embedded(&self) -> bool {
+        self.managed.is_some()
+    }
+
+    pub fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(name)
+    }
+
+    pub fn pdfa_def(&self) -> PathBuf {
+        self.path(PDFA_DEF)
+    }
+
+    pub fn srgb_icc(&self) -> PathBuf {
+        self.path(SRGB_ICC)
+    }
+
+    /// Checks that every asset is a readable, non-empty file.
+    fn verify(&self) -> Result<()> {
+        for (name, _) in EMBEDDED {
+            let path = self.path(name);
+            let metadata = std::fs::metadata(&path)
+                .map_err(|e| anyhow::anyhow!("Asset {} is missing: {}", path.display(), e))?;
+            if !metadata.is_file() || metadata.len() == 0 {
+                return Err(anyhow::anyhow!("Asset {} is not a non-empty file", path.display()));
+            }
+            std::fs::File::open(&path)
+                .map_err(|e| anyhow::anyhow!("Asset {} is not readable: {}", path.display(), e))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,342 @@
+use anyhow::Result;
+use base64::Engine as _;
+use chrono::{DateTime, FixedOffset};
+use lopdf::{dictionary, text_string, Dictionary, Document, Object, ObjectId, Stream};
+use serde::{Deserialize, Serialize};
+use std::time::Instant;
+use crate::infra::deterministic::Deterministic;
+use crate::infra::pdf_objects::{metadata_id, read_xmp, save, write_xmp};
+use crate::infra::xmp::{add_description, escape_xml};
+
+const MAX_FILENAME_CHARS: usize = 255;
+/// XMP namespace of Factur-X 1.0 and ZUGFeRD 2.1 and later.
+const FACTURX_NAMESPACE: &str = "urn:factur-x:pdfa:CrossIndustryDocument:invoice:1p0#";
+
+/// How an attachment relates to the document (`/AFRelationship`).
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AfRelationship {
+    /// The original the document was produced from.
+    Source,
+    /// Data behind the document, such as the table of a chart.
+    Data,
+    /// An equivalent representation, such as the XML of a hybrid invoice.
+    Alternative,
+    Supplement,
+    Unspecified,
+}
+
+impl AfRelationship {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Source => "Source",
+            Self::Data => "Data",
+            Self::Alternative => "Alternative",
+            Self::Supplement => "Supplement",
+            Self::Unspecified => "Unspecified",
+        }
+    }
+}
+
+/// One entry of the `attachments` list.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Attachment {
+    pub filename: String,
+    /// MIME type, written as the embedded file's `/Subtype`.
+    pub mime: String,
+    /// `unspecified` by default; for an e-invoice, what its profile expects.
+    #[serde(default)]
+    pub relationship: Option<AfRelationship>,
+    pub content_base64: String,
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+/// The e-invoice profile read from an embedded Factur-X or XRechnung XML,
+/// for the `fx` XMP properties.
+#[derive(Debug, Clone)]
+pub struct Invoice {
+    pub filename: String,
+    /// `ConformanceLevel`: MINIMUM, BASIC WL, BASIC, EN 16931, EXTENDED or XRECHNUNG.
+    pub conformance_level: &'static str,
+}
+
+impl Invoice {
+    /// File names the Factur-X and ZUGFeRD specifications reserve for the invoice.
+    const FILENAMES: [&'static str; 2] = ["factur-x.xml", "xrechnung.xml"];
+
+    /// Reads the profile from the guideline of the invoice's exchanged
+    /// document context.
+    fn detect(filename: &str, xml: &[u8]) -> Result<Self> {
+        let xml = std::str::from_utf8(xml)
+            .map_err(|_| anyhow::anyhow!("'{}' is not UTF-8 XML", filename))?;
+        let guideline = xml
+            .find("GuidelineSpecifiedDocumentContextParameter")
+            .map(|at| &xml[at..])
+            .and_then(|context| {
+                let start = context.find("ID>")? + 3;
+                let end = start + context[start..].find('<')?;
+                Some(context[start..end].trim())
+            })
+            .ok_or_else(|| {
+                anyhow::anyhow!("'{}' has no GuidelineSpecifiedDocumentContextParameter ID to tell its profile", filename)
+            })?;
+
+        let conformance_level = if guideline.contains("xrechnung") {
+            "XRECHNUNG"
+        } else if guideline.contains("extended") {
+            "EXTENDED"
+        } else if guideline.ends_with("basicwl") {
+            "BASIC WL"
+        } else if guideline.ends_with("minimum") {
+            "MINIMUM"
+        } else if guideline.ends_with(":basic") {
+            "BASIC"
+        } else if guideline == "urn:cen.eu:en16931:2017" {
+            "EN 16931"
+        } else {
+            return Err(anyhow::anyhow!("'{}' has an unknown Factur-X guideline '{}'", filename, guideline));
+        };
+        Ok(Self { filename: filename.to_string(), conformance_level })
+    }
+
+    /// Factur-X asks for `Data` with the profiles that are not a complete
+    /// invoice, and `Alternative` otherwise.
+    fn default_relationship(&self) -> AfRelationship {
+        match self.conformance_level {
+            "MINIMUM" | "BASIC WL" => AfRelationship::Data,
+            _ => AfRelationship::Alternative,
+        }
+    }
+
+    /// The `fx` properties and the PDF/A extension schema declaring them.
+    fn xmp_descriptions(&self) -> [String; 2] {
+        let properties = format!(
+            "    <rdf:Description rdf:about='' xmlns:fx='{ns}'>\n\
+      <fx:DocumentType>INVOICE</fx:DocumentType>\n\
+      <fx:DocumentFileName>{file}</fx:DocumentFileName>\n\
+      <fx:Version>1.0</fx:Version>\n\
+      <fx:ConformanceLevel>{level}</fx:ConformanceLevel>\n\
+    </rdf:Description>\n",
+            ns = FACTURX_NAMESPACE,
+            file = escape_xml(&self.filename),
+            level = self.conformance_level
+        );
+
+        let mut fields = String::new();
+        for (name, description) in [
+            ("DocumentFileName", "The name of the embedded XML document"),
+            ("DocumentType", "The type of the hybrid document in capital letters, e.g. INVOICE or ORDER"),
+            ("Version", "The actual version of the standard applying to the embedded XML document"),
+            ("ConformanceLevel", "The conformance level of the embedded XML document"),
+        ] {
+            fields.push_str(&format!(
+                "                <rdf:li rdf:parseType='Resource'>\n\
+                  <pdfaProperty:name>{}</pdfaProperty:name>\n\
+                  <pdfaProperty:valueType>Text</pdfaProperty:valueType>\n\
+                  <pdfaProperty:category>external</pdfaProperty:category>\n\
+                  <pdfaProperty:description>{}</pdfaProperty:description>\n\
+                </rdf:li>\n",
+                name, description
+            ));
+        }
+        let schema = format!(
+            "    <rdf:Description rdf:about=''\n\
+        xmlns:pdfaExtension='http://www.aiim.org/pdfa/ns/extension/'\n\
+        xmlns:pdfaSchema='http://www.aiim.org/pdfa/ns/schema#'\n\
+        xmlns:pdfaProperty='http://www.aiim.org/pdfa/ns/property#'>\n\
+      <pdfaExtension:schemas>\n\
+        <rdf:Bag>\n\
+          <rdf:li rdf:parseType='Resource'>\n\
+            <pdfaSchema:schema>Factur-X PDFA Extension Schema</pdfaSchema:schema>\n\
+            <pdfaSchema:namespaceURI>{}</pdfaSchema:namespaceURI>\n\
+            <pdfaSchema:prefix>fx</pdfaSchema:prefix>\n\
+            <pdfaSchema:property>\n\
+              <rdf:Seq>\n\
+{}              </rdf:Seq>\n\
+            </pdfaSchema:property>\n\
+          </rdf:li>\n\
+        </rdf:Bag>\n\
+      </pdfaExtension:schemas>\n\
+    </rdf:Description>\n",
+            FACTURX_NAMESPACE, fields
+        );
+        [properties, schema]
+    }
+}
+
+/// Decoded attachments, ready to embed.
+#[derive(Debug, Clone)]
+pub struct EmbeddedFiles {
+    files: Vec<(Attachment, Vec<u8>)>,
+    invoice: Option<Invoice>,
+}
+
+impl EmbeddedFiles {
+    /// Checks names and MIME types and decodes the contents, so a bad
+    /// request fails before rendering.
+    pub fn prepare(attachments: &[Attachment]) -> Result<Self> {
+        let mut files = Vec::with_capacity(attachments.len());
+        let mut invoice = None;
+        for (index, attachment) in attachments.iter().enumerate() {
+            let label = format!("attachments[{}]", index);
+            let name = attachment.filename.as_str();
+            if name.trim().is_empty()
+                || name.chars().count() > MAX_FILENAME_CHARS
+                || name.chars().any(|c| c.is_control() || c == '/' || c == '\\')
+            {
+                return Err(anyhow::anyhow!(
+                    "'{}.filename' must be a file name of 1 to {} characters, without slashes",
+                    label,
+                    MAX_FILENAME_CHARS
+                ));
+            }
+            if files.iter().any(|(a, _): &(Attachment, _)| a.filename.eq_ignore_ascii_case(name)) {
+                return Err(anyhow::anyhow!("'{}.filename' repeats '{}'", label, name));
+            }
+            let valid_mime = attachment.mime.split_once('/').is_some_and(|(kind, subtype)| {
+                let token = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_alphanumeric() || b"!#$&^_.+-".contains(&b));
+                token(kind) && token(subtype)
+            });
+            if !valid_mime {
+                return Err(anyhow::anyhow!("'{}.mime' must be a MIME type such as 'text/xml'", label));
+            }
+            let data = base64::engine::general_purpose::STANDARD
+                .decode(attachment.content_base64.trim())
+                .map_err(|e| anyhow::anyhow!("'{}.content_base64' is not valid base64: {}", label, e))?;
+
+            let mut attachment = attachment.clone();
+            if Invoice::FILENAMES.iter().any(|f| f.eq_ignore_ascii_case(name)) {
+                if invoice.is_some() {
+                    return Err(anyhow::anyhow!("'{}': only one e-invoice XML can be attached", label));
+                }
+                if attachment.mime != "text/xml" {
+                    return Err(anyhow::anyhow!("'{}.mime' must be 'text/xml' for the e-invoice '{}'", label, name));
+                }
+                let detected = Invoice::detect(name, &data)?;
+                attachment.relationship.get_or_insert(detected.default_relationship());
+                invoice = Some(detected);
+            }
+            // The content is not needed any more
+            attachment.content_base64 = String::new();
+            files.push((attachment, data));
+        }
+        Ok(Self { files, invoice })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// The Factur-X or XRechnung invoice among the files, if any.
+    pub fn invoice(&self) -> Option<&Invoice> {
+        self.invoice.as_ref()
+    }
+
+    /// Embeds the files as associated files of the document (`/AF`) and in
+    /// the `/EmbeddedFiles` name tree, and for an e-invoice adds the
+    /// Factur-X properties to the XMP metadata.
+    pub fn apply(&self, pdf_data: &[u8], modified: DateTime<FixedOffset>) -> Result<Vec<u8>> {
+        let start = Instant::now();
+        let mut doc = Document::load_mem(pdf_data)
+            .map_err(|e| anyhow::anyhow!("Failed to parse PDF: {}", e))?;
+        let mod_date = Object::string_literal(Deterministic::pdf_date(modified));
+
+        let mut entries = Vec::with_capacity(self.files.len());
+        for (attachment, data) in &self.files {
+            let mut stream = Stream::new(
+                dictionary! {
+                    "Type" => "EmbeddedFile",
+                    "Subtype" => Object::Name(attachment.mime.as_bytes().to_vec()),
+                    "Params" => dictionary! { "ModDate" => mod_date.clone(), "Size" => data.len() as i64 },
+                },
+                data.clone(),
+            );
+            stream.compress()?;
+            let stream_id = doc.add_object(stream);
+
+            let mut filespec = dictionary! {
+                "Type" => "Filespec",
+                // Byte string for older readers, Unicode in /UF
+                "F" => Object::string_literal(Self::ascii_name(&attachment.filename)),
+                "UF" => text_string(&attachment.filename),
+                "EF" => dictionary! { "F" => stream_id, "UF" => stream_id },
+                "AFRelationship" => attachment.relationship.unwrap_or(AfRelationship::Unspecified).name(),
+            };
+            if let Some(description) = &attachment.description {
+                filespec.set("Desc", text_string(description));
+            }
+            entries.push((text_string(&attachment.filename), doc.add_object(filespec)));
+        }
+
+        Self::add_to_catalog(&mut doc, &entries)?;
+
+        if let Some(invoice) = &self.invoice {
+            let id = metadata_id(&doc)
+                .ok_or_else(|| anyhow::anyhow!("The PDF has no XMP metadata for the Factur-X properties"))?;
+            let mut xmp = read_xmp(&doc, id)?;
+            for description in invoice.xmp_descriptions() {
+                xmp = add_description(&xmp, &description);
+            }
+            write_xmp(&mut doc, id, xmp)?;
+        }
+
+        let output = save(&mut doc)?;
+        tracing::debug!(
+            event = "attachments_embedded",
+            count = self.files.len(),
+            size_bytes = self.files.iter().map(|(_, d)| d.len()).sum::<usize>(),
+            invoice_profile = self.invoice.as_ref().map(|i| i.conformance_level),
+            duration_ms = start.elapsed().as_millis() as u64,
+            "Attachments embedded"
+        );
+        Ok(output)
+    }
+
+    /// Adds the file specifications to the catalog's `/AF` and to the
+    /// `/EmbeddedFiles` name tree, kept sorted.
+    fn add_to_catalog(doc: &mut Document, entries: &[(Object, ObjectId)]) -> Result<()> {
+        let catalog = doc.catalog()?.clone();
+        let mut names = match catalog.get(b"Names").ok().map(|n| doc.dereference(n)) {
+            Some(Ok((_, Object::Dictionary(names)))) => names.clone(),
+            _ => Dictionary::new(),
+        };
+        let mut tree: Vec<(Vec<u8>, Object)> = Vec::new();
+        if let Ok((_, Object::Dictionary(existing))) = names.get(b"EmbeddedFiles").and_then(|e| doc.dereference(e)) {
+            if let Ok(Object::Array(pairs)) = existing.get(b"Names") {
+                for pair in pairs.chunks_exact(2) {
+                    if let Object::String(key, _) = &pair[0] {
+                        tree.push((key.clone(), pair[1].clone()));
+                    }
+                }
+            }
+        }
+        for (key, id) in entries {
+            if let Object::String(key, _) = key {
+                tree.push((key.clone(), (*id).into()));
+            }
+        }
+        tree.sort_by(|a, b| a.0.cmp(&b.0));
+        let pairs: Vec<Object> = tree
+            .into_iter()
+            .flat_map(|(key, value)| [Object::string_literal(key), value])
+            .collect();
+        names.set("EmbeddedFiles", dictionary! { "Names" => pairs });
+
+        let mut associated = match catalog.get(b"AF").ok().map(|a| doc.dereference(a)) {
+            Some(Ok((_, Object::Array(files)))) => files.clone(),
+            _ => Vec::new(),
+        };
+        associated.extend(entries.iter().map(|(_, id)| Object::Reference(*id)));
+
+        let catalog = doc.catalog_mut()?;
+        catalog.set("Names", names);
+        catalog.set("AF", associated);
+        Ok(())
+    }
+
+    fn ascii_name(filename: &str) -> String {
+        filename.chars().map(|c| if c.is_ascii() { c } else { '_' }).collect()
+    }
+}
@@ -1,13 +1,182 @@
 use headless_chrome::{Browser, LaunchOptions};
 use headless_chrome::types::PrintToPdfOptions;
-use headless_chrome::protocol::cdp::Page;
+use headless_chrome::protocol::cdp::{Emulation, Fetch, Network, Page, Runtime, Target, CSS, DOM};
+use headless_chrome::protocol::cdp::types::Event;
+use headless_chrome::Tab;
+use headless_chrome::browser::tab::RequestPausedDecision;
 use anyhow::Result;
-use std::sync::{Arc, RwLock};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::ops::Deref;
+use std::sync::{Arc, Mutex, RwLock};
 use std::time::Instant;
+use crate::core::renderer::{Engine, Renderer};
+use crate::infra::deterministic::FROZEN_CLOCK_SCRIPT;
+use crate::infra::diagnostics::{DiagnosticsCollector, PageDiagnostics};
+use crate::infra::font_coverage::{FontCheck, FontCoverage, FontFallback, PlatformFont, TextProbe};
+use crate::infra::outline::{Heading, Outline, OutlineOptions};
+use crate::infra::url_allowlist::UrlAllowlist;
+
+/// Options forwarded to `Page.printToPDF`.
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct PrintOptions {
+    /// Emit a tagged PDF with a structure tree for assistive technologies.
+    #[serde(default)]
+    pub tagged: bool,
+    /// Turn headings into PDF bookmarks. Implies `tagged`, since Chrome
+    /// builds the outline from the structure tree.
+    #[serde(default)]
+    pub outline: Option<OutlineOptions>,
+    /// Report, or reject, text that the requested fonts do not cover.
+    #[serde(default)]
+    pub font_check: FontCheck,
+}
+
+/// Result of a browser print.
+pub struct PrintedPdf {
+    pub data: Vec<u8>,
+    /// Headings in document order, collected when an outline was requested.
+    pub headings: Vec<Heading>,
+    /// Text that fell back to another font, collected when a font check was requested.
+    pub font_fallbacks: Vec<FontFallback>,
+}
+
+/// A page to print by navigating the tab instead of injecting HTML.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct UrlSource {
+    pub url: String,
+    /// Extra HTTP headers, sent only with requests to allowlisted hosts.
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    #[serde(default)]
+    pub cookies: Vec<SourceCookie>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct SourceCookie {
+    pub name: String,
+    pub value: String,
+    /// Defaults to the host of the source URL.
+    #[serde(default)]
+    pub domain: Option<String>,
+    #[serde(default)]
+    pub path: Option<String>,
+    #[serde(default)]
+    pub secure: Option<bool>,
+    #[serde(default)]
+    pub http_only: Option<bool>,
+}
+
+/// What the tab should load before printing.
+pub enum PageSource<'a> {
+    /// Rendered template HTML, injected with `Page.setDocumentContent`.
+    Html(&'a str),
+    /// A remote page. Every request it makes is checked against the allowlist.
+    Url {
+        source: &'a UrlSource,
+        allowlist: &'a UrlAllowlist,
+    },
+}
+
+/// Overrides applied to the tab via the CDP Emulation domain before the
+/// document is loaded. Every field is optional; unset fields keep the
+/// container defaults.
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct EmulationOptions {
+    /// CSS media type: "print" or "screen".
+    #[serde(default)]
+    pub media_type: Option<String>,
+    #[serde(default)]
+    pub viewport_width: Option<u32>,
+    #[serde(default)]
+    pub viewport_height: Option<u32>,
+    #[serde(default)]
+    pub device_scale_factor: Option<f64>,
+    /// Value for `prefers-color-scheme`: "light" or "dark".
+    #[serde(default)]
+    pub color_scheme: Option<String>,
+    /// IANA timezone id, e.g. "America/Sao_Paulo".
+    #[serde(default)]
+    pub timezone: Option<String>,
+    /// ICU locale, e.g. "pt-BR".
+    #[serde(default)]
+    pub locale: Option<String>,
+    /// Freeze the JS `Date` clock at this many milliseconds since the epoch.
+    /// Set by deterministic renders, not by the request.
+    #[serde(skip)]
+    pub frozen_time_ms: Option<i64>,
+}
+
+impl EmulationOptions {
+    pub fn validate(&self) -> Result<()> {
+        if let Some(media) = &self.media_type {
+            if media != "print" && media != "screen" {
+                return Err(anyhow::anyhow!("Invalid media_type '{}': expected 'print' or 'screen'", media));
+            }
+        }
+        if let Some(scheme) = &self.color_scheme {
+            if scheme != "light" && scheme != "dark" {
+                return Err(anyhow::anyhow!("Invalid color_scheme '{}': expected 'light' or 'dark'", scheme));
+            }
+        }
+        if let Some(scale) = self.device_scale_factor {
+            if !(scale > 0.0 && scale <= 10.0) {
+                return Err(anyhow::anyhow!("Invalid device_scale_factor {}: expected a value in (0, 10]", scale));
+            }
+        }
+        if self.viewport_width == Some(0) || self.viewport_height == Some(0) {
+            return Err(anyhow::anyhow!("Viewport dimensions must be greater than zero"));
+        }
+        Ok(())
+    }
+}
 
 #[derive(Clone)]
 pub struct BrowserManager {
     browser: Arc<RwLock<Browser>>,
+    /// Browser contexts left by finished renders, already cleared.
+    contexts: Arc<Mutex<Vec<String>>>,
+}
+
+/// A tab in a browser context (an incognito profile) that no other render
+/// is using, so renders never see each other's cookies or cache.
+///
+/// Dropping it clears the context's cookies and cache and closes the tab,
+/// on every path. headless_chrome cannot dispose a context, so a cleared
+/// context goes back to the manager for the next render instead.
+struct RenderTab<'a> {
+    tab: Arc<Tab>,
+    context_id: String,
+    contexts: &'a Mutex<Vec<String>>,
+}
+
+impl Deref for RenderTab<'_> {
+    type Target = Tab;
+
+    fn deref(&self) -> &Tab {
+        &self.tab
+    }
+}
+
+impl Drop for RenderTab<'_> {
+    fn drop(&mut self) {
+        let cleared = self.tab.call_method(Network::ClearBrowserCookies(None))
+            .and_then(|_| self.tab.call_method(Network::ClearBrowserCache(None)));
+        let _ = self.tab.close(true);
+
+        match cleared {
+            Ok(_) => {
+                if let Ok(mut contexts) = self.contexts.lock() {
+                    contexts.push(std::mem::take(&mut self.context_id));
+                }
+            }
+            Err(e) => tracing::warn!(
+                event = "browser_context_discarded",
+                error = %e,
+                "Failed to clear browser context, not reusing it"
+            ),
+        }
+    }
 }
 
 impl BrowserManager {
@@ -26,6 +195,7 @@ impl BrowserManager {
         
         Ok(Self {
             browser: Arc::new(RwLock::new(browser)),
+            contexts: Arc::new(Mutex::new(Vec::new())),
         })
     }
 
@@ -65,19 +235,292 @@ impl BrowserManager {
             .map_err(|e| anyhow::anyhow!("Failed to launch browser: {}", e))
     }
 
-    pub fn print_to_pdf(&self, html: &str) -> Result<Vec<u8>> {
-        let start = std::time::Instant::now();
-        let html_size = html.len();
-        
+    fn apply_emulation(tab: &Tab, emulation: &EmulationOptions) -> Result<()> {
+        if emulation.media_type.is_some() || emulation.color_scheme.is_some() {
+            let features = emulation.color_scheme.as_ref().map(|scheme| {
+                vec![Emulation::MediaFeature {
+                    name: "prefers-color-scheme".to_string(),
+                    value: scheme.clone(),
+                }]
+            });
+            tab.call_method(Emulation::SetEmulatedMedia {
+                media: emulation.media_type.clone(),
+                features,
+            })
+            .map_err(|e| anyhow::anyhow!("Failed to set emulated media: {}", e))?;
+        }
+
+        if emulation.viewport_width.is_some()
+            || emulation.viewport_height.is_some()
+            || emulation.device_scale_factor.is_some()
+        {
+            tab.call_method(Emulation::SetDeviceMetricsOverride {
+                width: emulation.viewport_width.unwrap_or(1280),
+                height: emulation.viewport_height.unwrap_or(800),
+                device_scale_factor: emulation.device_scale_factor.unwrap_or(1.0),
+                mobile: false,
+                scale: None,
+                screen_width: None,
+                screen_height: None,
+                position_x: None,
+                position_y: None,
+                dont_set_visible_size: None,
+                screen_orientation: None,
+                viewport: None,
+                display_feature: None,
+                device_posture: None,
+            })
+            .map_err(|e| anyhow::anyhow!("Failed to set device metrics: {}", e))?;
+        }
+
+        if let Some(timezone) = &emulation.timezone {
+            tab.call_method(Emulation::SetTimezoneOverride {
+                timezone_id: timezone.clone(),
+            })
+            .map_err(|e| anyhow::anyhow!("Failed to set timezone '{}': {}", timezone, e))?;
+        }
+
+        if let Some(epoch_ms) = emulation.frozen_time_ms {
+            tab.call_method(Page::AddScriptToEvaluateOnNewDocument {
+                source: FROZEN_CLOCK_SCRIPT.replace("{{EPOCH_MS}}", &epoch_ms.to_string()),
+                world_name: None,
+                include_command_line_api: None,
+                run_immediately: None,
+            })
+            .map_err(|e| anyhow::anyhow!("Failed to freeze page clock: {}", e))?;
+        }
+
+        if let Some(locale) = &emulation.locale {
+            tab.call_method(Emulation::SetLocaleOverride {
+                locale: Some(locale.clone()),
+            })
+            .map_err(|e| anyhow::anyhow!("Failed to set locale '{}': {}", locale, e))?;
+        }
+
         tracing::debug!(
-            event = "browser_pdf_started",
-            html_size_bytes = html_size,
-            "Starting browser PDF generation"
+            event = "browser_emulation_applied",
+            media_type = ?emulation.media_type,
+            viewport_width = ?emulation.viewport_width,
+            viewport_height = ?emulation.viewport_height,
+            device_scale_factor = ?emulation.device_scale_factor,
+            color_scheme = ?emulation.color_scheme,
+            timezone = ?emulation.timezone,
+            locale = ?emulation.locale,
+            frozen_time_ms = ?emulation.frozen_time_ms,
+            "Emulation overrides applied"
+        );
+
+        Ok(())
+    }
+
+    fn load_html(tab: &Tab, html: &str) -> Result<()> {
+        tracing::debug!(
+            event = "browser_setting_content",
+            html_size_bytes = html.len(),
+            "Setting document content via CDP"
+        );
+
+        tab.navigate_to("about:blank")
+            .map_err(|e| anyhow::anyhow!("Failed to navigate to blank: {}", e))?
+            .wait_until_navigated()
+            .map_err(|e| anyhow::anyhow!("Failed to wait for blank navigation: {}", e))?;
+
+        // Get the main frame ID for SetDocumentContent
+        let frame_tree = tab.call_method(Page::GetFrameTree(None))
+            .map_err(|e| anyhow::anyhow!("Failed to get frame tree: {}", e))?;
+        let frame_id = frame_tree.frame_tree.frame.id;
+
+        // Inject HTML directly via CDP (handles large HTML with data URIs)
+        tab.call_method(Page::SetDocumentContent {
+            frame_id,
+            html: html.to_string(),
+        })
+        .map_err(|e| anyhow::anyhow!("Failed to set document content: {}", e))?;
+
+        std::thread::sleep(std::time::Duration::from_millis(300));
+
+        tracing::debug!(event = "browser_content_set", "Document content set successfully");
+        Ok(())
+    }
+
+    fn load_url(tab: &Tab, source: &UrlSource, allowlist: &UrlAllowlist) -> Result<()> {
+        let url = allowlist.check(&source.url).map_err(|e| anyhow::anyhow!(e))?;
+        let host = url.host_str().unwrap_or_default().to_string();
+
+        tracing::debug!(
+            event = "browser_navigating_url",
+            host = %host,
+            header_count = source.headers.len(),
+            cookie_count = source.cookies.len(),
+            "Navigating tab to source URL"
         );
 
+        Self::guard_requests(tab, source, allowlist)?;
+
+        if !source.cookies.is_empty() {
+            let cookies = source.cookies
+                .iter()
+                .map(|c| Network::CookieParam {
+                    name: c.name.clone(),
+                    value: c.value.clone(),
+                    url: if c.domain.is_none() { Some(url.to_string()) } else { None },
+                    domain: c.domain.clone(),
+                    path: c.path.clone(),
+                    secure: c.secure,
+                    http_only: c.http_only,
+                    same_site: None,
+                    expires: None,
+                    priority: None,
+                    same_party: None,
+                    source_scheme: None,
+                    source_port: None,
+                    partition_key: None,
+                })
+                .collect();
+            tab.set_cookies(cookies)
+                .map_err(|e| anyhow::anyhow!("Failed to set cookies: {}", e))?;
+        }
+
+        tab.navigate_to(url.as_str())
+            .map_err(|e| anyhow::anyhow!("Failed to navigate to '{}': {}", host, e))?
+            .wait_until_navigated()
+            .map_err(|e| anyhow::anyhow!("Failed to wait for navigation to '{}': {}", host, e))?;
+
+        // Redirects must not escape the allowlist
+        let final_url = tab.get_url();
+        if let Err(e) = allowlist.check(&final_url) {
+            tracing::warn!(
+                event = "browser_url_redirect_blocked",
+                host = %host,
+                error = %e,
+                "Navigation ended outside the URL allowlist"
+            );
+            return Err(anyhow::anyhow!("Navigation was redirected outside the allowlist: {}", e));
+        }
+
+        tracing::debug!(event = "browser_url_loaded", host = %host, "Source URL loaded");
+        Ok(())
+    }
+
+    /// Pauses every request the tab makes before it is sent. Navigations,
+    /// redirects and subresources whose host is not allowlisted fail, and
+    /// the source headers are added only to allowlisted hosts. WebSockets
+    /// do not go through the Fetch domain, so they are blocked outright.
+    fn guard_requests(tab: &Tab, source: &UrlSource, allowlist: &UrlAllowlist) -> Result<()> {
+        tab.call_method(Network::Enable {
+            max_total_buffer_size: None,
+            max_resource_buffer_size: None,
+            max_post_data_size: None,
+        })
+        .map_err(|e| anyhow::anyhow!("Failed to enable Network domain: {}", e))?;
+        tab.call_method(Network::SetBlockedURLs {
+            urls: vec!["ws://*".to_string(), "wss://*".to_string()],
+        })
+        .map_err(|e| anyhow::anyhow!("Failed to block WebSockets: {}", e))?;
+
+        let allowlist = allowlist.clone();
+        let extra_headers = source.headers.clone();
+        tab.enable_request_interception(Arc::new(
+            move |_transport, _session_id, event: Fetch::events::RequestPausedEvent| {
+                Self::intercept_request(event, &allowlist, &extra_headers)
+            },
+        ))
+        .map_err(|e| anyhow::anyhow!("Failed to install request interceptor: {}", e))?;
+
+        let patterns = [Fetch::RequestPattern {
+            url_pattern: Some("*".to_string()),
+            resource_Type: None,
+            request_stage: Some(Fetch::RequestStage::Request),
+        }];
+        tab.enable_fetch(Some(&patterns), None)
+            .map_err(|e| anyhow::anyhow!("Failed to enable request interception: {}", e))?;
+        Ok(())
+    }
+
+    fn intercept_request(
+        event: Fetch::events::RequestPausedEvent,
+        allowlist: &UrlAllowlist,
+        extra_headers: &HashMap<String, String>,
+    ) -> RequestPausedDecision {
+        let params = event.params;
+        let request_id = params.request_id;
+
+        // Inline data never reaches the network
+        if params.request.url.starts_with("data:") || params.request.url.starts_with("blob:") {
+            return RequestPausedDecision::Continue(None);
+        }
+
+        if let Err(e) = allowlist.check(&params.request.url) {
+            tracing::warn!(
+                event = "browser_request_blocked",
+                resource_type = ?params.resource_Type,
+                error = %e,
+                "Blocked request outside the URL allowlist"
+            );
+            return RequestPausedDecision::Fail(Fetch::FailRequest {
+                request_id,
+                error_reason: Network::ErrorReason::BlockedByClient,
+            });
+        }
+
+        if extra_headers.is_empty() {
+            return RequestPausedDecision::Continue(None);
+        }
+
+        // Continuing with headers replaces them all, so keep the page's own
+        let mut headers: Vec<Fetch::HeaderEntry> = params.request.headers.0
+            .as_ref()
+            .and_then(|h| h.as_object())
+            .map(|h| {
+                h.iter()
+                    .filter(|(name, _)| !extra_headers.keys().any(|k| k.eq_ignore_ascii_case(name)))
+                    .filter_map(|(name, value)| value.as_str().map(|v| Fetch::HeaderEntry {
+                        name: name.clone(),
+                        value: v.to_string(),
+                    }))
+                    .collect()
+            })
+            .unwrap_or_default();
+        headers.extend(extra_headers.iter().map(|(name, value)| Fetch::HeaderEntry {
+            name: name.clone(),
+            value: value.clone(),
+        }));
+
+        RequestPausedDecision::Continue(Some(Fetch::ContinueRequest {
+            request_id,
+            url: None,
+            method: None,
+            post_data: None,
+            headers: Some(headers),
+            intercept_response: None,
+        }))
+    }
+
+    /// Opens a tab in a context of its own: a cleared one from an earlier
+    /// render, or a new one.
+    fn new_isolated_tab(browser: &Browser, context_id: Option<String>) -> Result<(Arc<Tab>, String)> {
+        let context_id = match context_id {
+            Some(id) => id,
+            None => browser.new_context()?.get_id().to_string(),
+        };
+        let tab = browser.new_tab_with_options(Target::CreateTarget {
+            url: "about:blank".to_string(),
+            width: None,
+            height: None,
+            browser_context_id: Some(context_id.clone()),
+            enable_begin_frame_control: None,
+            new_window: None,
+            background: None,
+            for_tab: None,
+        })?;
+        Ok((tab, context_id))
+    }
+
+    fn open_tab(&self) -> Result<RenderTab<'_>> {
+        let reused = self.contexts.lock().map_err(|_| anyhow::anyhow!("Context pool lock poisoned"))?.pop();
         let tab = {
             let browser_guard = self.browser.read().map_err(|_| anyhow::anyhow!("Browser lock poisoned"))?;
-            browser_guard.new_tab()
+            Self::new_isolated_tab(&browser_guard, reused)
         };
 
         let tab = match tab {
@@ -96,8 +539,12 @@ impl BrowserManager {
                 match Self::create_browser() {
                     Ok(new_browser) => {
                         *browser_guard = new_browser;
+                        // Contexts belong to the old browser
+                        if let Ok(mut contexts) = self.contexts.lock() {
+                            contexts.clear();
+                        }
                         tracing::info!(event = "browser_restarted", "Browser restarted successfully");
-                        browser_guard.new_tab()
+                        Self::new_isolated_tab(&browser_guard, None)
                             .map_err(|e| anyhow::anyhow!("Failed to create tab after restart: {}", e))?
                     },
                     Err(e) => {
@@ -112,33 +559,23 @@ impl BrowserManager {
             }
         };
 
-        tracing::debug!(
-            event = "browser_setting_content",
-            html_size_bytes = html_size,
-            "Setting document content via CDP"
-        );
+        let (tab, context_id) = tab;
+        Ok(RenderTab { tab, context_id, contexts: &self.contexts })
+    }
 
-        tab.navigate_to("about:blank")
-            .map_err(|e| anyhow::anyhow!("Failed to navigate to blank: {}", e))?
-            .wait_until_navigated()
-            .map_err(|e| anyhow::anyhow!("Failed to wait for blank navigation: {}", e))?;
+    fn load_page(tab: &Tab, source: PageSource<'_>, emulation: &EmulationOptions) -> Result<()> {
+        Self::apply_emulation(tab, emulation)?;
 
-        // Get the main frame ID for SetDocumentContent
-        let frame_tree = tab.call_method(Page::GetFrameTree(None))
-            .map_err(|e| anyhow::anyhow!("Failed to get frame tree: {}", e))?;
-        let frame_id = frame_tree.frame_tree.frame.id;
-
-        // Inject HTML directly via CDP (handles large HTML with data URIs)
-        tab.call_method(Page::SetDocumentContent {
-            frame_id,
-            html: html.to_string(),
-        })
-        .map_err(|e| anyhow::anyhow!("Failed to set document content: {}", e))?;
-
-        std::thread::sleep(std::time::Duration::from_millis(300));
+        match source {
+            PageSource::Html(html) => Self::load_html(tab, html)?,
+            PageSource::Url { source, allowlist } => Self::load_url(tab, source, allowlist)?,
+        }
 
-        tracing::debug!(event = "browser_content_set", "Document content set successfully");
+        Self::wait_for_images(tab);
+        Ok(())
+    }
 
+    fn wait_for_images(tab: &Tab) {
         let wait_for_images_script = r#"
             new Promise((resolve) => {
                 const imgTags = Array.from(document.querySelectorAll('img'));
@@ -216,6 +653,36 @@ impl BrowserManager {
                 "Images loading completed"
             );
         }
+    }
+
+    pub fn print_to_pdf(
+        &self,
+        source: PageSource<'_>,
+        emulation: &EmulationOptions,
+        print: &PrintOptions,
+    ) -> Result<PrintedPdf> {
+        let start = std::time::Instant::now();
+
+        tracing::debug!(
+            event = "browser_pdf_started",
+            source = match source {
+                PageSource::Html(_) => "html",
+                PageSource::Url { .. } => "url",
+            },
+            "Starting browser PDF generation"
+        );
+
+        let tab = self.open_tab()?;
+        Self::load_page(&tab, source, emulation)?;
+
+        let headings = match &print.outline {
+            Some(outline) => Self::collect_headings(&tab, outline)?,
+            None => Vec::new(),
+        };
+        let font_fallbacks = match print.font_check {
+            FontCheck::Off => Vec::new(),
+            FontCheck::Report | FontCheck::Strict => Self::collect_font_fallbacks(&tab)?,
+        };
 
         let pdf_data = tab.print_to_pdf(Some(PrintToPdfOptions {
             print_background: Some(true),
@@ -228,14 +695,15 @@ impl BrowserManager {
             margin_bottom: Some(0.0),
             margin_left: Some(0.0),
             margin_right: Some(0.0),
+            generate_tagged_pdf: Some(print.tagged || print.outline.is_some()),
+            generate_document_outline: Some(print.outline.is_some()),
             ..Default::default()
         }))
             .map_err(|e| {
                 tracing::error!(event = "browser_print_failed", error = %e, "Failed to print to PDF");
                 anyhow::anyhow!("Failed to print to PDF: {}", e)
             })?;
-
-        let _ = tab.close(true);
+        drop(tab);
 
         let duration = start.elapsed();
         tracing::debug!(
@@ -245,6 +713,139 @@ impl BrowserManager {
             "Browser PDF generation completed"
         );
 
-        Ok(pdf_data)
+        Ok(PrintedPdf { data: pdf_data, headings, font_fallbacks })
+    }
+
+    /// Loads the page like [`print_to_pdf`](Self::print_to_pdf) does, without
+    /// printing, and reports console output, exceptions and failed requests.
+    pub fn inspect(&self, source: PageSource<'_>, emulation: &EmulationOptions) -> Result<PageDiagnostics> {
+        let start = std::time::Instant::now();
+        let tab = self.open_tab()?;
+
+        let collector = DiagnosticsCollector::new();
+        let listener = collector.clone();
+        tab.add_event_listener(Arc::new(move |event: &Event| listener.handle(event)))
+            .map_err(|e| anyhow::anyhow!("Failed to subscribe to tab events: {}", e))?;
+        tab.call_method(Runtime::Enable(None))
+            .map_err(|e| anyhow::anyhow!("Failed to enable Runtime domain: {}", e))?;
+        tab.call_method(Network::Enable {
+            max_total_buffer_size: None,
+            max_resource_buffer_size: None,
+            max_post_data_size: None,
+        })
+        .map_err(|e| anyhow::anyhow!("Failed to enable Network domain: {}", e))?;
+
+        let loaded = Self::load_page(&tab, source, emulation);
+        // The font probe is best-effort here; the rest of the report still helps
+        let font_fallbacks = match &loaded {
+            Ok(()) => Self::collect_font_fallbacks(&tab).unwrap_or_else(|e| {
+                tracing::warn!(event = "browser_font_probe_failed", error = %e, "Failed to collect font fallbacks");
+                Vec::new()
+            }),
+            Err(_) => Vec::new(),
+        };
+        drop(tab);
+        loaded?;
+
+        let mut diagnostics = collector.finish();
+        diagnostics.font_fallbacks = font_fallbacks;
+        let duration = start.elapsed();
+        tracing::debug!(
+            event = "browser_inspect_complete",
+            duration_ms = duration.as_millis() as u64,
+            console_count = diagnostics.console.len(),
+            exception_count = diagnostics.exceptions.len(),
+            failed_request_count = diagnostics.failed_requests.len(),
+            font_fallback_count = diagnostics.font_fallbacks.len(),
+            "Page inspection completed"
+        );
+
+        Ok(diagnostics)
+    }
+
+    fn collect_headings(tab: &Tab, outline: &OutlineOptions) -> Result<Vec<Heading>> {
+        let exclude = serde_json::to_string(&outline.exclude_classes)?;
+        let script = Outline::COLLECT_HEADINGS_SCRIPT.replace("{{EXCLUDE}}", &exclude);
+
+        let result = tab.evaluate(&script, false)
+            .map_err(|e| anyhow::anyhow!("Failed to collect headings: {}", e))?;
+        let json = result.value
+            .as_ref()
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow::anyhow!("Heading collection returned no value"))?;
+        let headings: Vec<Heading> = serde_json::from_str(json)?;
+
+        tracing::debug!(
+            event = "browser_headings_collected",
+            heading_count = headings.len(),
+            excluded_count = headings.iter().filter(|h| h.excluded).count(),
+            "Headings collected for document outline"
+        );
+
+        Ok(headings)
+    }
+}
+
+impl BrowserManager {
+    /// Asks Chrome which platform fonts drew the text of each element and
+    /// keeps those that are not in the element's `font-family`.
+    fn collect_font_fallbacks(tab: &Tab) -> Result<Vec<FontFallback>> {
+        let script = FontCoverage::PROBE_SCRIPT.replace("{{LIMIT}}", &FontCoverage::PROBE_LIMIT.to_string());
+        let result = tab.evaluate(&script, false)
+            .map_err(|e| anyhow::anyhow!("Failed to probe text elements: {}", e))?;
+        let json = result.value
+            .as_ref()
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow::anyhow!("Text probe returned no value"))?;
+        let probes: Vec<TextProbe> = serde_json::from_str(json)?;
+
+        tab.call_method(DOM::Enable { include_whitespace: None })
+            .map_err(|e| anyhow::anyhow!("Failed to enable DOM domain: {}", e))?;
+        tab.call_method(CSS::Enable(None))
+            .map_err(|e| anyhow::anyhow!("Failed to enable CSS domain: {}", e))?;
+
+        let root = tab.get_document()
+            .map_err(|e| anyhow::anyhow!("Failed to get document: {}", e))?;
+        let node_ids = tab.call_method(DOM::QuerySelectorAll {
+            node_id: root.node_id,
+            selector: FontCoverage::PROBE_SELECTOR.to_string(),
+        })
+        .map_err(|e| anyhow::anyhow!("Failed to query probed elements: {}", e))?
+        .node_ids;
+
+        let mut platform_fonts = Vec::with_capacity(node_ids.len());
+        for node_id in node_ids {
+            let fonts = tab.call_method(CSS::GetPlatformFontsForNode { node_id })
+                .map_err(|e| anyhow::anyhow!("Failed to get platform fonts: {}", e))?
+                .fonts
+                .into_iter()
+                .map(|f| PlatformFont { family: f.family_name, glyph_count: f.glyph_count as u64 })
+                .collect::<Vec<_>>();
+            platform_fonts.push(fonts);
+        }
+
+        if let Err(e) = tab.evaluate(FontCoverage::CLEANUP_SCRIPT, false) {
+            tracing::warn!(event = "browser_font_probe_cleanup_failed", error = %e, "Failed to remove font probe markers");
+        }
+
+        let fallbacks = FontCoverage::fallbacks(&probes, &platform_fonts);
+        tracing::debug!(
+            event = "browser_font_fallbacks_collected",
+            probed_elements = probes.len(),
+            fallback_count = fallbacks.len(),
+            "Font fallbacks collected"
+        );
+
+        Ok(fallbacks)
+    }
+}
+
+impl Renderer for BrowserManager {
+    fn engine(&self) -> Engine {
+        Engine::Chrome
+    }
+
+    fn render(&self, source: PageSource<'_>, emulation: &EmulationOptions, print: &PrintOptions) -> Result<PrintedPdf> {
+        self.print_to_pdf(source, emulation, print)
     }
 }
@@ -0,0 +1,417 @@
+//! Output color spaces: what Ghostscript converts the pages to and the ICC
+//! profile written as the document's OutputIntent.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use anyhow::Result;
+use base64::Engine as _;
+use lopdf::{dictionary, Document, Object, Stream};
+use serde::{Deserialize, Serialize};
+use tempfile::NamedTempFile;
+use crate::infra::pdf_objects::save;
+
+/// Largest ICC profile accepted in a request.
+const MAX_PROFILE_BYTES: usize = 16 * 1024 * 1024;
+const MAX_CONDITION_CHARS: usize = 255;
+const ICC_REGISTRY: &str = "http://www.color.org";
+/// Resolution of `mono` pages: sharper than fine fax mode, so small print
+/// stays legible.
+pub const MONO_DPI: u32 = 300;
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorMode {
+    /// Chrome's sRGB output, unchanged.
+    #[default]
+    Rgb,
+    /// Every color converted to DeviceCMYK with the `icc_profile`.
+    Cmyk,
+    /// Every color converted to DeviceGray.
+    Gray,
+    /// Pages rendered to 1-bit images, like a fax.
+    Mono,
+}
+
+impl ColorMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Rgb => "rgb",
+            Self::Cmyk => "cmyk",
+            Self::Gray => "gray",
+            Self::Mono => "mono",
+        }
+    }
+
+    /// pdfwrite switches that convert every color to this space. Mono
+    /// pages are already bilevel images and stay DeviceGray.
+    pub fn gs_args(&self) -> Vec<String> {
+        let (strategy, model) = match self {
+            Self::Rgb => ("RGB", "DeviceRGB"),
+            Self::Cmyk => ("CMYK", "DeviceCMYK"),
+            Self::Gray | Self::Mono => ("Gray", "DeviceGray"),
+        };
+        vec![
+            format!("-sColorConversionStrategy={}", strategy),
+            format!("-sProcessColorModel={}", model),
+        ]
+    }
+}
+
+/// Standard printing conditions, whose profiles the operator installs in
+/// `ICC_PROFILES_DIR` since their licenses do not allow bundling them.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PrintCondition {
+    /// Offset on coated paper, ISO 12647-2 (Europe).
+    Fogra39,
+    /// GRACoL 2013 on coated #1 paper (North America).
+    Gracol,
+}
+
+impl PrintCondition {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Fogra39 => "fogra39",
+            Self::Gracol => "gracol",
+        }
+    }
+
+    fn file_name(&self) -> String {
+        format!("{}.icc", self.as_str())
+    }
+
+    /// The characterization name from the ICC registry.
+    fn identifier(&self) -> &'static str {
+        match self {
+            Self::Fogra39 => "FOGRA39",
+            Self::Gracol => "CGATS21_CRPC6",
+        }
+    }
+
+    fn condition(&self) -> &'static str {
+        match self {
+            Self::Fogra39 => "Coated FOGRA39 (ISO 12647-2:2004)",
+            Self::Gracol => "GRACoL 2013 (CGATS.21-2 CRPC6)",
+        }
+    }
+}
+
+/// The `icc_profile` option: a standard printing condition or an uploaded profile.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum IccProfile {
+    Named(PrintCondition),
+    Custom(CustomProfile),
+}
+
+/// An output profile sent with the request, such as the print shop's own.
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct CustomProfile {
+    /// The `.icc` file, base64-encoded. Must be a CMYK output profile.
+    pub icc_base64: String,
+    /// Printing condition the profile characterizes, written as the
+    /// `/OutputConditionIdentifier`.
+    pub output_condition_identifier: String,
+    /// Human-readable description; defaults to the identifier.
+    #[serde(default)]
+    pub output_condition: Option<String>,
+    /// Registry where the identifier is defined; defaults to the ICC's.
+    #[serde(default)]
+    pub registry_name: Option<String>,
+}
+
+impl std::fmt::Debug for CustomProfile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CustomProfile")
+            .field("icc_base64", &format_args!("<{} bytes>", self.icc_base64.len()))
+            .field("output_condition_identifier", &self.output_condition_identifier)
+            .field("output_condition", &self.output_condition)
+            .field("registry_name", &self.registry_name)
+            .finish()
+    }
+}
+
+impl IccProfile {
+    /// Short name for logs: the printing condition, or `custom`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Named(condition) => condition.as_str(),
+            Self::Custom(_) => "custom",
+        }
+    }
+}
+
+/// Directory with the profiles of the standard printing conditions.
+#[derive(Clone, Debug)]
+pub struct IccProfiles {
+    dir: PathBuf,
+}
+
+impl IccProfiles {
+    /// Reads `ICC_PROFILES_DIR` (default `/app/icc-profiles`).
+    pub fn from_env() -> Self {
+        let dir = std::env::var("ICC_PROFILES_DIR").unwrap_or_else(|_| "/app/icc-profiles".to_string());
+        Self { dir: PathBuf::from(dir) }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Standard printing conditions whose profile is installed.
+    pub fn installed(&self) -> Vec<&'static str> {
+        [PrintCondition::Fogra39, PrintCondition::Gracol]
+            .into_iter()
+            .filter(|c| self.dir.join(c.file_name()).is_file())
+            .map(|c| c.as_str())
+            .collect()
+    }
+
+    /// The OutputIntent for `mode`, or `None` for the other modes, which
+    /// keep the bundled sRGB intent. Checks the profile, so a bad request
+    /// fails before rendering.
+    pub fn resolve(&self, mode: ColorMode, profile: Option<&IccProfile>) -> Result<Option<OutputIntent>> {
+        let profile = match (mode, profile) {
+            (ColorMode::Rgb | ColorMode::Gray | ColorMode::Mono, None) => return Ok(None),
+            (ColorMode::Rgb | ColorMode::Gray | ColorMode::Mono, Some(_)) => {
+                return Err(anyhow::anyhow!("'icc_profile' needs 'color_mode' cmyk"));
+            }
+            (ColorMode::Cmyk, None) => {
+                return Err(anyhow::anyhow!("'color_mode' cmyk needs an 'icc_profile': fogra39, gracol or an uploaded profile"));
+            }
+            (ColorMode::Cmyk, Some(profile)) => profile,
+        };
+
+        match profile {
+            IccProfile::Named(condition) => {
+                let path = self.dir.join(condition.file_name());
+                let data = std::fs::read(&path).map_err(|_| {
+                    anyhow::anyhow!(
+                        "The '{}' profile is not installed on this server ({} is missing)",
+                        condition.as_str(),
+                        path.display()
+                    )
+                })?;
+                check_profile(&data).map_err(|e| anyhow::anyhow!("{}: {}", path.display(), e))?;
+                Ok(Some(OutputIntent {
+                    path,
+                    _upload: None,
+                    identifier: condition.identifier().to_string(),
+                    condition: condition.condition().to_string(),
+                    registry: ICC_REGISTRY.to_string(),
+                }))
+            }
+            IccProfile::Custom(custom) => {
+                let data = base64::engine::general_purpose::STANDARD
+                    .decode(custom.icc_base64.trim())
+                    .map_err(|e| anyhow::anyhow!("'icc_profile.icc_base64' is not valid base64: {}", e))?;
+                check_profile(&data).map_err(|e| anyhow::anyhow!("'icc_profile.icc_base64': {}", e))?;
+                let fields = [
+                    ("output_condition_identifier", Some(&custom.output_condition_identifier)),
+                    ("output_condition", custom.output_condition.as_ref()),
+                    ("registry_name", custom.registry_name.as_ref()),
+                ];
+                for (name, value) in fields {
+                    if let Some(value) = value {
+                        if value.trim().is_empty() || value.chars().count() > MAX_CONDITION_CHARS || value.chars().any(char::is_control) {
+                            return Err(anyhow::anyhow!(
+                                "'icc_profile.{}' must be a single line of 1 to {} characters",
+                                name,
+                                MAX_CONDITION_CHARS
+                            ));
+                        }
+                    }
+                }
+
+                let mut file = NamedTempFile::new()?;
+                file.write_all(&data)?;
+                let identifier = custom.output_condition_identifier.clone();
+                Ok(Some(OutputIntent {
+                    path: file.path().to_path_buf(),
+                    _upload: Some(Arc::new(file)),
+                    condition: custom.output_condition.clone().unwrap_or_else(|| identifier.clone()),
+                    registry: custom.registry_name.clone().unwrap_or_else(|| ICC_REGISTRY.to_string()),
+                    identifier,
+                }))
+            }
+        }
+    }
+}
+
+/// A checked CMYK output profile and how the OutputIntent describes it.
+#[derive(Clone, Debug)]
+pub struct OutputIntent {
+    path: PathBuf,
+    /// Keeps an uploaded profile on disk while the intent is in use.
+    _upload: Option<Arc<NamedTempFile>>,
+    identifier: String,
+    condition: String,
+    registry: String,
+}
+
+impl OutputIntent {
+    pub fn identifier(&self) -> &str {
+        &self.identifier
+    }
+
+    /// pdfwrite switches that convert the colors with this profile.
+    pub fn conversion_args(&self) -> Vec<String> {
+        let mut args = ColorMode::Cmyk.gs_args();
+        args.extend([
+            // Ghostscript opens the profile, which -dSAFER only allows when permitted
+            format!("--permit-file-read={}", self.path.display()),
+            format!("-sOutputICCProfile={}", self.path.display()),
+        ]);
+        args
+    }
+
+    /// [`conversion_args`](Self::conversion_args) plus the values
+    /// `PDFA_def.ps` writes to the PDF/A OutputIntent.
+    pub fn pdfa_args(&self) -> Vec<String> {
+        let mut args = self.conversion_args();
+        args.extend([
+            format!("-sICCProfile={}", self.path.display()),
+            "-dOutputIntentComponents=4".to_string(),
+            format!("-sOutputConditionIdentifier={}", self.identifier),
+            format!("-sOutputCondition={}", self.condition),
+            format!("-sOutputIntentRegistry={}", self.registry),
+        ]);
+        args
+    }
+}
+
+/// Builds an image-only PDF from `pbm`, the binary PBM pages Ghostscript
+/// rendered at `dpi`, one bilevel image per page. The `/Info` of `source`
+/// is kept.
+pub fn bilevel_pdf(pbm: &[u8], dpi: u32, source: &[u8]) -> Result<Vec<u8>> {
+    let mut doc = Document::with_version("1.4");
+    let pages_id = doc.new_object_id();
+    let mut kids = Vec::new();
+    let mut rest = pbm;
+    while !rest.is_empty() {
+        let (width, height, bits, next) = next_pbm(rest)?;
+        rest = next;
+
+        let mut image = Stream::new(
+            dictionary! {
+                "Type" => "XObject",
+                "Subtype" => "Image",
+                "Width" => i64::from(width),
+                "Height" => i64::from(height),
+                "ColorSpace" => "DeviceGray",
+                "BitsPerComponent" => 1,
+                // PBM uses 1 for black
+                "Decode" => vec![1.into(), 0.into()],
+            },
+            bits.to_vec(),
+        );
+        image.compress()?;
+        let image_id = doc.add_object(image);
+
+        let (page_width, page_height) = (points(width, dpi), points(height, dpi));
+        let mut content = Stream::new(
+            dictionary! {},
+            format!("q {:.2} 0 0 {:.2} 0 0 cm /Page Do Q", page_width, page_height).into_bytes(),
+        );
+        content.compress()?;
+        let content_id = doc.add_object(content);
+        kids.push(Object::from(doc.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "MediaBox" => vec![0.into(), 0.into(), page_width.into(), page_height.into()],
+            "Resources" => dictionary! { "XObject" => dictionary! { "Page" => image_id } },
+            "Contents" => content_id,
+        })));
+    }
+    if kids.is_empty() {
+        return Err(anyhow::anyhow!("Ghostscript rendered no pages"));
+    }
+
+    let count = kids.len() as i64;
+    doc.objects.insert(pages_id, Object::Dictionary(dictionary! { "Type" => "Pages", "Kids" => kids, "Count" => count }));
+    let catalog_id = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+    doc.trailer.set("Root", catalog_id);
+    if let Ok(original) = Document::load_mem(source) {
+        if let Ok(info) = original.trailer.get(b"Info").and_then(|i| original.dereference(i)).and_then(|(_, i)| i.as_dict()) {
+            let info = info.iter().filter(|(_, v)| !matches!(v, Object::Reference(_))).map(|(k, v)| (k.clone(), v.clone())).collect();
+            let info_id = doc.add_object(Object::Dictionary(info));
+            doc.trailer.set("Info", info_id);
+        }
+    }
+    save(&mut doc)
+}
+
+fn points(pixels: u32, dpi: u32) -> f32 {
+    pixels as f32 * 72.0 / dpi as f32
+}
+
+/// Splits the first image off a stream of binary (`P4`) PBM images:
+/// width, height, the packed rows and what follows.
+fn next_pbm(data: &[u8]) -> Result<(u32, u32, &[u8], &[u8])> {
+    if !data.starts_with(b"P4") {
+        return Err(anyhow::anyhow!("Ghostscript output is not a binary PBM image"));
+    }
+    let mut at = 2;
+    let mut fields = [0u32; 2];
+    for field in fields.iter_mut() {
+        // Whitespace and comments before each number
+        loop {
+            match data.get(at) {
+                Some(b'#') => {
+                    while data.get(at).is_some_and(|b| *b != b'\n') {
+                        at += 1;
+                    }
+                }
+                Some(b) if b.is_ascii_whitespace() => at += 1,
+                _ => break,
+            }
+        }
+        let start = at;
+        while data.get(at).is_some_and(u8::is_ascii_digit) {
+            at += 1;
+        }
+        *field = std::str::from_utf8(&data[start..at])?
+            .parse()
+            .map_err(|_| anyhow::anyhow!("Invalid PBM header"))?;
+    }
+    // A single whitespace byte ends the header
+    at += 1;
+    let [width, height] = fields;
+    let size = (width as usize).div_ceil(8) * height as usize;
+    if width == 0 || height == 0 || data.len() < at + size {
+        return Err(anyhow::anyhow!("Truncated PBM image of {}x{}", width, height));
+    }
+    let rest = &data[at + size..];
+    // Some writers separate images with a newline
+    let rest = rest.strip_prefix(b"\n").unwrap_or(rest);
+    Ok((width, height, &data[at..at + size], rest))
+}
+
+/// Checks the ICC header: an output (printer) profile in CMYK.
+fn check_profile(data: &[u8]) -> Result<()> {
+    if data.len() > MAX_PROFILE_BYTES {
+        return Err(anyhow::anyhow!("the profile is larger than {} MB", MAX_PROFILE_BYTES / 1024 / 1024));
+    }
+    if data.len() < 128 || &data[36..40] != b"acsp" {
+        return Err(anyhow::anyhow!("not an ICC profile"));
+    }
+    let declared = u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as usize;
+    if declared > data.len() {
+        return Err(anyhow::anyhow!("the profile is truncated ({} of {} bytes)", data.len(), declared));
+    }
+    if &data[16..20] != b"CMYK" {
+        return Err(anyhow::anyhow!(
+            "a CMYK profile is needed, found '{}'",
+            String::from_utf8_lossy(&data[16..20]).trim()
+        ));
+    }
+    if &data[12..16] != b"prtr" {
+        return Err(anyhow::anyhow!(
+            "an output (printer) profile is needed, found class '{}'",
+            String::from_utf8_lossy(&data[12..16])
+        ));
+    }
+    Ok(())
+}
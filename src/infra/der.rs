@@ -0,0 +1,275 @@
+//! Just enough ASN.1 DER to read certificates and write CMS structures.
+
+use anyhow::Result;
+
+pub const INTEGER: u8 = 0x02;
+pub const BIT_STRING: u8 = 0x03;
+pub const OCTET_STRING: u8 = 0x04;
+pub const NULL: u8 = 0x05;
+pub const OID: u8 = 0x06;
+pub const UTF8_STRING: u8 = 0x0C;
+pub const PRINTABLE_STRING: u8 = 0x13;
+pub const T61_STRING: u8 = 0x14;
+pub const IA5_STRING: u8 = 0x16;
+pub const UTC_TIME: u8 = 0x17;
+pub const GENERALIZED_TIME: u8 = 0x18;
+pub const BMP_STRING: u8 = 0x1E;
+pub const SEQUENCE: u8 = 0x30;
+pub const SET: u8 = 0x31;
+
+/// One decoded element.
+#[derive(Debug, Clone, Copy)]
+pub struct Tlv<'a> {
+    pub tag: u8,
+    pub content: &'a [u8],
+    /// The whole element, header included.
+    pub raw: &'a [u8],
+}
+
+impl<'a> Tlv<'a> {
+    /// Reads the element at the start of `data`; returns it and what follows.
+    pub fn parse(data: &'a [u8]) -> Result<(Self, &'a [u8])> {
+        let truncated = || anyhow::anyhow!("Truncated DER data");
+        let tag = *data.first().ok_or_else(truncated)?;
+        if tag & 0x1F == 0x1F {
+            return Err(anyhow::anyhow!("Multi-byte DER tags are not supported"));
+        }
+        let first = *data.get(1).ok_or_else(truncated)?;
+        let (length, header) = if first < 0x80 {
+            (first as usize, 2)
+        } else {
+            let count = (first & 0x7F) as usize;
+            if count == 0 || count > 4 {
+                return Err(anyhow::anyhow!("Unsupported DER length"));
+            }
+            let bytes = data.get(2..2 + count).ok_or_else(truncated)?;
+            (bytes.iter().fold(0usize, |acc, b| (acc << 8) | *b as usize), 2 + count)
+        };
+        let end = header.checked_add(length).filter(|end| *end <= data.len()).ok_or_else(truncated)?;
+        Ok((Self { tag, content: &data[header..end], raw: &data[..end] }, &data[end..]))
+    }
+
+    /// The elements inside a constructed element.
+    pub fn children(&self) -> Result<Vec<Tlv<'a>>> {
+        let mut rest = self.content;
+        let mut children = Vec::new();
+        while !rest.is_empty() {
+            let (child, next) = Tlv::parse(rest)?;
+            children.push(child);
+            rest = next;
+        }
+        Ok(children)
+    }
+
+    pub fn expect(self, tag: u8) -> Result<Self> {
+        if self.tag != tag {
+            return Err(anyhow::anyhow!("Expected DER tag {:#04x}, found {:#04x}", tag, self.tag));
+        }
+        Ok(self)
+    }
+
+    /// Text of a string element, for the string types found in names.
+    pub fn text(&self) -> Option<String> {
+        match self.tag {
+            UTF8_STRING | PRINTABLE_STRING | IA5_STRING => String::from_utf8(self.content.to_vec()).ok(),
+            // Latin-1 in practice
+            T61_STRING => Some(self.content.iter().map(|b| *b as char).collect()),
+            BMP_STRING => {
+                let units: Vec<u16> = self.content.chunks_exact(2).map(|c| u16::from_be_bytes([c[0], c[1]])).collect();
+                String::from_utf16(&units).ok()
+            }
+            _ => None,
+        }
+    }
+
+    /// Dotted form of an OID element.
+    pub fn oid(&self) -> Option<String> {
+        if self.tag != OID || self.content.is_empty() {
+            return None;
+        }
+        let mut arcs = Vec::new();
+        let mut value = 0u64;
+        for byte in self.content {
+            value = (value << 7) | u64::from(byte & 0x7F);
+            if byte & 0x80 == 0 {
+                arcs.push(value);
+                value = 0;
+            }
+        }
+        let first = arcs.first().copied()?;
+        let (a, b) = if first < 80 { (first / 40, first % 40) } else { (2, first - 80) };
+        let mut dotted = format!("{}.{}", a, b);
+        for arc in &arcs[1..] {
+            dotted.push_str(&format!(".{}", arc));
+        }
+        Some(dotted)
+    }
+}
+
+pub fn tlv(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut out = vec![tag];
+    let length = content.len();
+    if length < 0x80 {
+        out.push(length as u8);
+    } else {
+        let bytes: Vec<u8> = length.to_be_bytes().into_iter().skip_while(|b| *b == 0).collect();
+        out.push(0x80 | bytes.len() as u8);
+        out.extend(bytes);
+    }
+    out.extend_from_slice(content);
+    out
+}
+
+pub fn sequence(items: &[&[u8]]) -> Vec<u8> {
+    tlv(SEQUENCE, &items.concat())
+}
+
+/// `SET OF`, with the elements in the sorted order DER requires.
+pub fn set(items: &[&[u8]]) -> Vec<u8> {
+    let mut sorted = items.to_vec();
+    sorted.sort();
+    tlv(SET, &sorted.concat())
+}
+
+/// Context-specific constructed element `[n]`.
+pub fn context(n: u8, content: &[u8]) -> Vec<u8> {
+    tlv(0xA0 | n, content)
+}
+
+pub fn oid(dotted: &str) -> Vec<u8> {
+    let arcs: Vec<u64> = dotted.split('.').map(|arc| arc.parse().unwrap_or(0)).collect();
+    let mut content = Vec::new();
+    let first = arcs.first().copied().unwrap_or(0) * 40 + arcs.get(1).copied().unwrap_or(0);
+    for arc in std::iter::once(first).chain(arcs.iter().skip(2).copied()) {
+        let mut bytes = vec![(arc & 0x7F) as u8];
+        let mut rest = arc >> 7;
+        while rest > 0 {
+            bytes.push(0x80 | (rest & 0x7F) as u8);
+            rest >>= 7;
+        }
+        bytes.reverse();
+        content.extend(bytes);
+    }
+    tlv(OID, &content)
+}
+
+pub fn integer(value: u64) -> Vec<u8> {
+    let mut bytes: Vec<u8> = value.to_be_bytes().into_iter().skip_while(|b| *b == 0).collect();
+    // Keep the value positive
+    if bytes.first().map(|b| b & 0x80 != 0).unwrap_or(true) {
+        bytes.insert(0, 0);
+    }
+    tlv(INTEGER, &bytes)
+}
+
+pub fn octet_string(content: &[u8]) -> Vec<u8> {
+    tlv(OCTET_STRING, content)
+}
+
+pub fn null() -> Vec<u8> {
+    vec![NULL, 0]
+}
+
+pub fn boolean(value: bool) -> Vec<u8> {
+    vec![0x01, 1, if value { 0xFF } else { 0 }]
+}
+
+/// `AlgorithmIdentifier` without parameters, or with NULL parameters.
+pub fn algorithm(dotted: &str, null_params: bool) -> Vec<u8> {
+    if null_params {
+        sequence(&[&oid(dotted), &null()])
+    } else {
+        sequence(&[&oid(dotted)])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_and_long_form_lengths() {
+        assert_eq!(tlv(OCTET_STRING, &[0xAB; 127])[..2], [OCTET_STRING, 127]);
+        assert_eq!(tlv(OCTET_STRING, &[0xAB; 128])[..3], [OCTET_STRING, 0x81, 128]);
+        assert_eq!(tlv(OCTET_STRING, &[0xAB; 255])[..3], [OCTET_STRING, 0x81, 255]);
+        assert_eq!(tlv(OCTET_STRING, &[0xAB; 256])[..4], [OCTET_STRING, 0x82, 0x01, 0x00]);
+        assert_eq!(tlv(OCTET_STRING, &[0xAB; 70_000])[..5], [OCTET_STRING, 0x83, 0x01, 0x11, 0x70]);
+    }
+
+    #[test]
+    fn parses_what_it_writes() {
+        for length in [0, 1, 127, 128, 300, 70_000] {
+            let content = vec![0x5A; length];
+            let mut encoded = octet_string(&content);
+            encoded.extend(null());
+
+            let (element, rest) = Tlv::parse(&encoded).unwrap();
+            assert_eq!(element.tag, OCTET_STRING);
+            assert_eq!(element.content, &content[..]);
+            assert_eq!(element.raw.len(), encoded.len() - 2);
+            assert_eq!(rest, &null()[..]);
+        }
+    }
+
+    #[test]
+    fn rejects_truncated_and_unsupported_elements() {
+        let encoded = octet_string(&[1; 300]);
+        assert!(Tlv::parse(&encoded[..encoded.len() - 1]).is_err());
+        assert!(Tlv::parse(&encoded[..3]).is_err());
+        assert!(Tlv::parse(&[OCTET_STRING]).is_err());
+        // Indefinite length, and a length of five bytes
+        assert!(Tlv::parse(&[SEQUENCE, 0x80, 0, 0]).is_err());
+        assert!(Tlv::parse(&[OCTET_STRING, 0x85, 0, 0, 0, 0, 1, 0]).is_err());
+        assert!(Tlv::parse(&[0x1F, 0x81, 0x01, 0]).is_err());
+    }
+
+    #[test]
+    fn set_sorts_its_elements() {
+        let a = integer(1);
+        let b = integer(2);
+        let long = octet_string(&[0; 200]);
+        let encoded = set(&[&long, &b, &a]);
+        assert_eq!(encoded[..3], [SET, 0x81, (a.len() + b.len() + long.len()) as u8]);
+
+        let (element, _) = Tlv::parse(&encoded).unwrap();
+        let children: Vec<&[u8]> = element.children().unwrap().iter().map(|c| c.raw).collect();
+        assert_eq!(children, [&a[..], &b[..], &long[..]]);
+        // A sequence keeps the given order
+        let encoded = sequence(&[&b, &a]);
+        let (element, _) = Tlv::parse(&encoded).unwrap();
+        let children: Vec<&[u8]> = element.children().unwrap().iter().map(|c| c.raw).collect();
+        assert_eq!(children, [&b[..], &a[..]]);
+    }
+
+    #[test]
+    fn integers_stay_positive() {
+        assert_eq!(integer(0), [INTEGER, 1, 0]);
+        assert_eq!(integer(0x7F), [INTEGER, 1, 0x7F]);
+        assert_eq!(integer(0x80), [INTEGER, 2, 0, 0x80]);
+        assert_eq!(integer(0x0100), [INTEGER, 2, 1, 0]);
+        assert_eq!(integer(u64::MAX)[..3], [INTEGER, 9, 0]);
+    }
+
+    #[test]
+    fn oids_round_trip() {
+        let encoded = oid("1.2.840.113549.1.7.2");
+        assert_eq!(encoded, [OID, 9, 0x2A, 0x86, 0x48, 0x86, 0xF7, 0x0D, 0x01, 0x07, 0x02]);
+        for dotted in ["1.2.840.113549.1.7.2", "2.16.840.1.101.3.4.2.1", "2.5.29.31", "1.3.6.1.5.5.7.3.8"] {
+            let encoded = oid(dotted);
+            let (element, _) = Tlv::parse(&encoded).unwrap();
+            assert_eq!(element.oid().as_deref(), Some(dotted));
+        }
+    }
+
+    #[test]
+    fn reads_name_strings() {
+        let text = |tag, content: &[u8]| {
+            let encoded = tlv(tag, content);
+            Tlv::parse(&encoded).unwrap().0.text()
+        };
+        assert_eq!(text(UTF8_STRING, "São Paulo".as_bytes()).as_deref(), Some("São Paulo"));
+        assert_eq!(text(T61_STRING, b"S\xE3o").as_deref(), Some("São"));
+        assert_eq!(text(BMP_STRING, &[0, b'S', 0, 0xE3, 0, b'o']).as_deref(), Some("São"));
+        assert_eq!(text(OCTET_STRING, b"raw"), None);
+    }
+}
@@ -0,0 +1,123 @@
+use anyhow::Result;
+use chrono::{DateTime, FixedOffset};
+use lopdf::{Document, Object, StringFormat};
+use sha2::{Digest, Sha256};
+use crate::infra::pdf_objects::{info_dict_mut, metadata_id, read_xmp, save, write_xmp};
+use crate::infra::xmp::replace_property;
+
+const PRODUCER: &str = "PDFSynth";
+const UUID_PLACEHOLDER: &str = "uuid:00000000-0000-0000-0000-000000000000";
+
+/// Script installed before any page script runs so `Date` reports a fixed
+/// instant. `{{EPOCH_MS}}` is replaced by milliseconds since the Unix epoch.
+pub const FROZEN_CLOCK_SCRIPT: &str = r#"
+    (() => {
+        const fixed = {{EPOCH_MS}};
+        const RealDate = Date;
+        function FrozenDate(...args) {
+            if (!new.target) {
+                return new RealDate(fixed).toString();
+            }
+            return args.length === 0 ? new RealDate(fixed) : new RealDate(...args);
+        }
+        FrozenDate.prototype = RealDate.prototype;
+        FrozenDate.now = () => fixed;
+        FrozenDate.parse = RealDate.parse;
+        FrozenDate.UTC = RealDate.UTC;
+        Date = FrozenDate;
+    })();
+"#;
+
+/// Removes every source of run-to-run variation that Chrome and Ghostscript
+/// write into a PDF: dates, producer strings, XMP UUIDs and the trailer `/ID`.
+///
+/// The `/ID` and UUIDs are derived from a hash of the normalized document, so
+/// identical input gives identical bytes.
+pub struct Deterministic;
+
+impl Deterministic {
+    pub fn normalize(pdf_data: &[u8], timestamp: DateTime<FixedOffset>) -> Result<Vec<u8>> {
+        let mut doc = Document::load_mem(pdf_data)
+            .map_err(|e| anyhow::anyhow!("Failed to parse PDF: {}", e))?;
+
+        // Only rewrite entries that exist, so DocInfo keeps matching the XMP
+        let pdf_date = Self::pdf_date(timestamp);
+        let info = info_dict_mut(&mut doc);
+        for key in ["CreationDate", "ModDate"] {
+            if info.has(key.as_bytes()) {
+                info.set(key, Object::string_literal(pdf_date.clone()));
+            }
+        }
+        info.set("Producer", Object::string_literal(PRODUCER));
+
+        let metadata_id = metadata_id(&doc);
+        if let Some(id) = metadata_id {
+            let xmp = read_xmp(&doc, id)?;
+            let xmp = Self::normalize_xmp(&xmp, &timestamp.to_rfc3339());
+            write_xmp(&mut doc, id, xmp)?;
+        }
+
+        // First pass: hash the document without its identifiers
+        doc.trailer.remove(b"ID");
+        let digest = Sha256::digest(save(&mut doc)?);
+
+        if let Some(id) = metadata_id {
+            let xmp = read_xmp(&doc, id)?.replace(UUID_PLACEHOLDER, &Self::uuid(&digest));
+            write_xmp(&mut doc, id, xmp)?;
+        }
+        let file_id = Object::String(digest[..16].to_vec(), StringFormat::Hexadecimal);
+        doc.trailer.set("ID", vec![file_id.clone(), file_id]);
+
+        save(&mut doc)
+    }
+
+    /// Parses an RFC 3339 timestamp, defaulting to the Unix epoch.
+    pub fn timestamp(value: Option<&str>) -> Result<DateTime<FixedOffset>> {
+        let value = value.unwrap_or("1970-01-01T00:00:00Z");
+        DateTime::parse_from_rfc3339(value)
+            .map_err(|e| anyhow::anyhow!("Invalid timestamp '{}': {}", value, e))
+    }
+
+    /// Formats `D:YYYYMMDDHHmmSS+HH'mm'`.
+    pub fn pdf_date(timestamp: DateTime<FixedOffset>) -> String {
+        let offset = timestamp.offset().local_minus_utc();
+        let sign = if offset < 0 { '-' } else { '+' };
+        let offset = offset.abs();
+        format!(
+            "D:{}{}{:02}'{:02}'",
+            timestamp.format("%Y%m%d%H%M%S"),
+            sign,
+            offset / 3600,
+            (offset % 3600) / 60
+        )
+    }
+
+    fn normalize_xmp(xmp: &str, date: &str) -> String {
+        let mut xmp = xmp.to_string();
+        for name in ["xmp:CreateDate", "xmp:ModifyDate", "xmp:MetadataDate"] {
+            xmp = replace_property(&xmp, name, date);
+        }
+        xmp = replace_property(&xmp, "pdf:Producer", PRODUCER);
+
+        // Ghostscript writes fresh UUIDs for rdf:about, DocumentID and InstanceID
+        let mut normalized = String::with_capacity(xmp.len());
+        let mut rest = xmp.as_str();
+        while let Some(pos) = rest.find("uuid:") {
+            normalized.push_str(&rest[..pos]);
+            let candidate = &rest[pos + "uuid:".len()..];
+            let len = candidate
+                .bytes()
+                .take_while(|b| b.is_ascii_hexdigit() || *b == b'-')
+                .count();
+            normalized.push_str(UUID_PLACEHOLDER);
+            rest = &candidate[len..];
+        }
+        normalized.push_str(rest);
+        normalized
+    }
+
+    fn uuid(digest: &[u8]) -> String {
+        let hex: String = digest[16..32].iter().map(|b| format!("{:02x}", b)).collect();
+        format!("uuid:{}-{}-{}-{}-{}", &hex[0..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..32])
+    }
+}
@@ -0,0 +1,194 @@
+use headless_chrome::protocol::cdp::types::Event;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use crate::infra::font_coverage::FontFallback;
+
+/// Longest URL kept in a report; data URIs of embedded images can be megabytes.
+const MAX_URL_LENGTH: usize = 256;
+
+/// What the page reported while it was loading.
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct PageDiagnostics {
+    pub console: Vec<ConsoleMessage>,
+    pub exceptions: Vec<JsException>,
+    pub failed_requests: Vec<FailedRequest>,
+    /// Text drawn with a font outside its `font-family` list.
+    pub font_fallbacks: Vec<FontFallback>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ConsoleMessage {
+    /// `log`, `warning`, `error`, `debug`, ...
+    pub level: String,
+    pub text: String,
+    pub url: Option<String>,
+    pub line: Option<u64>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct JsException {
+    pub message: String,
+    pub url: Option<String>,
+    pub line: Option<u64>,
+    pub column: Option<u64>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct FailedRequest {
+    pub url: String,
+    pub error: String,
+    pub resource_type: Option<String>,
+    pub blocked_reason: Option<String>,
+    pub canceled: bool,
+}
+
+/// Collects `Runtime.consoleAPICalled`, `Runtime.exceptionThrown` and
+/// `Network.loadingFailed` events from a tab.
+///
+/// Event params are read through their JSON form so the report follows the
+/// CDP field names rather than the generated Rust types.
+#[derive(Clone, Default)]
+pub struct DiagnosticsCollector {
+    report: Arc<Mutex<PageDiagnostics>>,
+    /// `Network.loadingFailed` only carries the request id.
+    request_urls: Arc<Mutex<HashMap<String, String>>>,
+}
+
+impl DiagnosticsCollector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn handle(&self, event: &Event) {
+        match event {
+            Event::RuntimeConsoleAPICalled(e) => self.on_console(Self::params(&e.params)),
+            Event::RuntimeExceptionThrown(e) => self.on_exception(Self::params(&e.params)),
+            Event::NetworkRequestWillBeSent(e) => self.on_request(Self::params(&e.params)),
+            Event::NetworkLoadingFailed(e) => self.on_loading_failed(Self::params(&e.params)),
+            _ => {}
+        }
+    }
+
+    pub fn finish(&self) -> PageDiagnostics {
+        self.report
+            .lock()
+            .map(|report| report.clone())
+            .unwrap_or_default()
+    }
+
+    fn params<T: Serialize>(params: &T) -> Value {
+        serde_json::to_value(params).unwrap_or(Value::Null)
+    }
+
+    fn on_console(&self, params: Value) {
+        let text = params["args"]
+            .as_array()
+            .map(|args| args.iter().map(Self::describe_remote_object).collect::<Vec<_>>().join(" "))
+            .unwrap_or_default();
+        let frame = &params["stackTrace"]["callFrames"][0];
+
+        let message = ConsoleMessage {
+            level: params["type"].as_str().unwrap_or("log").to_string(),
+            text,
+            url: frame["url"].as_str().filter(|u| !u.is_empty()).map(Self::truncate_url),
+            // CDP line numbers are zero-based
+            line: frame["lineNumber"].as_u64().map(|l| l + 1),
+        };
+
+        tracing::debug!(
+            event = "browser_console_message",
+            level = %message.level,
+            text = %message.text,
+            "Console message captured"
+        );
+
+        if let Ok(mut report) = self.report.lock() {
+            report.console.push(message);
+        }
+    }
+
+    fn on_exception(&self, params: Value) {
+        let details = &params["exceptionDetails"];
+        let message = details["exception"]["description"]
+            .as_str()
+            .or_else(|| details["text"].as_str())
+            .unwrap_or("Uncaught exception")
+            .to_string();
+
+        let exception = JsException {
+            message,
+            url: details["url"].as_str().filter(|u| !u.is_empty()).map(Self::truncate_url),
+            line: details["lineNumber"].as_u64().map(|l| l + 1),
+            column: details["columnNumber"].as_u64().map(|c| c + 1),
+        };
+
+        tracing::debug!(
+            event = "browser_exception_thrown",
+            message = %exception.message,
+            "JavaScript exception captured"
+        );
+
+        if let Ok(mut report) = self.report.lock() {
+            report.exceptions.push(exception);
+        }
+    }
+
+    fn on_request(&self, params: Value) {
+        if let (Some(id), Some(url)) = (params["requestId"].as_str(), params["request"]["url"].as_str()) {
+            if let Ok(mut urls) = self.request_urls.lock() {
+                urls.insert(id.to_string(), Self::truncate_url(url));
+            }
+        }
+    }
+
+    fn on_loading_failed(&self, params: Value) {
+        let url = params["requestId"]
+            .as_str()
+            .and_then(|id| self.request_urls.lock().ok()?.get(id).cloned())
+            .unwrap_or_default();
+
+        let failed = FailedRequest {
+            url,
+            error: params["errorText"].as_str().unwrap_or_default().to_string(),
+            resource_type: params["type"].as_str().map(str::to_string),
+            blocked_reason: params["blockedReason"].as_str().map(str::to_string),
+            canceled: params["canceled"].as_bool().unwrap_or(false),
+        };
+
+        tracing::debug!(
+            event = "browser_request_failed",
+            url = %failed.url,
+            error = %failed.error,
+            "Network request failed"
+        );
+
+        if let Ok(mut report) = self.report.lock() {
+            report.failed_requests.push(failed);
+        }
+    }
+
+    fn describe_remote_object(object: &Value) -> String {
+        match &object["value"] {
+            Value::String(s) => s.clone(),
+            Value::Null => object["description"]
+                .as_str()
+                .or_else(|| object["type"].as_str())
+                .unwrap_or_default()
+                .to_string(),
+            value => value.to_string(),
+        }
+    }
+
+    fn truncate_url(url: &str) -> String {
+        if url.len() <= MAX_URL_LENGTH {
+            return url.to_string();
+        }
+        let mut end = MAX_URL_LENGTH;
+        while !url.is_char_boundary(end) {
+            end -= 1;
+        }
+        format!("{}...", &url[..end])
+    }
+}
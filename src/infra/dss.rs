@@ -0,0 +1,146 @@
+//! PAdES-B-LTA: the validation material of a signed file and a document
+//! timestamp over it, appended as an incremental update so the signature
+//! already in the file stays valid.
+
+use anyhow::Result;
+use lopdf::{dictionary, text_string, Document, IncrementalDocument, Object, Stream};
+use std::io::Read;
+use std::time::{Duration, Instant};
+use crate::infra::cms::Certificate;
+use crate::infra::signing::{Placeholder, Signer};
+use crate::infra::tsa::{TsaClient, DEFAULT_TOKEN_RESERVE};
+
+/// Largest CRL downloaded.
+const MAX_CRL_BYTES: u64 = 10 * 1024 * 1024;
+
+pub struct LongTermValidation<'a> {
+    tsa: &'a TsaClient,
+}
+
+impl<'a> LongTermValidation<'a> {
+    pub fn new(tsa: &'a TsaClient) -> Self {
+        Self { tsa }
+    }
+
+    /// Appends a `/DSS` with `certificates` and the CRLs they point to,
+    /// then a `/DocTimeStamp` signature covering the whole file.
+    pub async fn apply(&self, pdf: Vec<u8>, certificates: Vec<Certificate>) -> Result<Vec<u8>> {
+        let start = Instant::now();
+        let mut unique: Vec<Certificate> = Vec::new();
+        for certificate in certificates {
+            if !unique.iter().any(|c| c.same_as(&certificate)) {
+                unique.push(certificate);
+            }
+        }
+        let mut urls: Vec<String> = Vec::new();
+        for url in unique.iter().flat_map(|c| c.crl_urls.iter()) {
+            if !urls.contains(url) {
+                urls.push(url.clone());
+            }
+        }
+        let crls = fetch_crls(urls, self.tsa.timeout()).await?;
+
+        let previous = Document::load_mem(&pdf)
+            .map_err(|e| anyhow::anyhow!("Failed to reload the signed PDF: {}", e))?;
+        let previous_len = pdf.len();
+        let version = previous.version.clone();
+        let page_id = previous
+            .page_iter()
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("The signed PDF has no pages"))?;
+        let mut update = IncrementalDocument::create_from(pdf, previous);
+        // lopdf would otherwise mark the update as 1.4
+        update.new_document.version = version;
+        // The timestamp's widget goes on the first page
+        Signer::clone_for_field(&mut update, page_id)?;
+
+        let doc = &mut update.new_document;
+        let certs: Vec<Object> = unique
+            .iter()
+            .map(|c| doc.add_object(Stream::new(dictionary! {}, c.der.clone())).into())
+            .collect();
+        let crl_count = crls.len();
+        let crls: Vec<Object> = crls
+            .into_iter()
+            .map(|crl| doc.add_object(Stream::new(dictionary! {}, crl)).into())
+            .collect();
+        let dss_id = doc.add_object(dictionary! { "Certs" => certs, "CRLs" => crls });
+        doc.catalog_mut()?.set("DSS", dss_id);
+
+        let timestamp_id = doc.add_object(dictionary! {
+            "Type" => "DocTimeStamp",
+            "Filter" => "Adobe.PPKLite",
+            "SubFilter" => "ETSI.RFC3161",
+            "ByteRange" => Placeholder::byte_range(),
+            "Contents" => Placeholder::contents(DEFAULT_TOKEN_RESERVE),
+        });
+        let form_id = doc.add_object(Stream::new(
+            dictionary! {
+                "Type" => "XObject",
+                "Subtype" => "Form",
+                "BBox" => vec![0.into(), 0.into(), 0.into(), 0.into()],
+                "Resources" => dictionary! {},
+            },
+            Vec::new(),
+        ));
+        let widget_id = doc.add_object(dictionary! {
+            "Type" => "Annot",
+            "Subtype" => "Widget",
+            "FT" => "Sig",
+            "T" => text_string(&format!("Signature{}", Signer::field_count(doc) + 1)),
+            "V" => timestamp_id,
+            // Print and Locked
+            "F" => 132,
+            "P" => page_id,
+            "Rect" => vec![0.into(), 0.into(), 0.into(), 0.into()],
+            "AP" => dictionary! { "N" => form_id },
+        });
+        Signer::append_annotation(doc, page_id, widget_id)?;
+        Signer::add_field(doc, widget_id)?;
+
+        let mut output = Vec::new();
+        update
+            .save_to(&mut output)
+            .map_err(|e| anyhow::anyhow!("Failed to write the incremental update: {}", e))?;
+        let placeholder = Placeholder::locate(&mut output, previous_len, DEFAULT_TOKEN_RESERVE)?;
+        let token = self.tsa.timestamp(&placeholder.digest(&output)).await?;
+        placeholder.fill(&mut output, &token.der)?;
+
+        tracing::debug!(
+            event = "document_timestamped",
+            certificates = unique.len(),
+            crls = crl_count,
+            time = %token.time,
+            duration_ms = start.elapsed().as_millis() as u64,
+            "Validation data and document timestamp added"
+        );
+        Ok(output)
+    }
+}
+
+/// Downloads each CRL; validation data with gaps would not give long-term
+/// proof, so any failure is an error.
+async fn fetch_crls(urls: Vec<String>, timeout: Duration) -> Result<Vec<Vec<u8>>> {
+    if urls.is_empty() {
+        return Ok(Vec::new());
+    }
+    tokio::task::spawn_blocking(move || {
+        urls.iter()
+            .map(|url| -> Result<Vec<u8>> {
+                let response = ureq::get(url)
+                    .timeout(timeout)
+                    .call()
+                    .map_err(|e| anyhow::anyhow!("Failed to download the CRL at {}: {}", url, e))?;
+                let mut body = Vec::new();
+                response
+                    .into_reader()
+                    .take(MAX_CRL_BYTES)
+                    .read_to_end(&mut body)
+                    .map_err(|e| anyhow::anyhow!("Failed to read the CRL at {}: {}", url, e))?;
+                Ok(body)
+            })
+            .collect()
+    })
+    .await
+    .map_err(|e| anyhow::anyhow!("CRL download task failed: {}", e))?
+}
@@ -0,0 +1,265 @@
+use anyhow::Result;
+use lopdf::{Dictionary, Document, Object};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Longest text sample kept for a fallback run.
+const MAX_SAMPLE_CHARS: usize = 80;
+
+/// How a render treats text that its fonts cannot cover.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum FontCheck {
+    #[default]
+    Off,
+    /// Report fonts, fallbacks and missing glyphs in response headers.
+    Report,
+    /// Like `report`, and fail the render on any fallback or missing glyph.
+    Strict,
+}
+
+/// Which fonts a render used and where its text was not covered.
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct FontReport {
+    /// Fonts in the output PDF.
+    pub fonts: Vec<UsedFont>,
+    /// Text drawn with a font that is not in its `font-family` list.
+    pub fallbacks: Vec<FontFallback>,
+    /// Glyphs drawn as `.notdef` (tofu), per font.
+    pub missing_glyphs: Vec<MissingGlyphs>,
+}
+
+impl FontReport {
+    pub fn is_clean(&self) -> bool {
+        self.fallbacks.is_empty() && self.missing_glyphs.is_empty()
+    }
+
+    pub fn missing_glyph_count(&self) -> u64 {
+        self.missing_glyphs.iter().map(|m| m.count).sum()
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct UsedFont {
+    /// `BaseFont` without the subset tag.
+    pub name: String,
+    pub embedded: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FontFallback {
+    /// The computed `font-family` of the text.
+    pub requested: String,
+    /// The platform font Chrome actually used.
+    pub used: String,
+    pub glyph_count: u64,
+    /// First text run that fell back.
+    pub sample: String,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct MissingGlyphs {
+    pub font: String,
+    pub count: u64,
+}
+
+/// An element with its own text, as listed by [`FontCoverage::PROBE_SCRIPT`].
+#[derive(Deserialize, Debug, Clone)]
+pub struct TextProbe {
+    pub family: String,
+    pub text: String,
+}
+
+/// A platform font Chrome reported for one probed element.
+#[derive(Debug, Clone)]
+pub struct PlatformFont {
+    pub family: String,
+    pub glyph_count: u64,
+}
+
+pub struct FontCoverage;
+
+impl FontCoverage {
+    /// Marks every element that directly contains text with
+    /// `data-pdfsynth-font-probe` and returns their computed font families and
+    /// text, in document order.
+    pub const PROBE_SCRIPT: &'static str = r#"
+        (() => {
+            const probes = [];
+            const walker = document.createTreeWalker(document.body || document.documentElement, NodeFilter.SHOW_ELEMENT);
+            for (let el = walker.currentNode; el; el = walker.nextNode()) {
+                const text = Array.from(el.childNodes)
+                    .filter(n => n.nodeType === Node.TEXT_NODE)
+                    .map(n => n.textContent)
+                    .join('')
+                    .trim();
+                if (!text || el.getClientRects().length === 0) continue;
+                if (probes.length >= {{LIMIT}}) break;
+                el.setAttribute('data-pdfsynth-font-probe', '');
+                probes.push({ family: getComputedStyle(el).fontFamily, text });
+            }
+            return JSON.stringify(probes);
+        })()
+    "#;
+
+    pub const PROBE_SELECTOR: &'static str = "[data-pdfsynth-font-probe]";
+
+    pub const CLEANUP_SCRIPT: &'static str = r#"
+        document.querySelectorAll('[data-pdfsynth-font-probe]')
+            .forEach(el => el.removeAttribute('data-pdfsynth-font-probe'))
+    "#;
+
+    /// Elements probed at most, to bound the CDP round trips on long documents.
+    pub const PROBE_LIMIT: usize = 2000;
+
+    /// Platform fonts that are not named in the element's `font-family`.
+    /// Lists made only of generic families (`serif`, `sans-serif`, ...) accept
+    /// whatever fontconfig picks.
+    pub fn fallbacks(probes: &[TextProbe], platform_fonts: &[Vec<PlatformFont>]) -> Vec<FontFallback> {
+        let mut fallbacks: BTreeMap<(String, String), FontFallback> = BTreeMap::new();
+
+        for (probe, fonts) in probes.iter().zip(platform_fonts) {
+            let requested = Self::named_families(&probe.family);
+            if requested.is_empty() {
+                continue;
+            }
+            for font in fonts {
+                if requested.iter().any(|r| r.eq_ignore_ascii_case(&font.family)) {
+                    continue;
+                }
+                fallbacks
+                    .entry((probe.family.clone(), font.family.clone()))
+                    .and_modify(|f| f.glyph_count += font.glyph_count)
+                    .or_insert_with(|| FontFallback {
+                        requested: probe.family.clone(),
+                        used: font.family.clone(),
+                        glyph_count: font.glyph_count,
+                        sample: probe.text.chars().take(MAX_SAMPLE_CHARS).collect(),
+                    });
+            }
+        }
+
+        fallbacks.into_values().collect()
+    }
+
+    /// Lists the fonts in the PDF and counts `.notdef` glyphs drawn with
+    /// `Identity-H` fonts, where glyph id 0 is the tofu box.
+    pub fn scan_pdf(pdf_data: &[u8]) -> Result<(Vec<UsedFont>, Vec<MissingGlyphs>)> {
+        let doc = Document::load_mem(pdf_data)
+            .map_err(|e| anyhow::anyhow!("Failed to parse PDF: {}", e))?;
+
+        let mut used: BTreeMap<String, bool> = BTreeMap::new();
+        let mut missing: BTreeMap<String, u64> = BTreeMap::new();
+
+        for page_id in doc.get_pages().into_values() {
+            let fonts = doc.get_page_fonts(page_id).unwrap_or_default();
+            for font in fonts.values() {
+                let name = Self::font_name(font);
+                let embedded = Self::is_embedded(&doc, font);
+                used.entry(name).and_modify(|e| *e |= embedded).or_insert(embedded);
+            }
+
+            let Ok(content) = doc.get_and_decode_page_content(page_id) else { continue };
+            let mut current: Option<&Dictionary> = None;
+            for op in &content.operations {
+                match op.operator.as_str() {
+                    "Tf" => {
+                        current = op
+                            .operands
+                            .first()
+                            .and_then(|o| o.as_name().ok())
+                            .and_then(|name| fonts.get(name).copied());
+                    }
+                    "Tj" | "TJ" | "'" | "\"" => {
+                        let Some(font) = current.filter(|f| Self::is_identity_h(f)) else { continue };
+                        let count = Self::count_notdef(op.operator.as_str(), &op.operands);
+                        if count > 0 {
+                            *missing.entry(Self::font_name(font)).or_default() += count;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let used = used
+            .into_iter()
+            .map(|(name, embedded)| UsedFont { name, embedded })
+            .collect();
+        let missing = missing
+            .into_iter()
+            .map(|(font, count)| MissingGlyphs { font, count })
+            .collect();
+        Ok((used, missing))
+    }
+
+    fn named_families(font_family: &str) -> Vec<String> {
+        const GENERIC: [&str; 10] = [
+            "serif", "sans-serif", "monospace", "cursive", "fantasy",
+            "system-ui", "emoji", "math", "fangsong", "ui-monospace",
+        ];
+        font_family
+            .split(',')
+            .map(|f| f.trim().trim_matches(|c| c == '"' || c == '\'').to_string())
+            .filter(|f| !f.is_empty() && !GENERIC.iter().any(|g| g.eq_ignore_ascii_case(f)))
+            .collect()
+    }
+
+    fn count_notdef(operator: &str, operands: &[Object]) -> u64 {
+        let strings: Vec<&[u8]> = match operator {
+            "TJ" => operands
+                .first()
+                .and_then(|o| o.as_array().ok())
+                .map(|items| items.iter().filter_map(|i| i.as_str().ok()).collect())
+                .unwrap_or_default(),
+            _ => operands.last().and_then(|o| o.as_str().ok()).into_iter().collect(),
+        };
+        strings
+            .iter()
+            .flat_map(|s| s.chunks_exact(2))
+            .filter(|code| code == &[0, 0])
+            .count() as u64
+    }
+
+    fn font_name(font: &Dictionary) -> String {
+        let name = font
+            .get(b"BaseFont")
+            .and_then(Object::as_name)
+            .map(|n| String::from_utf8_lossy(n).into_owned())
+            .unwrap_or_else(|_| "(unnamed)".to_string());
+        // Subset fonts are named `ABCDEF+Family`
+        match name.split_once('+') {
+            Some((tag, rest)) if tag.len() == 6 && tag.bytes().all(|b| b.is_ascii_uppercase()) => rest.to_string(),
+            _ => name,
+        }
+    }
+
+    fn is_identity_h(font: &Dictionary) -> bool {
+        matches!(font.get(b"Subtype").and_then(Object::as_name), Ok(b"Type0"))
+            && matches!(font.get(b"Encoding").and_then(Object::as_name), Ok(b"Identity-H"))
+    }
+
+    fn is_embedded(doc: &Document, font: &Dictionary) -> bool {
+        let resolve = |object: &Object| doc.dereference(object).ok().map(|(_, o)| o.clone());
+
+        // Type0 fonts carry the descriptor on their descendant CIDFont
+        let descendant = font
+            .get(b"DescendantFonts")
+            .ok()
+            .and_then(resolve)
+            .and_then(|o| o.as_array().ok().and_then(|a| a.first().cloned()))
+            .and_then(|o| resolve(&o));
+        let font = match &descendant {
+            Some(Object::Dictionary(d)) => d,
+            _ => font,
+        };
+
+        font.get(b"FontDescriptor")
+            .ok()
+            .and_then(resolve)
+            .and_then(|d| d.as_dict().ok().cloned())
+            .map(|d| d.has(b"FontFile") || d.has(b"FontFile2") || d.has(b"FontFile3"))
+            // Type3 fonts define their glyphs inline
+            .unwrap_or(matches!(font.get(b"Subtype").and_then(Object::as_name), Ok(b"Type3")))
+    }
+}
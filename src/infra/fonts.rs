@@ -0,0 +1,555 @@
+use anyhow::Context;
+use base64::Engine as _;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Arc;
+use std::time::Instant;
+use thiserror::Error;
+
+/// Fields printed by `fc-list` / `fc-scan`, one face per line.
+const FC_FORMAT: &str = "%{family[0]}\\t%{style[0]}\\t%{weight}\\t%{slant}\\t%{file}\\n";
+
+#[derive(Error, Debug)]
+pub enum FontError {
+    /// The upload or tenant id is not acceptable; reported as a client error.
+    #[error("{0}")]
+    Invalid(String),
+    /// The caller may not act for the tenant, or write to the shared directory.
+    #[error("{0}")]
+    Forbidden(String),
+    #[error(transparent)]
+    Internal(#[from] anyhow::Error),
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum FontSource {
+    /// Installed with the image (`fonts-liberation`, ...).
+    System,
+    /// Uploaded to the shared fonts directory; visible to every render.
+    Shared,
+    /// Uploaded by the requesting tenant; only used by its own renders.
+    Tenant,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct FontFace {
+    pub family: String,
+    pub style: String,
+    /// CSS `font-weight` (100–900).
+    pub weight: u16,
+    pub italic: bool,
+    pub file: String,
+    pub source: FontSource,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum FontFormat {
+    TrueType,
+    OpenType,
+    Woff2,
+}
+
+impl FontFormat {
+    fn sniff(data: &[u8]) -> Option<Self> {
+        match data.get(..4)? {
+            [0x00, 0x01, 0x00, 0x00] | b"true" => Some(Self::TrueType),
+            b"OTTO" => Some(Self::OpenType),
+            b"wOF2" => Some(Self::Woff2),
+            _ => None,
+        }
+    }
+
+    fn from_extension(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "ttf" => Some(Self::TrueType),
+            "otf" => Some(Self::OpenType),
+            "woff2" => Some(Self::Woff2),
+            _ => None,
+        }
+    }
+
+    fn mime(&self) -> &'static str {
+        match self {
+            Self::TrueType => "font/ttf",
+            Self::OpenType => "font/otf",
+            Self::Woff2 => "font/woff2",
+        }
+    }
+
+    fn css_format(&self) -> &'static str {
+        match self {
+            Self::TrueType => "truetype",
+            Self::OpenType => "opentype",
+            Self::Woff2 => "woff2",
+        }
+    }
+}
+
+/// Uploaded fonts.
+///
+/// Shared fonts live in a directory registered with fontconfig, so both
+/// engines find them by family name. Tenant fonts are kept outside every
+/// fontconfig path and reach the page as `@font-face` rules with data URIs,
+/// which keeps them invisible to other tenants. Each tenant proves its id
+/// with its own token; shared uploads reach every tenant, so they need the
+/// operator token.
+#[derive(Clone)]
+pub struct FontStore {
+    shared_dir: PathBuf,
+    tenant_dir: PathBuf,
+    /// SHA-256 of the operator token; shared uploads are disabled without one.
+    admin_token_hash: Option<[u8; 32]>,
+    /// SHA-256 of each tenant's token; tenant requests are rejected without one.
+    tenant_token_hashes: Arc<HashMap<String, [u8; 32]>>,
+}
+
+impl std::fmt::Debug for FontStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FontStore")
+            .field("shared_dir", &self.shared_dir)
+            .field("tenant_dir", &self.tenant_dir)
+            .field("shared_uploads", &self.admin_token_hash.is_some())
+            .field("tenant_count", &self.tenant_token_hashes.len())
+            .finish()
+    }
+}
+
+impl FontStore {
+    pub fn new(
+        shared_dir: PathBuf,
+        tenant_dir: PathBuf,
+        admin_token: Option<&str>,
+        tenant_tokens: HashMap<String, String>,
+    ) -> Self {
+        let admin_token_hash = admin_token.filter(|t| !t.is_empty()).map(|t| Sha256::digest(t.as_bytes()).into());
+        let tenant_token_hashes = tenant_tokens
+            .into_iter()
+            .map(|(tenant, token)| (tenant, Sha256::digest(token.as_bytes()).into()))
+            .collect();
+        Self { shared_dir, tenant_dir, admin_token_hash, tenant_token_hashes: Arc::new(tenant_token_hashes) }
+    }
+
+    /// Reads `FONTS_DIR` (default `fonts`), `TENANT_FONTS_DIR` (default
+    /// `tenant-fonts`), `FONTS_ADMIN_TOKEN` and `TENANT_TOKENS_FILE`.
+    pub fn from_env() -> anyhow::Result<Self> {
+        let shared_dir = std::env::var("FONTS_DIR").unwrap_or_else(|_| "fonts".to_string());
+        let tenant_dir = std::env::var("TENANT_FONTS_DIR").unwrap_or_else(|_| "tenant-fonts".to_string());
+        let admin_token = std::env::var("FONTS_ADMIN_TOKEN").ok();
+        let tenant_tokens = match std::env::var("TENANT_TOKENS_FILE") {
+            Ok(path) if !path.is_empty() => Self::read_tenant_tokens(Path::new(&path))?,
+            _ => HashMap::new(),
+        };
+        Ok(Self::new(PathBuf::from(shared_dir), PathBuf::from(tenant_dir), admin_token.as_deref(), tenant_tokens))
+    }
+
+    /// Parses one `<tenant> <token>` pair per line; blank lines and lines
+    /// starting with `#` are skipped.
+    fn read_tenant_tokens(path: &Path) -> anyhow::Result<HashMap<String, String>> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read tenant tokens from {}", path.display()))?;
+        let mut tokens = HashMap::new();
+        for (index, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let [tenant, token] = fields[..] else {
+                anyhow::bail!("{}:{}: expected '<tenant> <token>'", path.display(), index + 1);
+            };
+            Self::check_tenant(tenant).map_err(|e| anyhow::anyhow!("{}:{}: {}", path.display(), index + 1, e))?;
+            if tokens.insert(tenant.to_string(), token.to_string()).is_some() {
+                anyhow::bail!("{}:{}: tenant '{}' is listed twice", path.display(), index + 1, tenant);
+            }
+        }
+        Ok(tokens)
+    }
+
+    pub fn shared_uploads_enabled(&self) -> bool {
+        self.admin_token_hash.is_some()
+    }
+
+    pub fn tenant_count(&self) -> usize {
+        self.tenant_token_hashes.len()
+    }
+
+    /// Checks the bearer token of a tenant request against the tenant's
+    /// entry in `TENANT_TOKENS_FILE`.
+    pub fn authorize_tenant(&self, tenant: &str, bearer: Option<&str>) -> Result<(), FontError> {
+        if self.tenant_token_hashes.is_empty() {
+            return Err(FontError::Forbidden(
+                "Tenant requests are disabled: TENANT_TOKENS_FILE is not configured".to_string(),
+            ));
+        }
+        let given: [u8; 32] = Sha256::digest(bearer.unwrap_or_default().as_bytes()).into();
+        match self.tenant_token_hashes.get(tenant) {
+            Some(expected) if bearer.is_some() && given == *expected => Ok(()),
+            _ => Err(FontError::Forbidden(format!(
+                "Requests for tenant '{}' need the tenant's token as 'Authorization: Bearer <token>'",
+                tenant
+            ))),
+        }
+    }
+
+    /// Checks the bearer token of a shared upload against `FONTS_ADMIN_TOKEN`.
+    pub fn authorize_shared(&self, bearer: Option<&str>) -> Result<(), FontError> {
+        let Some(expected) = &self.admin_token_hash else {
+            return Err(FontError::Forbidden(
+                "Shared font uploads are disabled: FONTS_ADMIN_TOKEN is not configured. Send X-Tenant-Id to upload a tenant font".to_string(),
+            ));
+        };
+        // Comparing digests keeps the token length and prefix out of the timing
+        let given: [u8; 32] = Sha256::digest(bearer.unwrap_or_default().as_bytes()).into();
+        if bearer.is_some() && given == *expected {
+            Ok(())
+        } else {
+            Err(FontError::Forbidden("Shared font uploads need the operator token as 'Authorization: Bearer <token>'".to_string()))
+        }
+    }
+
+    pub fn shared_dir(&self) -> &Path {
+        &self.shared_dir
+    }
+
+    pub fn tenant_dir(&self) -> &Path {
+        &self.tenant_dir
+    }
+
+    /// Tenant ids become directory names, so only `[A-Za-z0-9_-]{1,64}` is accepted.
+    pub fn check_tenant(tenant: &str) -> Result<(), FontError> {
+        let valid = !tenant.is_empty()
+            && tenant.len() <= 64
+            && tenant.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-');
+        if valid {
+            Ok(())
+        } else {
+            Err(FontError::Invalid(format!("Invalid tenant id '{}'", tenant)))
+        }
+    }
+
+    /// Fonts fontconfig can see, plus the tenant's own fonts.
+    pub async fn list(&self, tenant: Option<&str>) -> Result<Vec<FontFace>, FontError> {
+        let store = self.clone();
+        let tenant = tenant.map(str::to_string);
+        Self::blocking(move || store.list_blocking(tenant.as_deref())).await
+    }
+
+    /// Stores an uploaded TTF/OTF/WOFF2 file and returns the faces it holds.
+    /// Shared uploads refresh the fontconfig cache. An existing file is
+    /// never replaced.
+    pub async fn install(&self, tenant: Option<&str>, filename: &str, data: Vec<u8>) -> Result<Vec<FontFace>, FontError> {
+        let store = self.clone();
+        let tenant = tenant.map(str::to_string);
+        let filename = filename.to_string();
+        Self::blocking(move || store.install_blocking(tenant.as_deref(), &filename, &data)).await
+    }
+
+    /// `@font-face` rules for every tenant font, embedded as data URIs.
+    pub async fn font_face_css(&self, tenant: &str) -> Result<String, FontError> {
+        let store = self.clone();
+        let tenant = tenant.to_string();
+        Self::blocking(move || store.font_face_css_blocking(&tenant)).await
+    }
+
+    /// fontconfig tools and font files are slow enough to stall a runtime worker.
+    async fn blocking<T: Send + 'static>(
+        task: impl FnOnce() -> Result<T, FontError> + Send + 'static,
+    ) -> Result<T, FontError> {
+        tokio::task::spawn_blocking(task)
+            .await
+            .map_err(|e| FontError::Internal(anyhow::anyhow!("Font task failed: {}", e)))?
+    }
+
+    fn list_blocking(&self, tenant: Option<&str>) -> Result<Vec<FontFace>, FontError> {
+        let shared_dir = self.shared_dir.canonicalize().unwrap_or_else(|_| self.shared_dir.clone());
+        let output = Command::new("fc-list")
+            .arg("--format")
+            .arg(FC_FORMAT)
+            .output()
+            .context("Failed to execute fc-list")?;
+        if !output.status.success() {
+            return Err(anyhow::anyhow!("fc-list failed: {}", String::from_utf8_lossy(&output.stderr)).into());
+        }
+
+        let mut faces: Vec<FontFace> = Self::parse_faces(&String::from_utf8_lossy(&output.stdout), |file| {
+            if Path::new(file).starts_with(&shared_dir) {
+                FontSource::Shared
+            } else {
+                FontSource::System
+            }
+        });
+
+        if let Some(tenant) = tenant {
+            faces.extend(self.tenant_faces(tenant)?.into_iter().map(|(face, _)| face));
+        }
+
+        faces.sort_by(|a, b| {
+            (a.source, &a.family, a.weight, a.italic, &a.file).cmp(&(b.source, &b.family, b.weight, b.italic, &b.file))
+        });
+        faces.dedup_by(|a, b| a.source == b.source && a.family == b.family && a.style == b.style && a.file == b.file);
+        Ok(faces)
+    }
+
+    fn install_blocking(&self, tenant: Option<&str>, filename: &str, data: &[u8]) -> Result<Vec<FontFace>, FontError> {
+        let start = Instant::now();
+        let format = Self::check_upload(filename, data)?;
+
+        let dir = match tenant {
+            Some(tenant) => {
+                Self::check_tenant(tenant)?;
+                self.tenant_dir.join(tenant)
+            }
+            None => self.shared_dir.clone(),
+        };
+        std::fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+
+        // Write under a temporary name so a bad upload never replaces a good font
+        let target = dir.join(filename);
+        if target.exists() {
+            return Err(FontError::Invalid(format!("A font named '{}' already exists", filename)));
+        }
+        let staging = dir.join(format!(".{}.upload", filename));
+        std::fs::write(&staging, data).with_context(|| format!("Failed to write {}", staging.display()))?;
+
+        let source = if tenant.is_some() { FontSource::Tenant } else { FontSource::Shared };
+        let faces = Self::scan(&staging, |_| source)
+            .and_then(|faces| {
+                if faces.is_empty() {
+                    Err(FontError::Invalid(format!("'{}' does not contain a readable font", filename)))
+                } else {
+                    Ok(faces)
+                }
+            });
+        let mut faces = match faces {
+            Ok(faces) => faces,
+            Err(e) => {
+                let _ = std::fs::remove_file(&staging);
+                return Err(e);
+            }
+        };
+        // Linking fails if the name was taken meanwhile, where a rename would replace it
+        let linked = std::fs::hard_link(&staging, &target);
+        let _ = std::fs::remove_file(&staging);
+        match linked {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                return Err(FontError::Invalid(format!("A font named '{}' already exists", filename)));
+            }
+            Err(e) => {
+                return Err(anyhow::Error::new(e).context(format!("Failed to move font into {}", target.display())).into());
+            }
+        }
+        for face in &mut faces {
+            face.file = filename.to_string();
+        }
+
+        if tenant.is_none() {
+            self.refresh_cache()?;
+        }
+
+        tracing::info!(
+            event = "font_installed",
+            tenant = ?tenant,
+            file = %filename,
+            format = format.css_format(),
+            families = ?faces.iter().map(|f| f.family.as_str()).collect::<Vec<_>>(),
+            duration_ms = start.elapsed().as_millis() as u64,
+            "Font installed"
+        );
+
+        Ok(faces)
+    }
+
+    fn font_face_css_blocking(&self, tenant: &str) -> Result<String, FontError> {
+        let mut css = String::new();
+        for (face, path) in self.tenant_faces(tenant)? {
+            let Some(format) = FontFormat::from_extension(&path) else { continue };
+            let data = std::fs::read(&path).with_context(|| format!("Failed to read {}", path.display()))?;
+            css.push_str(&format!(
+                "@font-face {{ font-family: {}; font-weight: {}; font-style: {}; src: url(data:{};base64,{}) format(\"{}\"); }}\n",
+                Self::css_string(&face.family),
+                face.weight,
+                if face.italic { "italic" } else { "normal" },
+                format.mime(),
+                base64::engine::general_purpose::STANDARD.encode(&data),
+                format.css_format(),
+            ));
+        }
+        Ok(css)
+    }
+
+    /// Quotes `value` as a CSS string. `<` and `>` are escaped along with
+    /// quotes, backslashes and control characters, so a family name read
+    /// from an uploaded file cannot close the `<style>` element.
+    fn css_string(value: &str) -> String {
+        let mut quoted = String::with_capacity(value.len() + 2);
+        quoted.push('"');
+        for c in value.chars() {
+            match c {
+                '"' | '\\' | '<' | '>' => quoted.push_str(&format!("\\{:x} ", c as u32)),
+                c if c.is_control() => quoted.push_str(&format!("\\{:x} ", c as u32)),
+                c => quoted.push(c),
+            }
+        }
+        quoted.push('"');
+        quoted
+    }
+
+    /// Inserts `css` at the start of `<head>`, so template rules can still
+    /// override it, without putting anything before the doctype.
+    pub fn inject_css(html: &str, css: &str) -> String {
+        if css.is_empty() {
+            return html.to_string();
+        }
+        let style = format!("<style>\n{}</style>", css);
+        let lower = html.to_ascii_lowercase();
+
+        let insert_at = ["<head", "<html", "<!doctype"]
+            .iter()
+            .find_map(|tag| {
+                // `<head` must not match `<header`
+                let start = lower.match_indices(tag).map(|(i, _)| i).find(|&i| {
+                    matches!(lower.as_bytes().get(i + tag.len()), Some(b'>' | b' ' | b'\t' | b'\r' | b'\n'))
+                })?;
+                lower[start..].find('>').map(|end| start + end + 1)
+            })
+            .unwrap_or(0);
+
+        let mut out = String::with_capacity(html.len() + style.len());
+        out.push_str(&html[..insert_at]);
+        out.push_str(&style);
+        out.push_str(&html[insert_at..]);
+        out
+    }
+
+    fn check_upload(filename: &str, data: &[u8]) -> Result<FontFormat, FontError> {
+        let safe_name = !filename.is_empty()
+            && filename.len() <= 128
+            && !filename.starts_with('.')
+            && filename.bytes().all(|b| b.is_ascii_alphanumeric() || matches!(b, b'.' | b'_' | b'-'));
+        if !safe_name {
+            return Err(FontError::Invalid(format!(
+                "Invalid font file name '{}': use letters, digits, '.', '_' and '-'",
+                filename
+            )));
+        }
+
+        let expected = FontFormat::from_extension(Path::new(filename))
+            .ok_or_else(|| FontError::Invalid("Font file must have a .ttf, .otf or .woff2 extension".to_string()))?;
+        match FontFormat::sniff(data) {
+            Some(actual) if std::mem::discriminant(&actual) == std::mem::discriminant(&expected) => Ok(actual),
+            Some(actual) => Err(FontError::Invalid(format!(
+                "'{}' contains {} data, which does not match its extension",
+                filename,
+                actual.css_format()
+            ))),
+            None => Err(FontError::Invalid(format!("'{}' is not a TTF, OTF or WOFF2 font", filename))),
+        }
+    }
+
+    fn tenant_faces(&self, tenant: &str) -> Result<Vec<(FontFace, PathBuf)>, FontError> {
+        Self::check_tenant(tenant)?;
+        let dir = self.tenant_dir.join(tenant);
+        if !dir.is_dir() {
+            return Ok(Vec::new());
+        }
+
+        let mut faces = Vec::new();
+        let mut entries: Vec<PathBuf> = std::fs::read_dir(&dir)
+            .with_context(|| format!("Failed to read {}", dir.display()))?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| FontFormat::from_extension(path).is_some())
+            .collect();
+        entries.sort();
+
+        for path in entries {
+            for mut face in Self::scan(&path, |_| FontSource::Tenant)? {
+                face.file = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+                faces.push((face, path.clone()));
+            }
+        }
+        Ok(faces)
+    }
+
+    fn scan(path: &Path, source: impl Fn(&str) -> FontSource) -> Result<Vec<FontFace>, FontError> {
+        let output = Command::new("fc-scan")
+            .arg("--format")
+            .arg(FC_FORMAT)
+            .arg(path)
+            .output()
+            .context("Failed to execute fc-scan")?;
+        // fc-scan exits non-zero for files it cannot parse
+        Ok(Self::parse_faces(&String::from_utf8_lossy(&output.stdout), source))
+    }
+
+    fn refresh_cache(&self) -> Result<(), FontError> {
+        let start = Instant::now();
+        let output = Command::new("fc-cache")
+            .arg("-f")
+            .arg(&self.shared_dir)
+            .output()
+            .context("Failed to execute fc-cache")?;
+        if !output.status.success() {
+            return Err(anyhow::anyhow!("fc-cache failed: {}", String::from_utf8_lossy(&output.stderr)).into());
+        }
+        tracing::debug!(
+            event = "font_cache_refreshed",
+            dir = %self.shared_dir.display(),
+            duration_ms = start.elapsed().as_millis() as u64,
+            "Fontconfig cache refreshed"
+        );
+        Ok(())
+    }
+
+    fn parse_faces(output: &str, source: impl Fn(&str) -> FontSource) -> Vec<FontFace> {
+        output
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.split('\t');
+                let family = fields.next()?.trim();
+                let style = fields.next()?.trim();
+                let weight = fields.next()?.trim().parse::<f64>().ok()?;
+                let slant = fields.next()?.trim().parse::<i32>().unwrap_or(0);
+                let file = fields.next()?.trim();
+                if family.is_empty() {
+                    return None;
+                }
+                Some(FontFace {
+                    family: family.to_string(),
+                    style: style.to_string(),
+                    weight: Self::css_weight(weight),
+                    italic: slant != 0,
+                    file: Path::new(file)
+                        .file_name()
+                        .map(|n| n.to_string_lossy().into_owned())
+                        .unwrap_or_default(),
+                    source: source(file),
+                })
+            })
+            .collect()
+    }
+
+    /// Maps a fontconfig weight to the nearest CSS weight.
+    fn css_weight(fc_weight: f64) -> u16 {
+        const TABLE: [(f64, u16); 9] = [
+            (0.0, 100),
+            (40.0, 200),
+            (50.0, 300),
+            (80.0, 400),
+            (100.0, 500),
+            (180.0, 600),
+            (200.0, 700),
+            (205.0, 800),
+            (210.0, 900),
+        ];
+        TABLE
+            .iter()
+            .min_by(|a, b| (a.0 - fc_weight).abs().total_cmp(&(b.0 - fc_weight).abs()))
+            .map(|(_, css)| *css)
+            .unwrap_or(400)
+    }
+}
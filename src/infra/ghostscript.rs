@@ -1,74 +1,355 @@
-use std::process::Command;
 use std::io::Write;
-use std::time::Instant;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use anyhow::Result;
 use tempfile::NamedTempFile;
+use tokio::process::Command;
+use tokio::sync::Semaphore;
+use crate::infra::assets::Assets;
+use crate::infra::color::{bilevel_pdf, ColorMode, OutputIntent, MONO_DPI};
+use crate::infra::optimize::Optimize;
+use crate::infra::pdfa::{Pdfa, PdfaLevel};
 
-pub struct Ghostscript;
+/// Ghostscript runner with a deadline, a cap on concurrent processes and
+/// resource limits on each child.
+#[derive(Clone, Debug)]
+pub struct Ghostscript {
+    assets: Assets,
+    permits: Arc<Semaphore>,
+    max_concurrency: usize,
+    timeout: Duration,
+    /// Address space limit (`RLIMIT_AS`) in bytes.
+    memory_limit: Option<u64>,
+    /// CPU time limit (`RLIMIT_CPU`) in seconds.
+    cpu_limit: Option<u64>,
+}
 
 impl Ghostscript {
-    pub fn convert_to_pdfa(pdf_data: &[u8]) -> Result<Vec<u8>> {
+    pub fn new(
+        assets: Assets,
+        max_concurrency: usize,
+        timeout: Duration,
+        memory_limit: Option<u64>,
+        cpu_limit: Option<u64>,
+    ) -> Self {
+        let max_concurrency = max_concurrency.max(1);
+        Self {
+            assets,
+            permits: Arc::new(Semaphore::new(max_concurrency)),
+            max_concurrency,
+            timeout,
+            memory_limit,
+            cpu_limit,
+        }
+    }
+
+    /// Reads `GS_MAX_CONCURRENCY` (default: available CPUs), `GS_TIMEOUT_SECS`
+    /// (default 60), `GS_MEMORY_LIMIT_MB` (default 2048) and
+    /// `GS_CPU_LIMIT_SECS` (default: the timeout). A limit of 0 disables it.
+    pub fn from_env(assets: Assets) -> Self {
+        let var = |name: &str| std::env::var(name).ok().and_then(|v| v.parse::<u64>().ok());
+
+        let max_concurrency = var("GS_MAX_CONCURRENCY")
+            .map(|v| v as usize)
+            .unwrap_or_else(|| std::thread::available_parallelism().map(|n| n.get()).unwrap_or(2));
+        let timeout_secs = var("GS_TIMEOUT_SECS").unwrap_or(60);
+        let memory_limit = Some(var("GS_MEMORY_LIMIT_MB").unwrap_or(2048))
+            .filter(|mb| *mb > 0)
+            .map(|mb| mb * 1024 * 1024);
+        let cpu_limit = Some(var("GS_CPU_LIMIT_SECS").unwrap_or(timeout_secs)).filter(|s| *s > 0);
+
+        Self::new(assets, max_concurrency, Duration::from_secs(timeout_secs), memory_limit, cpu_limit)
+    }
+
+    pub fn max_concurrency(&self) -> usize {
+        self.max_concurrency
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    pub fn memory_limit(&self) -> Option<u64> {
+        self.memory_limit
+    }
+
+    pub fn cpu_limit(&self) -> Option<u64> {
+        self.cpu_limit
+    }
+
+    /// Converts to PDF/A, applying `optimize` in the same pdfwrite pass so
+    /// the result stays conformant. Colors are converted to `mode`; CMYK
+    /// uses the profile of `intent`, which becomes the OutputIntent, and
+    /// the other modes keep the sRGB one, which also covers DeviceGray.
+    pub async fn convert_to_pdfa(
+        &self,
+        pdf_data: &[u8],
+        level: PdfaLevel,
+        mode: ColorMode,
+        intent: Option<&OutputIntent>,
+        optimize: Option<&Optimize>,
+    ) -> Result<Vec<u8>> {
+        let start = Instant::now();
+        let icc_profile = self.assets.srgb_icc();
+
+        let mut args = vec![
+            format!("-dPDFA={}", level.part()),
+            "-dNOOUTERSAVE".to_string(),
+            "-dPDFACompatibilityPolicy=1".to_string(),
+        ];
+        match intent {
+            Some(intent) => args.extend(intent.pdfa_args()),
+            None => {
+                args.extend(mode.gs_args());
+                // PDFA_def.ps opens the profile, which -dSAFER only allows when permitted
+                args.push(format!("--permit-file-read={}", icc_profile.display()));
+                args.push(format!("-sICCProfile={}", icc_profile.display()));
+            }
+        }
+        if let Some(optimize) = optimize {
+            args.extend(optimize.gs_args());
+        }
+
+        let output = self
+            .run("pdfwrite", pdf_data, args, optimize.and_then(Optimize::postscript), Some(self.assets.pdfa_def()), "pdfa")
+            .await?;
+
+        // Ghostscript only knows conformance B
+        let output_data = Pdfa::identify(&output, level)?;
+        let duration = start.elapsed();
+
+        tracing::debug!(
+            event = "ghostscript_pdfa_complete",
+            duration_ms = duration.as_millis() as u64,
+            input_size_bytes = pdf_data.len(),
+            output_size_bytes = output_data.len(),
+            pdf_a_level = level.as_str(),
+            color_mode = mode.as_str(),
+            output_intent = intent.map(OutputIntent::identifier).unwrap_or("sRGB"),
+            optimize = optimize.map(Optimize::name),
+            "PDF/A conversion completed successfully"
+        );
+
+        Ok(output_data)
+    }
+
+    /// Converts a plain PDF to `mode`. CMYK uses the profile of `intent` for
+    /// the conversion only: an OutputIntent would claim PDF/X conformance,
+    /// which nothing here checks.
+    pub async fn convert_colors(
+        &self,
+        pdf_data: &[u8],
+        mode: ColorMode,
+        intent: Option<&OutputIntent>,
+        optimize: Option<&Optimize>,
+    ) -> Result<Vec<u8>> {
+        let start = Instant::now();
+        let mut args = match intent {
+            Some(intent) => intent.conversion_args(),
+            None => mode.gs_args(),
+        };
+        if let Some(optimize) = optimize {
+            args.extend(optimize.gs_args());
+        }
+        let output = self
+            .run("pdfwrite", pdf_data, args, optimize.and_then(Optimize::postscript), None, "color")
+            .await?;
+
+        tracing::debug!(
+            event = "ghostscript_color_complete",
+            duration_ms = start.elapsed().as_millis() as u64,
+            input_size_bytes = pdf_data.len(),
+            output_size_bytes = output.len(),
+            color_mode = mode.as_str(),
+            icc_profile = intent.map(OutputIntent::identifier),
+            optimize = optimize.map(Optimize::name),
+            "PDF color conversion completed successfully"
+        );
+
+        Ok(output)
+    }
+
+    /// Renders every page to a 1-bit bitmap, images dithered, and returns
+    /// an image-only PDF of them. Text stops being selectable.
+    pub async fn rasterize_mono(&self, pdf_data: &[u8]) -> Result<Vec<u8>> {
+        let start = Instant::now();
+        let args = vec![format!("-r{}", MONO_DPI)];
+        let bitmaps = self.run("pbmraw", pdf_data, args, None, None, "mono").await?;
+        let output = bilevel_pdf(&bitmaps, MONO_DPI, pdf_data)?;
+
+        tracing::debug!(
+            event = "ghostscript_mono_complete",
+            duration_ms = start.elapsed().as_millis() as u64,
+            input_size_bytes = pdf_data.len(),
+            output_size_bytes = output.len(),
+            dpi = MONO_DPI,
+            "PDF rasterized to monochrome"
+        );
+
+        Ok(output)
+    }
+
+    /// Rewrites the PDF with the image and font settings of `optimize`.
+    pub async fn optimize(&self, pdf_data: &[u8], optimize: &Optimize) -> Result<Vec<u8>> {
+        let start = Instant::now();
+        let output = self
+            .run("pdfwrite", pdf_data, optimize.gs_args(), optimize.postscript(), None, "optimize")
+            .await?;
+
+        tracing::debug!(
+            event = "ghostscript_optimize_complete",
+            duration_ms = start.elapsed().as_millis() as u64,
+            input_size_bytes = pdf_data.len(),
+            output_size_bytes = output.len(),
+            optimize = optimize.name(),
+            "PDF optimization completed successfully"
+        );
+
+        Ok(output)
+    }
+
+    /// Runs `device` on `pdf_data`. `postscript` runs before the `prefix`
+    /// file, which runs before the input.
+    async fn run(
+        &self,
+        device: &str,
+        pdf_data: &[u8],
+        args: Vec<String>,
+        postscript: Option<String>,
+        prefix: Option<PathBuf>,
+        operation: &'static str,
+    ) -> Result<Vec<u8>> {
         let start = Instant::now();
         let input_size = pdf_data.len();
-        
+
+        // The deadline covers the process, not the wait for a permit
+        let _permit = self.permits.acquire().await?;
+        let queued = start.elapsed();
+
         tracing::debug!(
-            event = "ghostscript_pdfa_started",
+            event = "ghostscript_started",
+            operation = operation,
+            device = device,
             input_size_bytes = input_size,
-            "Starting PDF/A conversion with Ghostscript"
+            queue_ms = queued.as_millis() as u64,
+            "Starting Ghostscript"
         );
 
         let mut input_file = NamedTempFile::new()?;
         input_file.write_all(pdf_data)?;
-        
+
         let output_file = NamedTempFile::new()?;
         let output_path = output_file.path().to_str().unwrap().to_string();
 
-        let output = Command::new("gs")
-            .arg("-dPDFA=1")
+        let mut command = Command::new("gs");
+        command
             .arg("-dBATCH")
             .arg("-dNOPAUSE")
-            .arg("-dNOOUTERSAVE")
-            .arg("-sColorConversionStrategy=RGB")
-            .arg("-sProcessColorModel=DeviceRGB")
-            .arg("-sDEVICE=pdfwrite")
-            .arg("-dPDFACompatibilityPolicy=1")
-            .arg(format!("-sOutputFile={}", output_path))
-            .arg("assets/PDFA_def.ps")
+            .arg(format!("-sDEVICE={}", device))
+            .args(&args)
+            .arg(format!("-sOutputFile={}", output_path));
+        if let Some(postscript) = postscript {
+            command.arg("-c").arg(postscript).arg("-f");
+        }
+        if let Some(prefix) = prefix {
+            command.arg(prefix);
+        }
+        command
             .arg(input_file.path())
-            .output()
-            .map_err(|e| {
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        self.apply_limits(&mut command);
+
+        let child = command.spawn().map_err(|e| {
+            tracing::error!(
+                event = "ghostscript_execute_failed",
+                operation = operation,
+                error = %e,
+                "Failed to execute Ghostscript"
+            );
+            anyhow::anyhow!("Failed to execute ghostscript: {}", e)
+        })?;
+
+        // On expiry the child is dropped, and kill_on_drop sends SIGKILL
+        let output = match tokio::time::timeout(self.timeout, child.wait_with_output()).await {
+            Ok(output) => output?,
+            Err(_) => {
                 tracing::error!(
-                    event = "ghostscript_execute_failed",
-                    error = %e,
-                    "Failed to execute Ghostscript"
+                    event = "ghostscript_timeout",
+                    operation = operation,
+                    timeout_secs = self.timeout.as_secs(),
+                    input_size_bytes = input_size,
+                    "Ghostscript exceeded its deadline and was killed"
                 );
-                anyhow::anyhow!("Failed to execute ghostscript: {}", e)
-            })?;
+                return Err(anyhow::anyhow!("Ghostscript timed out after {}s", self.timeout.as_secs()));
+            }
+        };
 
         if !output.status.success() {
             let stderr = String::from_utf8_lossy(&output.stderr);
-            let duration = start.elapsed();
             tracing::error!(
-                event = "ghostscript_pdfa_failed",
-                duration_ms = duration.as_millis() as u64,
+                event = "ghostscript_failed",
+                operation = operation,
+                duration_ms = start.elapsed().as_millis() as u64,
                 exit_code = output.status.code(),
+                signal = Self::signal(&output.status),
                 stderr = %stderr,
-                "Ghostscript PDF/A conversion failed"
+                "Ghostscript run failed"
             );
             return Err(anyhow::anyhow!("Ghostscript failed with status: {}. Stderr: {}", output.status, stderr));
         }
 
-        let output_data = std::fs::read(&output_path)?;
-        let duration = start.elapsed();
-        
-        tracing::debug!(
-            event = "ghostscript_pdfa_complete",
-            duration_ms = duration.as_millis() as u64,
-            input_size_bytes = input_size,
-            output_size_bytes = output_data.len(),
-            "PDF/A conversion completed successfully"
-        );
+        Ok(std::fs::read(&output_path)?)
+    }
 
-        Ok(output_data)
+    /// Sets `RLIMIT_AS` and `RLIMIT_CPU` in the child before it execs `gs`.
+    /// Past the CPU limit the kernel sends `SIGXCPU`; an allocation past the
+    /// memory limit fails and `gs` exits with an error.
+    #[cfg(unix)]
+    fn apply_limits(&self, command: &mut Command) {
+        let memory_limit = self.memory_limit;
+        let cpu_limit = self.cpu_limit;
+        if memory_limit.is_none() && cpu_limit.is_none() {
+            return;
+        }
+
+        let set = |resource, value: u64| {
+            let limit = libc::rlimit { rlim_cur: value as libc::rlim_t, rlim_max: value as libc::rlim_t };
+            // SAFETY: setrlimit only reads `limit`
+            if unsafe { libc::setrlimit(resource, &limit) } != 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        };
+
+        // SAFETY: the closure only calls setrlimit, which is async-signal-safe
+        unsafe {
+            command.pre_exec(move || {
+                if let Some(bytes) = memory_limit {
+                    set(libc::RLIMIT_AS, bytes)?;
+                }
+                if let Some(secs) = cpu_limit {
+                    set(libc::RLIMIT_CPU, secs)?;
+                }
+                Ok(())
+            });
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn apply_limits(&self, _command: &mut Command) {}
+
+    #[cfg(unix)]
+    fn signal(status: &std::process::ExitStatus) -> Option<i32> {
+        std::os::unix::process::ExitStatusExt::signal(status)
+    }
+
+    #[cfg(not(unix))]
+    fn signal(_status: &std::process::ExitStatus) -> Option<i32> {
+        None
     }
 }
@@ -0,0 +1,368 @@
+use anyhow::Result;
+use flate2::read::ZlibDecoder;
+use lopdf::{dictionary, Object, ObjectId, Stream};
+use std::io::Read;
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// Largest image accepted, in pixels (50 megapixels).
+const MAX_PIXELS: u64 = 50_000_000;
+
+/// A raster image ready to become an image XObject.
+///
+/// JPEG data is passed through as `DCTDecode`, and PNG data without alpha
+/// keeps its zlib stream with a PNG predictor, so neither is re-encoded.
+/// PNGs with alpha or 16-bit samples are decoded and split into an 8-bit
+/// image and a soft mask.
+pub struct RasterImage {
+    pub width: u32,
+    pub height: u32,
+    image: Stream,
+    /// 8-bit alpha channel, uncompressed.
+    alpha: Option<Vec<u8>>,
+}
+
+impl RasterImage {
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        if data.starts_with(PNG_SIGNATURE) {
+            Self::png(data)
+        } else if data.starts_with(&[0xFF, 0xD8]) {
+            Self::jpeg(data)
+        } else {
+            Err(anyhow::anyhow!("Unsupported image format: use PNG or JPEG"))
+        }
+    }
+
+    pub fn has_alpha(&self) -> bool {
+        self.alpha.is_some()
+    }
+
+    /// Adds the image XObject, and its soft mask if it has one, to `doc`.
+    pub fn add_to(&self, doc: &mut lopdf::Document) -> Result<ObjectId> {
+        let mut image = self.image.clone();
+        if let Some(alpha) = &self.alpha {
+            let mut mask = Stream::new(
+                dictionary! {
+                    "Type" => "XObject",
+                    "Subtype" => "Image",
+                    "Width" => i64::from(self.width),
+                    "Height" => i64::from(self.height),
+                    "ColorSpace" => "DeviceGray",
+                    "BitsPerComponent" => 8,
+                },
+                alpha.clone(),
+            );
+            mask.compress()?;
+            image.dict.set("SMask", doc.add_object(mask));
+        }
+        Ok(doc.add_object(image))
+    }
+
+    fn jpeg(data: &[u8]) -> Result<Self> {
+        let mut at = 2;
+        let mut adobe = false;
+        while at + 4 <= data.len() {
+            if data[at] != 0xFF {
+                return Err(anyhow::anyhow!("Invalid JPEG: marker expected at byte {}", at));
+            }
+            let marker = data[at + 1];
+            // Fill bytes and markers without a length
+            if marker == 0xFF || (0xD0..=0xD9).contains(&marker) || marker == 0x01 {
+                at += if marker == 0xFF { 1 } else { 2 };
+                continue;
+            }
+            let length = u16::from_be_bytes([data[at + 2], data[at + 3]]) as usize;
+            let segment = data.get(at + 4..at + 2 + length).ok_or_else(|| anyhow::anyhow!("Invalid JPEG: truncated segment"))?;
+            match marker {
+                0xEE if segment.starts_with(b"Adobe") => adobe = true,
+                // Baseline, extended and progressive frames
+                0xC0..=0xC2 => {
+                    let [precision, h1, h2, w1, w2, components, ..] = *segment else {
+                        return Err(anyhow::anyhow!("Invalid JPEG: truncated frame header"));
+                    };
+                    if precision != 8 {
+                        return Err(anyhow::anyhow!("Unsupported JPEG: {}-bit samples", precision));
+                    }
+                    Self::check_size(u16::from_be_bytes([w1, w2]).into(), u16::from_be_bytes([h1, h2]).into())?;
+                    let mut dict = dictionary! {
+                        "Type" => "XObject",
+                        "Subtype" => "Image",
+                        "Width" => i64::from(u16::from_be_bytes([w1, w2])),
+                        "Height" => i64::from(u16::from_be_bytes([h1, h2])),
+                        "BitsPerComponent" => 8,
+                        "Filter" => "DCTDecode",
+                    };
+                    match components {
+                        1 => dict.set("ColorSpace", "DeviceGray"),
+                        3 => dict.set("ColorSpace", "DeviceRGB"),
+                        4 => {
+                            dict.set("ColorSpace", "DeviceCMYK");
+                            // Photoshop writes inverted CMYK
+                            if adobe {
+                                dict.set("Decode", vec![1.into(), 0.into(), 1.into(), 0.into(), 1.into(), 0.into(), 1.into(), 0.into()]);
+                            }
+                        }
+                        n => return Err(anyhow::anyhow!("Unsupported JPEG: {} color components", n)),
+                    }
+                    return Ok(Self {
+                        width: u32::from(u16::from_be_bytes([w1, w2])),
+                        height: u32::from(u16::from_be_bytes([h1, h2])),
+                        image: Stream::new(dict, data.to_vec()).with_compression(false),
+                        alpha: None,
+                    });
+                }
+                0xC3 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF => {
+                    return Err(anyhow::anyhow!("Unsupported JPEG: lossless or arithmetic coding"));
+                }
+                _ => {}
+            }
+            at += 2 + length;
+        }
+        Err(anyhow::anyhow!("Invalid JPEG: no frame header"))
+    }
+
+    fn png(data: &[u8]) -> Result<Self> {
+        let mut at = PNG_SIGNATURE.len();
+        let mut header = None;
+        let mut palette = None;
+        let mut compressed = Vec::new();
+        while at + 8 <= data.len() {
+            let length = u32::from_be_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]]) as usize;
+            let kind = &data[at + 4..at + 8];
+            let body = data.get(at + 8..at + 8 + length).ok_or_else(|| anyhow::anyhow!("Invalid PNG: truncated chunk"))?;
+            match kind {
+                b"IHDR" => header = Some(body),
+                b"PLTE" => palette = Some(body),
+                b"IDAT" => compressed.extend_from_slice(body),
+                b"IEND" => break,
+                _ => {}
+            }
+            // Length, type and CRC
+            at += 12 + length;
+        }
+
+        let Some(&[w1, w2, w3, w4, h1, h2, h3, h4, depth, color_type, _, _, interlace]) = header else {
+            return Err(anyhow::anyhow!("Invalid PNG: missing header"));
+        };
+        let width = u32::from_be_bytes([w1, w2, w3, w4]);
+        let height = u32::from_be_bytes([h1, h2, h3, h4]);
+        if width == 0 || height == 0 {
+            return Err(anyhow::anyhow!("Invalid PNG: empty image"));
+        }
+        Self::check_size(width, height)?;
+        if interlace != 0 {
+            return Err(anyhow::anyhow!("Unsupported PNG: interlaced images"));
+        }
+        let (colors, alpha) = match color_type {
+            0 => (1, false),
+            2 => (3, false),
+            3 => (1, false),
+            4 => (1, true),
+            6 => (3, true),
+            other => return Err(anyhow::anyhow!("Invalid PNG: color type {}", other)),
+        };
+        let color_space = match (color_type, palette) {
+            (3, Some(palette)) => Object::Array(vec![
+                "Indexed".into(),
+                "DeviceRGB".into(),
+                ((palette.len() / 3) as i64 - 1).into(),
+                Object::string_literal(palette.to_vec()),
+            ]),
+            (3, None) => return Err(anyhow::anyhow!("Invalid PNG: missing palette")),
+            _ if colors == 1 => "DeviceGray".into(),
+            _ => "DeviceRGB".into(),
+        };
+
+        let channels = colors + usize::from(alpha);
+        // Each row is a filter byte and the packed samples
+        let row_bytes = (width as usize * channels * usize::from(depth)).div_ceil(8);
+        let expected = (row_bytes + 1)
+            .checked_mul(height as usize)
+            .ok_or_else(|| anyhow::anyhow!("Invalid PNG: image is too large"))?;
+        Self::check_inflated_size(&compressed, expected)?;
+
+        let params = dictionary! {
+            "Predictor" => 15,
+            "Colors" => channels as i64,
+            "BitsPerComponent" => i64::from(depth),
+            "Columns" => i64::from(width),
+        };
+        let mut dict = dictionary! {
+            "Type" => "XObject",
+            "Subtype" => "Image",
+            "Width" => i64::from(width),
+            "Height" => i64::from(height),
+            "ColorSpace" => color_space,
+        };
+
+        if !alpha && depth <= 8 {
+            dict.set("BitsPerComponent", i64::from(depth));
+            dict.set("Filter", "FlateDecode");
+            dict.set("DecodeParms", params);
+            return Ok(Self { width, height, image: Stream::new(dict, compressed).with_compression(false), alpha: None });
+        }
+
+        // Unfiltered samples, then split color and alpha at 8 bits
+        let samples = Stream::new(dictionary! { "Filter" => "FlateDecode", "DecodeParms" => params }, compressed)
+            .decompressed_content()
+            .map_err(|e| anyhow::anyhow!("Invalid PNG: {}", e))?;
+        let bytes_per_sample = usize::from(depth / 8);
+        let pixel_count = width as usize * height as usize;
+        let sample_bytes = pixel_count
+            .checked_mul(channels * bytes_per_sample)
+            .ok_or_else(|| anyhow::anyhow!("Invalid PNG: image is too large"))?;
+        if depth < 8 || samples.len() < sample_bytes {
+            return Err(anyhow::anyhow!("Invalid PNG: image data is incomplete"));
+        }
+        let mut color = Vec::with_capacity(pixel_count * colors);
+        let mut alpha_channel = alpha.then(|| Vec::with_capacity(pixel_count));
+        for pixel in samples.chunks_exact(channels * bytes_per_sample).take(pixel_count) {
+            // Most significant byte of each sample
+            for channel in 0..colors {
+                color.push(pixel[channel * bytes_per_sample]);
+            }
+            if let Some(alpha_channel) = alpha_channel.as_mut() {
+                alpha_channel.push(pixel[colors * bytes_per_sample]);
+            }
+        }
+
+        dict.set("BitsPerComponent", 8);
+        let mut image = Stream::new(dict, color);
+        image.compress()?;
+        Ok(Self { width, height, image, alpha: alpha_channel })
+    }
+
+    fn check_size(width: u32, height: u32) -> Result<()> {
+        if u64::from(width) * u64::from(height) > MAX_PIXELS {
+            return Err(anyhow::anyhow!(
+                "Image is {}x{} pixels; at most {} megapixels are accepted",
+                width,
+                height,
+                MAX_PIXELS / 1_000_000
+            ));
+        }
+        Ok(())
+    }
+
+    /// Inflates into a sink, so a small stream that expands far beyond the
+    /// header's dimensions is refused without being held in memory.
+    fn check_inflated_size(compressed: &[u8], expected: usize) -> Result<()> {
+        let limit = expected as u64 + 1;
+        let inflated = std::io::copy(&mut ZlibDecoder::new(compressed).take(limit), &mut std::io::sink())
+            .map_err(|e| anyhow::anyhow!("Invalid PNG: {}", e))?;
+        if inflated > expected as u64 {
+            return Err(anyhow::anyhow!("Invalid PNG: image data is larger than its dimensions"));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::ZlibEncoder;
+    use std::io::Write;
+
+    fn zlib(data: &[u8]) -> Vec<u8> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    /// Chunks with a zero CRC, which the decoder does not check.
+    fn png(width: u32, height: u32, depth: u8, color_type: u8, idat: &[u8]) -> Vec<u8> {
+        let mut header = [width.to_be_bytes(), height.to_be_bytes()].concat();
+        header.extend([depth, color_type, 0, 0, 0]);
+        let mut data = PNG_SIGNATURE.to_vec();
+        for (kind, body) in [(&b"IHDR"[..], &header[..]), (b"IDAT", idat), (b"IEND", &[])] {
+            data.extend((body.len() as u32).to_be_bytes());
+            data.extend(kind);
+            data.extend(body);
+            data.extend([0; 4]);
+        }
+        data
+    }
+
+    /// SOI, an APP0 segment and a baseline frame header.
+    fn jpeg(width: u16, height: u16, components: u8) -> Vec<u8> {
+        let mut data = vec![0xFF, 0xD8, 0xFF, 0xE0, 0, 7, b'J', b'F', b'I', b'F', 0];
+        let mut frame = vec![8];
+        frame.extend(height.to_be_bytes());
+        frame.extend(width.to_be_bytes());
+        frame.push(components);
+        for id in 1..=components {
+            frame.extend([id, 0x11, 0]);
+        }
+        data.extend([0xFF, 0xC0]);
+        data.extend((frame.len() as u16 + 2).to_be_bytes());
+        data.extend(frame);
+        data.extend([0xFF, 0xD9]);
+        data
+    }
+
+    fn error(data: &[u8]) -> String {
+        RasterImage::decode(data).err().expect("decoding should fail").to_string()
+    }
+
+    #[test]
+    fn passes_opaque_png_data_through() {
+        // Two RGB rows, each with a filter byte
+        let idat = zlib(&[0, 255, 0, 0, 0, 255, 0, 0, 0, 0, 255, 255, 255, 255]);
+        let image = RasterImage::decode(&png(2, 2, 8, 2, &idat)).unwrap();
+        assert_eq!((image.width, image.height, image.has_alpha()), (2, 2, false));
+        assert_eq!(image.image.content, idat);
+        assert_eq!(image.image.dict.get(b"Filter").unwrap().as_name().unwrap(), b"FlateDecode");
+    }
+
+    #[test]
+    fn splits_alpha_into_a_soft_mask() {
+        let idat = zlib(&[0, 10, 20, 30, 128, 40, 50, 60, 255]);
+        let image = RasterImage::decode(&png(2, 1, 8, 6, &idat)).unwrap();
+        assert_eq!(image.alpha.as_deref(), Some(&[128, 255][..]));
+        // Too small to be worth compressing
+        assert!(image.image.dict.get(b"Filter").is_err());
+        assert_eq!(image.image.content, vec![10, 20, 30, 40, 50, 60]);
+
+        let mut doc = lopdf::Document::with_version("1.7");
+        let id = image.add_to(&mut doc).unwrap();
+        let dict = &doc.get_object(id).unwrap().as_stream().unwrap().dict;
+        assert!(dict.get(b"SMask").unwrap().as_reference().is_ok());
+    }
+
+    #[test]
+    fn reads_jpeg_frame_headers() {
+        let image = RasterImage::decode(&jpeg(640, 480, 3)).unwrap();
+        assert_eq!((image.width, image.height), (640, 480));
+        assert_eq!(image.image.dict.get(b"ColorSpace").unwrap().as_name().unwrap(), b"DeviceRGB");
+        assert_eq!(image.image.content, jpeg(640, 480, 3));
+        assert!(error(&jpeg(10, 10, 2)).contains("2 color components"));
+    }
+
+    #[test]
+    fn rejects_truncated_images() {
+        let idat = zlib(&[0, 255, 0, 0]);
+        let complete = png(1, 1, 8, 2, &idat);
+        assert!(error(&complete[..complete.len() - 20]).contains("truncated chunk"));
+        assert!(error(&complete[..PNG_SIGNATURE.len()]).contains("missing header"));
+        assert!(error(&png(2, 2, 8, 6, &zlib(&[0, 1, 2, 3, 4]))).contains("Invalid PNG"));
+        assert!(error(&png(1, 1, 8, 6, &idat[..idat.len() - 3])).contains("Invalid PNG"));
+
+        let frame = jpeg(640, 480, 3);
+        assert!(error(&frame[..15]).contains("truncated segment"));
+        assert!(error(&frame[..11]).contains("no frame header"));
+        assert!(error(&[0xFF, 0xD8, 0x00, 0x00, 0x00, 0x00]).contains("marker expected"));
+        assert!(error(b"GIF89a").contains("Unsupported image format"));
+    }
+
+    #[test]
+    fn rejects_oversized_images() {
+        assert!(error(&png(10_000, 10_000, 8, 2, &zlib(&[0]))).contains("megapixels"));
+        assert!(error(&jpeg(65_535, 65_535, 3)).contains("megapixels"));
+        assert!(error(&png(0, 1, 8, 2, &zlib(&[0]))).contains("empty image"));
+
+        // A 1x1 header over a megabyte of samples
+        let bomb = zlib(&vec![0; 1024 * 1024]);
+        assert!(bomb.len() < 2048);
+        assert!(error(&png(1, 1, 8, 0, &bomb)).contains("larger than its dimensions"));
+    }
+}
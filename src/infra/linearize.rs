@@ -0,0 +1,123 @@
+//! Linearized ("fast web view") output through qpdf, which writes the
+//! first page's objects and the hint tables at the start of the file.
+
+use std::io::Write;
+use std::process::Stdio;
+use std::time::{Duration, Instant};
+use anyhow::Result;
+use tempfile::NamedTempFile;
+use tokio::process::Command;
+
+/// The linearization dictionary must start within this many bytes.
+const LINEARIZATION_WINDOW: usize = 1024;
+
+/// qpdf exits with 3 when it succeeded with warnings.
+const EXIT_WARNINGS: i32 = 3;
+
+#[derive(Clone, Debug)]
+pub struct Linearizer {
+    binary: String,
+    timeout: Duration,
+}
+
+impl Linearizer {
+    /// Uses `QPDF_PATH` (default `qpdf`) if it runs, otherwise returns
+    /// `None` so `linearize` is rejected. `QPDF_TIMEOUT_SECS` (default 60)
+    /// bounds each run.
+    pub fn detect() -> Option<Self> {
+        let binary = std::env::var("QPDF_PATH").unwrap_or_else(|_| "qpdf".to_string());
+        let output = std::process::Command::new(&binary).arg("--version").output().ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        let timeout_secs = std::env::var("QPDF_TIMEOUT_SECS")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(60);
+
+        tracing::info!(
+            event = "qpdf_detected",
+            binary = %binary,
+            version = %String::from_utf8_lossy(&output.stdout).lines().next().unwrap_or_default(),
+            timeout_secs = timeout_secs,
+            "qpdf available for linearized output"
+        );
+        Some(Self { binary, timeout: Duration::from_secs(timeout_secs) })
+    }
+
+    /// Rewrites `pdf_data` linearized, keeping stream data and object
+    /// streams as they are. `password` opens an encrypted file, whose
+    /// encryption is kept; `deterministic` derives the `/ID` from the
+    /// content and `pdfa` adds the end-of-line PDF/A wants before `endstream`.
+    pub async fn linearize(&self, pdf_data: &[u8], password: Option<&str>, deterministic: bool, pdfa: bool) -> Result<Vec<u8>> {
+        let start = Instant::now();
+
+        let mut input_file = NamedTempFile::new()?;
+        input_file.write_all(pdf_data)?;
+        let output_file = NamedTempFile::new()?;
+
+        let mut command = Command::new(&self.binary);
+        command
+            .arg("--linearize")
+            .arg("--stream-data=preserve")
+            .arg("--object-streams=preserve");
+        if deterministic {
+            command.arg("--deterministic-id");
+        }
+        if pdfa {
+            command.arg("--newline-before-endstream");
+        }
+        // A file keeps the password off the process list
+        let password_file = match password {
+            Some(password) => {
+                let mut file = NamedTempFile::new()?;
+                file.write_all(password.as_bytes())?;
+                command.arg(format!("--password-file={}", file.path().display()));
+                Some(file)
+            }
+            None => None,
+        };
+        command
+            .arg(input_file.path())
+            .arg(output_file.path())
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+
+        let child = command
+            .spawn()
+            .map_err(|e| anyhow::anyhow!("Failed to execute qpdf: {}", e))?;
+        let output = match tokio::time::timeout(self.timeout, child.wait_with_output()).await {
+            Ok(output) => output?,
+            Err(_) => return Err(anyhow::anyhow!("qpdf timed out after {}s", self.timeout.as_secs())),
+        };
+        drop(password_file);
+
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        match output.status.code() {
+            Some(0) => {}
+            Some(EXIT_WARNINGS) => {
+                tracing::warn!(event = "qpdf_warnings", stderr = %stderr.trim(), "qpdf linearized the PDF with warnings");
+            }
+            _ => return Err(anyhow::anyhow!("qpdf failed with status: {}. Stderr: {}", output.status, stderr.trim())),
+        }
+
+        let linearized = std::fs::read(output_file.path())?;
+        tracing::debug!(
+            event = "pdf_linearized",
+            input_size_bytes = pdf_data.len(),
+            output_size_bytes = linearized.len(),
+            duration_ms = start.elapsed().as_millis() as u64,
+            "PDF linearized"
+        );
+        Ok(linearized)
+    }
+}
+
+/// Whether the file starts with a linearization dictionary. Writers that
+/// append to such a file must not rewrite it, or the layout is lost.
+pub fn is_linearized(pdf_data: &[u8]) -> bool {
+    let window = &pdf_data[..pdf_data.len().min(LINEARIZATION_WINDOW)];
+    window.windows(b"/Linearized".len()).any(|w| w == b"/Linearized")
+}
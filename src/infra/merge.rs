@@ -0,0 +1,215 @@
+use anyhow::Result;
+use lopdf::{dictionary, text_string, Document, Object, ObjectId};
+use std::time::Instant;
+use thiserror::Error;
+use crate::infra::pdf_objects::{flatten_inherited, resolve_named_destinations, save};
+
+/// One input of a merge, in output order.
+pub struct MergeInput<'a> {
+    pub data: &'a [u8],
+    /// Bookmark that wraps the part's own bookmarks and points at its first page.
+    pub bookmark: Option<&'a str>,
+}
+
+#[derive(Error, Debug)]
+pub enum MergeError {
+    /// A part cannot be merged; reported as a client error.
+    #[error("{0}")]
+    Invalid(String),
+    #[error(transparent)]
+    Internal(#[from] anyhow::Error),
+}
+
+impl From<lopdf::Error> for MergeError {
+    fn from(e: lopdf::Error) -> Self {
+        Self::Internal(e.into())
+    }
+}
+
+/// Concatenates PDFs page by page.
+///
+/// Bookmarks and link annotations are carried over. Named destinations are
+/// resolved to explicit ones first, so names that repeat across parts cannot
+/// clash. Document-level structures that cannot be combined (structure
+/// trees, forms, XMP) are dropped. Signed parts are rejected, since their
+/// signature fields would be left without the form that holds them.
+pub struct PdfMerger;
+
+impl PdfMerger {
+    pub fn merge(inputs: &[MergeInput<'_>]) -> Result<Vec<u8>, MergeError> {
+        let start = Instant::now();
+        if inputs.is_empty() {
+            return Err(MergeError::Invalid("Nothing to merge".to_string()));
+        }
+
+        let mut merged = Document::with_version("1.4");
+        let pages_id = merged.new_object_id();
+        let outlines_id = merged.new_object_id();
+        let mut kids = Vec::new();
+        // Top-level bookmarks, in order
+        let mut bookmarks: Vec<ObjectId> = Vec::new();
+
+        for (index, input) in inputs.iter().enumerate() {
+            let mut doc = Document::load_mem(input.data)
+                .map_err(|e| MergeError::Invalid(format!("Part {} is not a valid PDF: {}", index + 1, e)))?;
+            if doc.is_encrypted() {
+                return Err(MergeError::Invalid(format!("Part {} is encrypted", index + 1)));
+            }
+            if Self::has_signature_fields(&doc) {
+                return Err(MergeError::Invalid(format!(
+                    "Part {} is signed or has signature fields, which merging would break; send the unsigned document instead",
+                    index + 1
+                )));
+            }
+            if doc.version > merged.version {
+                merged.version = doc.version.clone();
+            }
+
+            resolve_named_destinations(&mut doc);
+            flatten_inherited(&mut doc);
+            doc.renumber_objects_with(merged.max_id + 1);
+
+            let page_ids: Vec<ObjectId> = doc.get_pages().into_values().collect();
+            let Some(&first_page) = page_ids.first() else {
+                return Err(MergeError::Invalid(format!("Part {} has no pages", index + 1)));
+            };
+            let part_bookmarks = Self::top_level_bookmarks(&doc);
+
+            // Only pages and what they reference survive; the old catalog and
+            // page tree are left behind for prune_objects
+            let catalog_id = doc.trailer.get(b"Root").and_then(Object::as_reference)?;
+            for (id, object) in std::mem::take(&mut doc.objects) {
+                if id == catalog_id {
+                    continue;
+                }
+                merged.objects.insert(id, object);
+            }
+            merged.max_id = merged.max_id.max(doc.max_id);
+
+            for page_id in &page_ids {
+                let page = merged.get_dictionary_mut(*page_id)?;
+                page.set("Parent", pages_id);
+                // Part of the dropped structure tree
+                page.remove(b"StructParents");
+            }
+            kids.extend(page_ids.iter().map(|id| Object::Reference(*id)));
+
+            match input.bookmark {
+                Some(title) => {
+                    let id = merged.add_object(dictionary! {
+                        "Title" => text_string(title),
+                        "Dest" => vec![first_page.into(), "Fit".into()],
+                    });
+                    Self::link_children(&mut merged, id, &part_bookmarks)?;
+                    bookmarks.push(id);
+                }
+                None => bookmarks.extend(part_bookmarks),
+            }
+        }
+
+        let page_count = kids.len();
+        merged.objects.insert(
+            pages_id,
+            Object::Dictionary(dictionary! { "Type" => "Pages", "Kids" => kids, "Count" => page_count as i64 }),
+        );
+
+        let mut catalog = dictionary! { "Type" => "Catalog", "Pages" => pages_id };
+        if !bookmarks.is_empty() {
+            merged.objects.insert(outlines_id, Object::Dictionary(dictionary! { "Type" => "Outlines" }));
+            Self::link_children(&mut merged, outlines_id, &bookmarks)?;
+            catalog.set("Outlines", outlines_id);
+            catalog.set("PageMode", "UseOutlines");
+        }
+        let catalog_id = merged.add_object(catalog);
+        merged.trailer.set("Root", catalog_id);
+        merged.prune_objects();
+
+        tracing::debug!(
+            event = "pdf_merged",
+            part_count = inputs.len(),
+            page_count = page_count,
+            bookmark_count = bookmarks.len(),
+            duration_ms = start.elapsed().as_millis() as u64,
+            "PDFs merged"
+        );
+
+        Ok(save(&mut merged)?)
+    }
+
+    fn has_signature_fields(doc: &Document) -> bool {
+        doc.objects.values().any(|object| {
+            object
+                .as_dict()
+                .and_then(|dict| dict.get(b"FT"))
+                .and_then(Object::as_name)
+                .map(|ft| ft == b"Sig")
+                .unwrap_or(false)
+        })
+    }
+
+    fn top_level_bookmarks(doc: &Document) -> Vec<ObjectId> {
+        let mut items = Vec::new();
+        let mut next = doc
+            .catalog()
+            .and_then(|c| c.get(b"Outlines"))
+            .and_then(Object::as_reference)
+            .and_then(|id| doc.get_dictionary(id))
+            .and_then(|root| root.get(b"First"))
+            .and_then(Object::as_reference)
+            .ok();
+        while let Some(id) = next {
+            // Guards against a cyclic /Next chain
+            if items.contains(&id) {
+                break;
+            }
+            items.push(id);
+            next = doc
+                .get_dictionary(id)
+                .and_then(|item| item.get(b"Next"))
+                .and_then(Object::as_reference)
+                .ok();
+        }
+        items
+    }
+
+    /// Makes `children` the bookmarks under `parent`, in order.
+    fn link_children(doc: &mut Document, parent: ObjectId, children: &[ObjectId]) -> Result<()> {
+        for (index, id) in children.iter().enumerate() {
+            let item = doc.get_dictionary_mut(*id)?;
+            item.set("Parent", parent);
+            match index.checked_sub(1).map(|i| children[i]) {
+                Some(prev) => item.set("Prev", prev),
+                None => {
+                    item.remove(b"Prev");
+                }
+            }
+            match children.get(index + 1) {
+                Some(next) => item.set("Next", *next),
+                None => {
+                    item.remove(b"Next");
+                }
+            }
+        }
+
+        // Open children count themselves plus their own visible descendants
+        let visible: i64 = children
+            .iter()
+            .map(|id| {
+                let count = doc
+                    .get_dictionary(*id)
+                    .and_then(|item| item.get(b"Count"))
+                    .and_then(Object::as_i64)
+                    .unwrap_or(0);
+                1 + count.max(0)
+            })
+            .sum();
+
+        let parent_dict = doc.get_dictionary_mut(parent)?;
+        if let (Some(first), Some(last)) = (children.first(), children.last()) {
+            parent_dict.set("First", *first);
+            parent_dict.set("Last", *last);
+            parent_dict.set("Count", visible);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,128 @@
+use anyhow::Result;
+use lopdf::{dictionary, text_string, Document, Stream};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::BTreeMap;
+use crate::infra::pdf_objects::{info_dict_mut, metadata_id, save, write_xmp};
+use crate::infra::pdfa::PdfaLevel;
+use crate::infra::xmp::XmpPacket;
+
+/// DocInfo keys that `custom` entries may not override.
+const RESERVED_KEYS: [&str; 9] = [
+    "Title", "Author", "Subject", "Keywords", "Creator", "Producer", "CreationDate", "ModDate", "Trapped",
+];
+
+/// Document properties written to `/Info` and the XMP packet.
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct DocumentMetadata {
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub author: Option<String>,
+    #[serde(default)]
+    pub subject: Option<String>,
+    /// A list, or a single comma-separated string.
+    #[serde(default, deserialize_with = "keywords")]
+    pub keywords: Vec<String>,
+    /// Application that created the source document (`/Creator`).
+    #[serde(default)]
+    pub creator: Option<String>,
+    /// BCP 47 tag for `/Lang` and `dc:language`.
+    #[serde(default)]
+    pub language: Option<String>,
+    /// Extra `/Info` entries. Not mirrored in XMP, which would need an extension schema.
+    #[serde(default)]
+    pub custom: BTreeMap<String, String>,
+}
+
+fn keywords<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Vec<String>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Keywords {
+        List(Vec<String>),
+        Text(String),
+    }
+
+    let keywords = match Keywords::deserialize(deserializer)? {
+        Keywords::List(list) => list,
+        Keywords::Text(text) => text.split(',').map(str::to_string).collect(),
+    };
+    Ok(keywords
+        .into_iter()
+        .map(|k| k.trim().to_string())
+        .filter(|k| !k.is_empty())
+        .collect())
+}
+
+impl DocumentMetadata {
+    pub fn validate(&self) -> Result<()> {
+        for key in self.custom.keys() {
+            let valid_name = !key.is_empty()
+                && key.len() <= 127
+                && key.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-' || b == b'.');
+            if !valid_name {
+                return Err(anyhow::anyhow!(
+                    "Invalid custom metadata key '{}': use letters, digits, '_', '-' and '.'",
+                    key
+                ));
+            }
+            if RESERVED_KEYS.iter().any(|r| r.eq_ignore_ascii_case(key)) {
+                return Err(anyhow::anyhow!("Custom metadata key '{}' is reserved; use the matching field", key));
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the metadata to `/Info` and replaces the XMP packet with one
+    /// built from the resulting `/Info`, keeping the PDF/A identification.
+    /// `language` is the resolved document language, which may come from
+    /// the render options or the html instead of [`Self::language`].
+    pub fn apply(&self, pdf_data: &[u8], language: Option<&str>, pdfa: Option<PdfaLevel>) -> Result<Vec<u8>> {
+        let mut doc = Document::load_mem(pdf_data)
+            .map_err(|e| anyhow::anyhow!("Failed to parse PDF: {}", e))?;
+
+        let keywords = (!self.keywords.is_empty()).then(|| self.keywords.join(", "));
+        let info = info_dict_mut(&mut doc);
+        for (key, value) in [
+            ("Title", &self.title),
+            ("Author", &self.author),
+            ("Subject", &self.subject),
+            ("Keywords", &keywords),
+            ("Creator", &self.creator),
+        ] {
+            if let Some(value) = value {
+                info.set(key, text_string(value));
+            }
+        }
+        for (key, value) in &self.custom {
+            info.set(key.as_bytes(), text_string(value));
+        }
+
+        if let Some(language) = language {
+            doc.catalog_mut()?.set("Lang", text_string(language));
+        }
+
+        let xmp = XmpPacket::from_info(&mut doc, language, pdfa.map(|l| (l.part(), l.conformance())));
+        match metadata_id(&doc) {
+            Some(id) => write_xmp(&mut doc, id, xmp.to_xml())?,
+            None => {
+                let mut stream = Stream::new(
+                    dictionary! { "Type" => "Metadata", "Subtype" => "XML" },
+                    xmp.to_xml().into_bytes(),
+                );
+                stream.allows_compression = false;
+                let id = doc.add_object(stream);
+                doc.catalog_mut()?.set("Metadata", id);
+            }
+        }
+
+        tracing::debug!(
+            event = "metadata_applied",
+            has_title = self.title.is_some(),
+            keyword_count = self.keywords.len(),
+            custom_count = self.custom.len(),
+            "Document metadata applied"
+        );
+
+        save(&mut doc)
+    }
+}
@@ -1,3 +1,32 @@
 pub mod templates;
 pub mod browser;
 pub mod ghostscript;
+pub mod url_allowlist;
+pub mod pdf_objects;
+pub mod xmp;
+pub mod tagging;
+pub mod outline;
+pub mod diagnostics;
+pub mod deterministic;
+pub mod weasyprint;
+pub mod fonts;
+pub mod font_coverage;
+pub mod pdfa;
+pub mod metadata;
+pub mod validation;
+pub mod assets;
+pub mod optimize;
+pub mod merge;
+pub mod split;
+pub mod truetype;
+pub mod image;
+pub mod watermark;
+pub mod der;
+pub mod cms;
+pub mod tsa;
+pub mod signing;
+pub mod dss;
+pub mod security;
+pub mod attachments;
+pub mod linearize;
+pub mod color;
@@ -0,0 +1,143 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+/// Ghostscript `PDFSETTINGS` presets.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OptimizePreset {
+    /// 72 dpi images, lowest size.
+    Screen,
+    /// 150 dpi images.
+    Ebook,
+    /// 300 dpi images.
+    Printer,
+    /// 300 dpi images, color preserving.
+    Prepress,
+}
+
+impl OptimizePreset {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Screen => "screen",
+            Self::Ebook => "ebook",
+            Self::Printer => "printer",
+            Self::Prepress => "prepress",
+        }
+    }
+}
+
+/// The `optimize` option: a preset name or custom settings.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum Optimize {
+    Preset(OptimizePreset),
+    Custom(CustomOptimize),
+}
+
+/// Custom optimization; unset fields keep the `preset` (or Ghostscript) default.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct CustomOptimize {
+    #[serde(default)]
+    pub preset: Option<OptimizePreset>,
+    /// Images above this resolution (dpi) are downsampled to it.
+    #[serde(default)]
+    pub image_resolution: Option<u32>,
+    /// JPEG quality for color and gray images, 1–100.
+    #[serde(default)]
+    pub jpeg_quality: Option<u8>,
+    #[serde(default = "enabled")]
+    pub subset_fonts: bool,
+    /// Store identical images once, e.g. a logo repeated on every page.
+    #[serde(default = "enabled")]
+    pub detect_duplicate_images: bool,
+}
+
+fn enabled() -> bool {
+    true
+}
+
+impl Optimize {
+    pub fn validate(&self) -> Result<()> {
+        let Self::Custom(custom) = self else {
+            return Ok(());
+        };
+        if let Some(dpi) = custom.image_resolution {
+            if !(36..=2400).contains(&dpi) {
+                return Err(anyhow::anyhow!("'image_resolution' must be between 36 and 2400 dpi"));
+            }
+        }
+        if let Some(quality) = custom.jpeg_quality {
+            if !(1..=100).contains(&quality) {
+                return Err(anyhow::anyhow!("'jpeg_quality' must be between 1 and 100"));
+            }
+        }
+        Ok(())
+    }
+
+    /// Short name for logs: the preset, or `custom`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Preset(preset) => preset.as_str(),
+            Self::Custom(_) => "custom",
+        }
+    }
+
+    /// pdfwrite switches for these settings.
+    pub fn gs_args(&self) -> Vec<String> {
+        let custom = match self {
+            Self::Preset(preset) => {
+                return vec![
+                    format!("-dPDFSETTINGS=/{}", preset.as_str()),
+                    "-dDetectDuplicateImages=true".to_string(),
+                ];
+            }
+            Self::Custom(custom) => custom,
+        };
+
+        let mut args = Vec::new();
+        if let Some(preset) = custom.preset {
+            args.push(format!("-dPDFSETTINGS=/{}", preset.as_str()));
+        }
+        if let Some(dpi) = custom.image_resolution {
+            for kind in ["Color", "Gray"] {
+                args.push(format!("-dDownsample{}Images=true", kind));
+                args.push(format!("-d{}ImageDownsampleType=/Bicubic", kind));
+                args.push(format!("-d{}ImageResolution={}", kind, dpi));
+            }
+            // Monochrome scans need more pixels to stay legible
+            args.push("-dDownsampleMonoImages=true".to_string());
+            args.push(format!("-dMonoImageResolution={}", dpi.saturating_mul(2)));
+        }
+        if custom.jpeg_quality.is_some() {
+            // QFactor is only honored with an explicit DCT filter
+            for kind in ["Color", "Gray"] {
+                args.push(format!("-dAutoFilter{}Images=false", kind));
+                args.push(format!("-d{}ImageFilter=/DCTEncode", kind));
+            }
+        }
+        args.push(format!("-dSubsetFonts={}", custom.subset_fonts));
+        args.push(format!("-dDetectDuplicateImages={}", custom.detect_duplicate_images));
+        args
+    }
+
+    /// PostScript for the settings that have no command-line switch, run
+    /// with `-c` before the input files.
+    pub fn postscript(&self) -> Option<String> {
+        let Self::Custom(CustomOptimize { jpeg_quality: Some(quality), .. }) = self else {
+            return None;
+        };
+        let q_factor = Self::q_factor(*quality);
+        Some(format!(
+            "<< /ColorImageDict << /QFactor {q:.2} /Blend 1 /HSamples [2 1 1 2] /VSamples [2 1 1 2] >> \
+             /GrayImageDict << /QFactor {q:.2} /Blend 1 /HSamples [2 1 1 2] /VSamples [2 1 1 2] >> >> setdistillerparams",
+            q = q_factor
+        ))
+    }
+
+    /// Maps a 1–100 quality to a DCT `QFactor`: 0.15 is Acrobat's
+    /// "maximum", 2.4 its "minimum".
+    fn q_factor(quality: u8) -> f32 {
+        (f32::from(100 - quality.min(100)) / 100.0 * 2.4).max(0.15)
+    }
+}
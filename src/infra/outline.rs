@@ -0,0 +1,312 @@
+use anyhow::Result;
+use lopdf::{dictionary, text_string, Dictionary, Document, Object, ObjectId};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use crate::infra::pdf_objects::{dict_text, save};
+
+/// Bookmark generation from `h1`–`h6`.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct OutlineOptions {
+    /// Deepest heading level that becomes a bookmark (1 = only `h1`).
+    #[serde(default = "default_max_depth")]
+    pub max_depth: u8,
+    /// Headings with one of these classes, or inside an element that has
+    /// one, are left out of the outline.
+    #[serde(default)]
+    pub exclude_classes: Vec<String>,
+}
+
+fn default_max_depth() -> u8 {
+    6
+}
+
+/// A heading as seen by the page, in document order.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct Heading {
+    pub level: u8,
+    pub text: String,
+    pub excluded: bool,
+}
+
+/// One bookmark, flattened so it can be re-attached after the page objects
+/// have been renumbered (e.g. by Ghostscript).
+#[derive(Debug, Clone)]
+pub struct OutlineEntry {
+    pub title: String,
+    pub level: u8,
+    pub page_index: usize,
+    /// Vertical position on the page in PDF user space, if known.
+    pub top: Option<f32>,
+}
+
+pub struct Outline;
+
+impl Outline {
+    /// Script that lists the visible headings with their exclusion state.
+    /// `{{EXCLUDE}}` is replaced by a JSON array of class names.
+    pub const COLLECT_HEADINGS_SCRIPT: &'static str = r#"
+        (() => {
+            const exclude = {{EXCLUDE}};
+            const headings = Array.from(document.querySelectorAll('h1, h2, h3, h4, h5, h6'))
+                .filter(h => h.getClientRects().length > 0)
+                .map(h => ({
+                    level: Number(h.tagName.substring(1)),
+                    text: h.innerText.trim(),
+                    excluded: exclude.some(c => h.closest('.' + CSS.escape(c)) !== null),
+                }));
+            return JSON.stringify(headings);
+        })()
+    "#;
+
+    /// Reads the outline Chrome wrote into the PDF.
+    pub fn extract(pdf_data: &[u8]) -> Result<Vec<OutlineEntry>> {
+        let doc = Document::load_mem(pdf_data)
+            .map_err(|e| anyhow::anyhow!("Failed to parse PDF: {}", e))?;
+        let pages: HashMap<ObjectId, usize> = doc
+            .get_pages()
+            .values()
+            .enumerate()
+            .map(|(index, id)| (*id, index))
+            .collect();
+
+        let mut entries = Vec::new();
+        let first = doc
+            .catalog()?
+            .get(b"Outlines")
+            .and_then(Object::as_reference)
+            .and_then(|id| doc.get_dictionary(id))
+            .and_then(|root| root.get(b"First"))
+            .and_then(Object::as_reference);
+
+        if let Ok(first) = first {
+            Self::collect(&doc, &pages, first, 1, &mut entries, &mut HashSet::new());
+        }
+
+        Ok(entries)
+    }
+
+    /// Applies depth and class filters. When the page headings line up with
+    /// the extracted entries, their tag level is used instead of the tree depth.
+    pub fn filter(entries: Vec<OutlineEntry>, headings: &[Heading], options: &OutlineOptions) -> Vec<OutlineEntry> {
+        let aligned = headings.len() == entries.len()
+            && headings.iter().zip(&entries).all(|(h, e)| h.text == e.title);
+
+        if !aligned && !headings.is_empty() {
+            tracing::warn!(
+                event = "outline_headings_mismatch",
+                heading_count = headings.len(),
+                entry_count = entries.len(),
+                "Outline does not match page headings; excluding by title"
+            );
+        }
+
+        let excluded_titles: HashSet<&str> = headings
+            .iter()
+            .filter(|h| h.excluded)
+            .map(|h| h.text.as_str())
+            .collect();
+
+        entries
+            .into_iter()
+            .enumerate()
+            .filter_map(|(index, mut entry)| {
+                let excluded = if aligned {
+                    entry.level = headings[index].level;
+                    headings[index].excluded
+                } else {
+                    excluded_titles.contains(entry.title.as_str())
+                };
+                (!excluded && entry.level <= options.max_depth).then_some(entry)
+            })
+            .collect()
+    }
+
+    /// Replaces the document outline with `entries`.
+    pub fn apply(pdf_data: &[u8], entries: &[OutlineEntry]) -> Result<Vec<u8>> {
+        let mut doc = Document::load_mem(pdf_data)
+            .map_err(|e| anyhow::anyhow!("Failed to parse PDF: {}", e))?;
+        let pages: Vec<ObjectId> = doc.get_pages().values().copied().collect();
+
+        let entries: Vec<&OutlineEntry> = entries
+            .iter()
+            .filter(|e| {
+                let in_range = e.page_index < pages.len();
+                if !in_range {
+                    tracing::warn!(
+                        event = "outline_entry_dropped",
+                        title = %e.title,
+                        page_index = e.page_index,
+                        page_count = pages.len(),
+                        "Bookmark points past the last page"
+                    );
+                }
+                in_range
+            })
+            .collect();
+
+        if entries.is_empty() {
+            let catalog = doc.catalog_mut()?;
+            catalog.remove(b"Outlines");
+            doc.prune_objects();
+            return save(&mut doc);
+        }
+
+        // Build the tree from levels: each entry nests under the closest
+        // preceding entry with a lower level.
+        let mut parents: Vec<Option<usize>> = Vec::with_capacity(entries.len());
+        let mut stack: Vec<usize> = Vec::new();
+        for (index, entry) in entries.iter().enumerate() {
+            while let Some(&top) = stack.last() {
+                if entries[top].level < entry.level {
+                    break;
+                }
+                stack.pop();
+            }
+            parents.push(stack.last().copied());
+            stack.push(index);
+        }
+
+        let mut children: Vec<Vec<usize>> = vec![Vec::new(); entries.len()];
+        let mut roots = Vec::new();
+        for (index, parent) in parents.iter().enumerate() {
+            match parent {
+                Some(p) => children[*p].push(index),
+                None => roots.push(index),
+            }
+        }
+
+        let root_id = doc.new_object_id();
+        let ids: Vec<ObjectId> = entries.iter().map(|_| doc.new_object_id()).collect();
+
+        let mut descendants = vec![0i64; entries.len()];
+        for index in (0..entries.len()).rev() {
+            if let Some(parent) = parents[index] {
+                descendants[parent] += descendants[index] + 1;
+            }
+        }
+
+        let link_siblings = |dict: &mut Dictionary, siblings: &[usize], position: usize| {
+            if position > 0 {
+                dict.set("Prev", ids[siblings[position - 1]]);
+            }
+            if position + 1 < siblings.len() {
+                dict.set("Next", ids[siblings[position + 1]]);
+            }
+        };
+
+        let mut items = Vec::with_capacity(entries.len());
+        for (index, entry) in entries.iter().enumerate() {
+            let siblings = match parents[index] {
+                Some(p) => &children[p],
+                None => &roots,
+            };
+            let position = siblings.iter().position(|&s| s == index).unwrap_or(0);
+
+            let page = pages[entry.page_index];
+            let destination = match entry.top {
+                Some(top) => vec![page.into(), "XYZ".into(), Object::Null, top.into(), Object::Null],
+                None => vec![page.into(), "Fit".into()],
+            };
+
+            let mut item = dictionary! {
+                "Title" => text_string(&entry.title),
+                "Parent" => parents[index].map(|p| ids[p]).unwrap_or(root_id),
+                "Dest" => destination,
+            };
+            link_siblings(&mut item, siblings, position);
+            if let (Some(first), Some(last)) = (children[index].first(), children[index].last()) {
+                item.set("First", ids[*first]);
+                item.set("Last", ids[*last]);
+                item.set("Count", descendants[index]);
+            }
+            items.push(item);
+        }
+
+        for (id, item) in ids.iter().zip(items) {
+            doc.objects.insert(*id, Object::Dictionary(item));
+        }
+        doc.objects.insert(
+            root_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Outlines",
+                "First" => ids[roots[0]],
+                "Last" => ids[*roots.last().expect("roots is non-empty")],
+                "Count" => entries.len() as i64,
+            }),
+        );
+
+        let catalog = doc.catalog_mut()?;
+        catalog.set("Outlines", root_id);
+        catalog.set("PageMode", "UseOutlines");
+
+        // Drop the items of the outline that was replaced
+        doc.prune_objects();
+        save(&mut doc)
+    }
+
+    fn collect(
+        doc: &Document,
+        pages: &HashMap<ObjectId, usize>,
+        id: ObjectId,
+        level: u8,
+        entries: &mut Vec<OutlineEntry>,
+        seen: &mut HashSet<ObjectId>,
+    ) {
+        let mut current = Some(id);
+        while let Some(id) = current {
+            // Guard against malformed, cyclic outlines
+            if !seen.insert(id) {
+                break;
+            }
+            let Ok(item) = doc.get_dictionary(id) else { break };
+
+            let title = dict_text(doc, item, b"Title").unwrap_or_default();
+            match Self::destination(doc, item).and_then(|d| Self::resolve_destination(pages, &d)) {
+                Some((page_index, top)) => entries.push(OutlineEntry { title, level, page_index, top }),
+                None => tracing::debug!(event = "outline_entry_skipped", title = %title, "Bookmark has no page destination"),
+            }
+
+            if let Ok(first) = item.get(b"First").and_then(Object::as_reference) {
+                Self::collect(doc, pages, first, level.saturating_add(1), entries, seen);
+            }
+            current = item.get(b"Next").and_then(Object::as_reference).ok();
+        }
+    }
+
+    /// Returns the explicit destination array of an outline item, following
+    /// `/A` GoTo actions and named destinations in `/Dests`.
+    fn destination(doc: &Document, item: &Dictionary) -> Option<Vec<Object>> {
+        let dest = match item.get(b"Dest") {
+            Ok(dest) => dest.clone(),
+            Err(_) => {
+                let action = doc.dereference(item.get(b"A").ok()?).ok()?.1.as_dict().ok()?;
+                action.get(b"D").ok()?.clone()
+            }
+        };
+
+        match doc.dereference(&dest).ok()?.1 {
+            Object::Array(array) => Some(array.clone()),
+            Object::Name(name) | Object::String(name, _) => {
+                let dests = doc.catalog().ok()?.get(b"Dests").ok()?;
+                let dests = doc.dereference(dests).ok()?.1.as_dict().ok()?;
+                match doc.dereference(dests.get(name).ok()?).ok()?.1 {
+                    Object::Array(array) => Some(array.clone()),
+                    Object::Dictionary(d) => d.get(b"D").ok()?.as_array().ok().cloned(),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    fn resolve_destination(pages: &HashMap<ObjectId, usize>, dest: &[Object]) -> Option<(usize, Option<f32>)> {
+        let page_index = *pages.get(&dest.first()?.as_reference().ok()?)?;
+        let is_xyz = matches!(dest.get(1).and_then(|o| o.as_name().ok()), Some(b"XYZ"));
+        let top = if is_xyz {
+            dest.get(3).and_then(|o| o.as_float().ok())
+        } else {
+            None
+        };
+        Some((page_index, top))
+    }
+}
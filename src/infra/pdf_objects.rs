@@ -0,0 +1,203 @@
+use anyhow::Result;
+use lopdf::xref::XrefType;
+use lopdf::{decode_text_string, Dictionary, Document, Object, ObjectId};
+use std::collections::BTreeMap;
+
+/// Page attributes a page may inherit from its `/Pages` ancestors.
+const INHERITABLE: [&[u8]; 4] = [b"Resources", b"MediaBox", b"CropBox", b"Rotate"];
+
+/// Reads a text entry from a dictionary, following references.
+pub fn dict_text(doc: &Document, dict: &Dictionary, key: &[u8]) -> Option<String> {
+    let object = dict.get(key).ok()?;
+    decode_text_string(doc.dereference(object).ok()?.1).ok()
+}
+
+/// Returns the trailer `/Info` dictionary, creating an empty one if needed.
+pub fn info_dict_mut(doc: &mut Document) -> &mut Dictionary {
+    let info_id = match doc.trailer.get(b"Info").and_then(Object::as_reference) {
+        Ok(id) if doc.get_dictionary(id).is_ok() => id,
+        _ => {
+            let id = doc.add_object(Dictionary::new());
+            doc.trailer.set("Info", id);
+            id
+        }
+    };
+    doc.get_dictionary_mut(info_id).expect("info dictionary was just resolved")
+}
+
+/// Returns the catalog `/Metadata` stream, if the document has one.
+pub fn metadata_id(doc: &Document) -> Option<ObjectId> {
+    doc.catalog().ok()?.get(b"Metadata").and_then(Object::as_reference).ok()
+}
+
+/// Reads the XMP packet stored in stream `id`.
+pub fn read_xmp(doc: &Document, id: ObjectId) -> Result<String> {
+    let stream = doc.get_object(id)?.as_stream()?;
+    let content = stream
+        .decompressed_content()
+        .unwrap_or_else(|_| stream.content.clone());
+    Ok(String::from_utf8_lossy(&content).into_owned())
+}
+
+/// Stores an XMP packet uncompressed, as PDF/A requires for the metadata stream.
+pub fn write_xmp(doc: &mut Document, id: ObjectId, xmp: String) -> Result<()> {
+    let stream = doc.get_object_mut(id)?.as_stream_mut()?;
+    stream.dict.remove(b"Filter");
+    stream.dict.remove(b"DecodeParms");
+    stream.allows_compression = false;
+    stream.set_content(xmp.into_bytes());
+    Ok(())
+}
+
+/// Rewrites `/Dest` entries and GoTo `/D` entries that name a destination
+/// into the explicit destination array.
+pub fn resolve_named_destinations(doc: &mut Document) {
+    let names = named_destinations(doc);
+    if names.is_empty() {
+        return;
+    }
+
+    for object in doc.objects.values_mut() {
+        replace_destinations(object, &names);
+    }
+}
+
+fn replace_destinations(object: &mut Object, names: &BTreeMap<Vec<u8>, Object>) {
+    match object {
+        Object::Dictionary(dict) => {
+            let go_to = matches!(dict.get(b"S").and_then(Object::as_name), Ok(b"GoTo"));
+            for (key, value) in dict.iter_mut() {
+                let name = match value {
+                    Object::Name(name) | Object::String(name, _) if key == b"Dest" || (go_to && key == b"D") => name,
+                    // Link actions are usually inline
+                    _ => {
+                        replace_destinations(value, names);
+                        continue;
+                    }
+                };
+                if let Some(dest) = names.get(name.as_slice()) {
+                    *value = dest.clone();
+                }
+            }
+        }
+        Object::Array(items) => items.iter_mut().for_each(|item| replace_destinations(item, names)),
+        _ => {}
+    }
+}
+
+/// Named destinations from the PDF 1.1 `/Dests` dictionary and the
+/// `/Names` `/Dests` name tree.
+fn named_destinations(doc: &Document) -> BTreeMap<Vec<u8>, Object> {
+    let mut names = BTreeMap::new();
+    let Ok(catalog) = doc.catalog() else {
+        return names;
+    };
+    let resolve = |object: &Object| doc.dereference(object).ok().map(|(_, o)| o.clone());
+    // A destination is an array, or a dictionary holding it in /D
+    let explicit = |object: &Object| match resolve(object)? {
+        Object::Array(dest) => Some(Object::Array(dest)),
+        Object::Dictionary(d) => d.get(b"D").ok().and_then(resolve).filter(|d| d.as_array().is_ok()),
+        _ => None,
+    };
+
+    if let Some(Object::Dictionary(dests)) = catalog.get(b"Dests").ok().and_then(resolve) {
+        for (name, dest) in dests.iter() {
+            if let Some(dest) = explicit(dest) {
+                names.insert(name.clone(), dest);
+            }
+        }
+    }
+
+    let tree = catalog
+        .get(b"Names")
+        .ok()
+        .and_then(resolve)
+        .and_then(|n| n.as_dict().ok().and_then(|n| n.get(b"Dests").ok()).and_then(resolve));
+    let mut pending: Vec<Object> = tree.into_iter().collect();
+    while let Some(node) = pending.pop() {
+        let Object::Dictionary(node) = node else { continue };
+        if let Ok(kids) = node.get(b"Kids").and_then(Object::as_array) {
+            pending.extend(kids.iter().filter_map(resolve));
+        }
+        if let Ok(pairs) = node.get(b"Names").and_then(Object::as_array) {
+            for pair in pairs.chunks_exact(2) {
+                if let (Ok(name), Some(dest)) = (pair[0].as_str(), explicit(&pair[1])) {
+                    names.insert(name.to_vec(), dest);
+                }
+            }
+        }
+    }
+
+    names
+}
+
+/// Copies inherited attributes onto each page, so the `/Pages` nodes
+/// above it can be dropped.
+pub fn flatten_inherited(doc: &mut Document) {
+    let page_ids: Vec<ObjectId> = doc.get_pages().into_values().collect();
+    for page_id in page_ids {
+        let mut inherited = Dictionary::new();
+        let mut parent = doc
+            .get_dictionary(page_id)
+            .and_then(|p| p.get(b"Parent"))
+            .and_then(Object::as_reference)
+            .ok();
+        // Bounded in case of a cyclic tree
+        for _ in 0..64 {
+            let Some(node) = parent.and_then(|id| doc.get_dictionary(id).ok()) else { break };
+            for key in INHERITABLE {
+                if !inherited.has(key) {
+                    if let Ok(value) = node.get(key) {
+                        inherited.set(key, value.clone());
+                    }
+                }
+            }
+            parent = node.get(b"Parent").and_then(Object::as_reference).ok();
+        }
+
+        if let Ok(page) = doc.get_dictionary_mut(page_id) {
+            for (key, value) in inherited.into_iter() {
+                if !page.has(&key) {
+                    page.set(key, value);
+                }
+            }
+        }
+    }
+}
+
+/// Whether an object dictionary brings in transparency, which PDF/A-1
+/// forbids: a soft mask other than `/None`, a transparency group, a
+/// constant opacity (`/CA`, `/ca`) below 1 or a blend mode other than
+/// `/Normal` and `/Compatible`.
+pub fn uses_transparency(doc: &Document, dict: &Dictionary) -> bool {
+    let resolve = |key: &[u8]| dict.get(key).ok().and_then(|o| doc.dereference(o).ok()).map(|(_, o)| o);
+    let soft_mask = matches!(dict.get(b"SMask"), Ok(o) if o.as_name().map(|n| n != b"None").unwrap_or(true));
+    let group = resolve(b"Group")
+        .and_then(|g| g.as_dict().ok())
+        .map(|g| matches!(g.get(b"S").and_then(Object::as_name), Ok(b"Transparency")))
+        .unwrap_or(false);
+    let opacity = [&b"CA"[..], b"ca"]
+        .iter()
+        .any(|key| matches!(resolve(key).map(Object::as_float), Some(Ok(alpha)) if alpha < 1.0));
+    // An array lists fallbacks in order of preference; any of them may be used
+    let blend_mode = match resolve(b"BM") {
+        Some(Object::Array(modes)) => modes.iter().any(|m| !matches!(m.as_name(), Ok(b"Normal" | b"Compatible"))),
+        Some(mode) => !matches!(mode.as_name(), Ok(b"Normal" | b"Compatible")),
+        None => false,
+    };
+    soft_mask || group || opacity || blend_mode
+}
+
+/// Serializes the document with a classic cross-reference table, which
+/// PDF/A-1 requires and every reader understands.
+pub fn save(doc: &mut Document) -> Result<Vec<u8>> {
+    doc.reference_table.cross_reference_type = XrefType::CrossReferenceTable;
+    // Leftovers from an input that used an xref stream or incremental updates
+    for key in [&b"Type"[..], b"W", b"Index", b"Filter", b"DecodeParms", b"Length", b"Prev", b"XRefStm"] {
+        doc.trailer.remove(key);
+    }
+    let mut output = Vec::new();
+    doc.save_to(&mut output)
+        .map_err(|e| anyhow::anyhow!("Failed to write PDF: {}", e))?;
+    Ok(output)
+}
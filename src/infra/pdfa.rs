@@ -0,0 +1,103 @@
+use anyhow::Result;
+use lopdf::Document;
+use serde::{Deserialize, Serialize};
+use crate::infra::pdf_objects::{metadata_id, read_xmp, save, write_xmp};
+use crate::infra::xmp::replace_property;
+
+/// PDF/A part and conformance level.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PdfaLevel {
+    #[serde(rename = "1b")]
+    A1b,
+    #[serde(rename = "2b")]
+    A2b,
+    #[serde(rename = "2u")]
+    A2u,
+    #[serde(rename = "3b")]
+    A3b,
+    #[serde(rename = "1a")]
+    A1a,
+    #[serde(rename = "2a")]
+    A2a,
+}
+
+impl PdfaLevel {
+    /// ISO 19005 part.
+    pub fn part(&self) -> u8 {
+        match self {
+            Self::A1b | Self::A1a => 1,
+            Self::A2b | Self::A2u | Self::A2a => 2,
+            Self::A3b => 3,
+        }
+    }
+
+    /// Conformance letter written to `pdfaid:conformance`.
+    pub fn conformance(&self) -> char {
+        match self {
+            Self::A1b | Self::A2b | Self::A3b => 'B',
+            Self::A2u => 'U',
+            Self::A1a | Self::A2a => 'A',
+        }
+    }
+
+    /// Level A needs the logical structure of a tagged PDF.
+    pub fn requires_tagging(&self) -> bool {
+        self.conformance() == 'A'
+    }
+
+    /// PDF/A-1 is based on PDF 1.4, later parts on PDF 1.7.
+    pub fn pdf_version(&self) -> &'static str {
+        if self.part() == 1 { "1.4" } else { "1.7" }
+    }
+
+    /// Only PDF/A-1 forbids transparency.
+    pub fn allows_transparency(&self) -> bool {
+        self.part() > 1
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::A1b => "1b",
+            Self::A2b => "2b",
+            Self::A2u => "2u",
+            Self::A3b => "3b",
+            Self::A1a => "1a",
+            Self::A2a => "2a",
+        }
+    }
+
+    /// Resolves `pdf_a_level` and the legacy `pdf_a` flag, which means 1b,
+    /// or 1a for tagged output.
+    pub fn resolve(level: Option<PdfaLevel>, pdf_a: bool, tagged: bool) -> Option<PdfaLevel> {
+        match (level, pdf_a) {
+            (Some(level), _) => Some(level),
+            (None, true) if tagged => Some(Self::A1a),
+            (None, true) => Some(Self::A1b),
+            (None, false) => None,
+        }
+    }
+}
+
+pub struct Pdfa;
+
+impl Pdfa {
+    /// Rewrites the `pdfaid` part and conformance in the XMP metadata.
+    ///
+    /// Ghostscript always claims conformance B, so U levels are set here.
+    pub fn identify(pdf_data: &[u8], level: PdfaLevel) -> Result<Vec<u8>> {
+        let mut doc = Document::load_mem(pdf_data)
+            .map_err(|e| anyhow::anyhow!("Failed to parse PDF: {}", e))?;
+        let id = metadata_id(&doc)
+            .ok_or_else(|| anyhow::anyhow!("PDF/A output has no XMP metadata stream"))?;
+
+        let xmp = read_xmp(&doc, id)?;
+        if !xmp.contains("pdfaid:part") {
+            return Err(anyhow::anyhow!("PDF/A output has no pdfaid identification"));
+        }
+        let xmp = replace_property(&xmp, "pdfaid:part", &level.part().to_string());
+        let xmp = replace_property(&xmp, "pdfaid:conformance", &level.conformance().to_string());
+        write_xmp(&mut doc, id, xmp)?;
+
+        save(&mut doc)
+    }
+}
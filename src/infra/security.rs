@@ -0,0 +1,499 @@
+use aes::cipher::{generic_array::GenericArray, BlockEncrypt, KeyInit};
+use aes::{Aes128, Aes256};
+use anyhow::Result;
+use lopdf::{dictionary, Dictionary, Document, Object, StringFormat};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256, Sha384, Sha512};
+use std::time::Instant;
+use crate::infra::pdf_objects::save;
+
+/// Longest password, in UTF-8 bytes, for revision 6 of the standard security handler.
+const MAX_PASSWORD_BYTES: usize = 127;
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EncryptionAlgorithm {
+    /// AES-256 (`/V 5 /R 6`, `AESV3`), readable by Acrobat X and later and
+    /// by every PDF 2.0 reader.
+    #[default]
+    #[serde(rename = "aes-256", alias = "AES-256")]
+    Aes256,
+}
+
+impl EncryptionAlgorithm {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Aes256 => "aes-256",
+        }
+    }
+}
+
+/// What a reader may do after opening the file with the user password.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Permission {
+    Print,
+    /// Copy or extract text and images.
+    Copy,
+    /// Change the content, insert, rotate or delete pages.
+    Modify,
+    /// Add comments and fill in form fields.
+    Annotate,
+}
+
+impl Permission {
+    /// Bits of `/P` granted, counting from 1 as the spec does.
+    fn bits(&self) -> &'static [u32] {
+        match self {
+            // 12: print at full quality
+            Self::Print => &[3, 12],
+            Self::Copy => &[5],
+            // 11: assemble the document
+            Self::Modify => &[4, 11],
+            // 9: fill in existing form fields
+            Self::Annotate => &[6, 9],
+        }
+    }
+}
+
+/// The `security` option: password protection and permission flags.
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Security {
+    /// Needed to open the file; when absent it opens without a prompt, with
+    /// the restrictions of `permissions`.
+    #[serde(default)]
+    pub user_password: Option<String>,
+    /// Lifts the restrictions; a random one is used when absent.
+    #[serde(default)]
+    pub owner_password: Option<String>,
+    /// Granted with the user password; all of them by default.
+    #[serde(default = "all_permissions")]
+    pub permissions: Vec<Permission>,
+    #[serde(default)]
+    pub algorithm: EncryptionAlgorithm,
+}
+
+fn all_permissions() -> Vec<Permission> {
+    vec![Permission::Print, Permission::Copy, Permission::Modify, Permission::Annotate]
+}
+
+// Keeps the passwords out of logs
+impl std::fmt::Debug for Security {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Security")
+            .field("user_password", &self.user_password.as_ref().map(|_| "***"))
+            .field("owner_password", &self.owner_password.as_ref().map(|_| "***"))
+            .field("permissions", &self.permissions)
+            .field("algorithm", &self.algorithm)
+            .finish()
+    }
+}
+
+impl Security {
+    pub fn validate(&self) -> Result<()> {
+        if self.user_password.is_none() && self.owner_password.is_none() {
+            return Err(anyhow::anyhow!("'security' needs a 'user_password' or an 'owner_password'"));
+        }
+        for (name, password) in [("user_password", &self.user_password), ("owner_password", &self.owner_password)] {
+            match password {
+                Some(p) if p.is_empty() => {
+                    return Err(anyhow::anyhow!("'security.{}' must not be empty; leave it out instead", name));
+                }
+                Some(p) if p.len() > MAX_PASSWORD_BYTES => {
+                    return Err(anyhow::anyhow!("'security.{}' must be at most {} bytes long", name, MAX_PASSWORD_BYTES));
+                }
+                _ => {}
+            }
+        }
+        if self.user_password.is_some() && self.user_password == self.owner_password {
+            return Err(anyhow::anyhow!(
+                "'security.owner_password' must differ from 'user_password', or the permissions would not apply"
+            ));
+        }
+        Ok(())
+    }
+
+    /// Encrypts every string and stream of the file. With `deterministic`,
+    /// keys, salts and IVs are derived from the input and the passwords, so
+    /// the same input gives the same output.
+    pub fn apply(&self, pdf_data: &[u8], deterministic: bool) -> Result<Vec<u8>> {
+        let start = Instant::now();
+        let mut doc = Document::load_mem(pdf_data)
+            .map_err(|e| anyhow::anyhow!("Failed to parse PDF: {}", e))?;
+        if doc.trailer.get(b"Encrypt").is_ok() {
+            return Err(anyhow::anyhow!("The PDF is already encrypted"));
+        }
+
+        let mut random = if deterministic {
+            let mut hasher = Sha256::new();
+            hasher.update(pdf_data);
+            for password in [&self.user_password, &self.owner_password] {
+                hasher.update(password.as_deref().unwrap_or_default().len().to_be_bytes());
+                hasher.update(password.as_deref().unwrap_or_default());
+            }
+            Random::seeded(hasher.finalize().into())
+        } else {
+            Random::system()
+        };
+
+        let file_key: [u8; 32] = random.array()?;
+        let owner_password = match &self.owner_password {
+            Some(password) => password.as_bytes().to_vec(),
+            None => random.array::<32>()?.to_vec(),
+        };
+        let encrypt = self.encryption_dictionary(&file_key, &owner_password, &mut random)?;
+
+        for object in doc.objects.values_mut() {
+            encrypt_object(object, &file_key, &mut random)?;
+        }
+
+        if doc.trailer.get(b"ID").is_err() {
+            let id = Object::String(random.array::<16>()?.to_vec(), StringFormat::Hexadecimal);
+            doc.trailer.set("ID", vec![id.clone(), id]);
+        }
+        // AES-256 is PDF 2.0, or 1.7 with Adobe's extension level 8
+        if doc.version.as_str() < "2.0" {
+            doc.version = "1.7".to_string();
+            let catalog = doc.catalog_mut()?;
+            let mut extensions = match catalog.get(b"Extensions") {
+                Ok(Object::Dictionary(extensions)) => extensions.clone(),
+                _ => Dictionary::new(),
+            };
+            extensions.set("ADBE", dictionary! { "BaseVersion" => Object::Name(b"1.7".to_vec()), "ExtensionLevel" => 8 });
+            catalog.set("Extensions", extensions);
+        }
+        let encrypt_id = doc.add_object(encrypt);
+        doc.trailer.set("Encrypt", encrypt_id);
+
+        let output = save(&mut doc)?;
+        tracing::debug!(
+            event = "pdf_encrypted",
+            algorithm = self.algorithm.as_str(),
+            user_password = self.user_password.is_some(),
+            permissions = ?self.permissions,
+            duration_ms = start.elapsed().as_millis() as u64,
+            "PDF encrypted"
+        );
+        Ok(output)
+    }
+
+    /// The standard security handler, revision 6 (ISO 32000-2, 7.6.4.4).
+    fn encryption_dictionary(&self, file_key: &[u8; 32], owner_password: &[u8], random: &mut Random) -> Result<Dictionary> {
+        let user_password = self.user_password.as_deref().unwrap_or_default().as_bytes();
+
+        // Validation salt, then key salt
+        let user_salts: [u8; 16] = random.array()?;
+        let mut u = hash_2b(user_password, &user_salts[..8], &[]).to_vec();
+        u.extend_from_slice(&user_salts);
+        let ue = aes256_cbc(&hash_2b(user_password, &user_salts[8..], &[]), &[0; 16], file_key);
+
+        let owner_salts: [u8; 16] = random.array()?;
+        let mut o = hash_2b(owner_password, &owner_salts[..8], &u).to_vec();
+        o.extend_from_slice(&owner_salts);
+        let oe = aes256_cbc(&hash_2b(owner_password, &owner_salts[8..], &u), &[0; 16], file_key);
+
+        let p = self.permission_flags();
+        let mut perms = [0u8; 16];
+        perms[..4].copy_from_slice(&p.to_le_bytes());
+        perms[4..8].copy_from_slice(&[0xFF; 4]);
+        // Metadata is encrypted too
+        perms[8] = b'T';
+        perms[9..12].copy_from_slice(b"adb");
+        perms[12..].copy_from_slice(&random.array::<4>()?);
+        let perms = aes256_cbc(file_key, &[0; 16], &perms);
+
+        let hex = |bytes: Vec<u8>| Object::String(bytes, StringFormat::Hexadecimal);
+        Ok(dictionary! {
+            "Filter" => "Standard",
+            "V" => 5,
+            "R" => 6,
+            "Length" => 256,
+            "CF" => dictionary! {
+                "StdCF" => dictionary! { "AuthEvent" => "DocOpen", "CFM" => "AESV3", "Length" => 32 },
+            },
+            "StmF" => "StdCF",
+            "StrF" => "StdCF",
+            "O" => hex(o),
+            "U" => hex(u),
+            "OE" => hex(oe),
+            "UE" => hex(ue),
+            "P" => i64::from(p as i32),
+            "Perms" => hex(perms),
+            "EncryptMetadata" => true,
+        })
+    }
+
+    /// `/P`: the granted bits over the ones the spec requires set.
+    fn permission_flags(&self) -> u32 {
+        // Bits 7-8 and 13-32 are reserved as 1; 10 (accessibility
+        // extraction) is always granted
+        let mut flags = 0xFFFF_F0C0u32 | 1 << 9;
+        for permission in &self.permissions {
+            for bit in permission.bits() {
+                flags |= 1 << (bit - 1);
+            }
+        }
+        flags
+    }
+}
+
+/// Strings and stream data of an indirect object, each with its own IV.
+fn encrypt_object(object: &mut Object, key: &[u8; 32], random: &mut Random) -> Result<()> {
+    match object {
+        Object::String(bytes, format) => {
+            *bytes = aes256_encrypt(key, bytes, random)?;
+            *format = StringFormat::Hexadecimal;
+        }
+        Object::Array(items) => {
+            for item in items {
+                encrypt_object(item, key, random)?;
+            }
+        }
+        Object::Dictionary(dict) => {
+            for (_, value) in dict.iter_mut() {
+                encrypt_object(value, key, random)?;
+            }
+        }
+        Object::Stream(stream) => {
+            // Dropped when the file is written
+            if matches!(stream.dict.get(b"Type"), Ok(Object::Name(name)) if name == b"XRef" || name == b"ObjStm") {
+                return Ok(());
+            }
+            for (_, value) in stream.dict.iter_mut() {
+                encrypt_object(value, key, random)?;
+            }
+            let content = aes256_encrypt(key, &stream.content, random)?;
+            stream.set_content(content);
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// AES-256-CBC with PKCS#7 padding, the IV first, as `AESV3` expects.
+fn aes256_encrypt(key: &[u8; 32], data: &[u8], random: &mut Random) -> Result<Vec<u8>> {
+    let iv: [u8; 16] = random.array()?;
+    let padding = 16 - data.len() % 16;
+    let mut padded = data.to_vec();
+    padded.resize(data.len() + padding, padding as u8);
+    let mut output = iv.to_vec();
+    output.extend(aes256_cbc(key, &iv, &padded));
+    Ok(output)
+}
+
+/// CBC over whole blocks, without padding.
+fn aes256_cbc(key: &[u8], iv: &[u8; 16], data: &[u8]) -> Vec<u8> {
+    cbc(&Aes256::new(GenericArray::from_slice(key)), iv, data)
+}
+
+fn cbc<C: BlockEncrypt>(cipher: &C, iv: &[u8; 16], data: &[u8]) -> Vec<u8> {
+    let mut previous = *iv;
+    let mut output = Vec::with_capacity(data.len());
+    for chunk in data.chunks_exact(16) {
+        let mut block = GenericArray::clone_from_slice(chunk);
+        for (byte, chained) in block.iter_mut().zip(previous) {
+            *byte ^= chained;
+        }
+        cipher.encrypt_block(&mut block);
+        previous.copy_from_slice(&block);
+        output.extend_from_slice(&block);
+    }
+    output
+}
+
+/// Algorithm 2.B: the password hash of revision 6, over `salt` and, for
+/// the owner password, the `/U` string.
+fn hash_2b(password: &[u8], salt: &[u8], user_key: &[u8]) -> [u8; 32] {
+    let mut k = Sha256::new().chain_update(password).chain_update(salt).chain_update(user_key).finalize().to_vec();
+    let mut round = 0u32;
+    loop {
+        let mut k1 = Vec::with_capacity(64 * (password.len() + k.len() + user_key.len()));
+        for _ in 0..64 {
+            k1.extend_from_slice(password);
+            k1.extend_from_slice(&k);
+            k1.extend_from_slice(user_key);
+        }
+        let iv: [u8; 16] = k[16..32].try_into().expect("16 bytes");
+        let e = cbc(&Aes128::new(GenericArray::from_slice(&k[..16])), &iv, &k1);
+        // The first 16 bytes as a big-endian number, modulo 3
+        k = match e[..16].iter().map(|b| u32::from(*b)).sum::<u32>() % 3 {
+            0 => Sha256::digest(&e).to_vec(),
+            1 => Sha384::digest(&e).to_vec(),
+            _ => Sha512::digest(&e).to_vec(),
+        };
+        round += 1;
+        if round >= 64 && u32::from(*e.last().expect("non-empty")) <= round - 32 {
+            break;
+        }
+    }
+    k[..32].try_into().expect("32 bytes")
+}
+
+/// Source of keys, salts and IVs: the system's, or a SHA-256 stream from a
+/// seed for deterministic output.
+enum Random {
+    System(SystemRandom),
+    Seeded { seed: [u8; 32], counter: u64 },
+}
+
+impl Random {
+    fn system() -> Self {
+        Self::System(SystemRandom::new())
+    }
+
+    fn seeded(seed: [u8; 32]) -> Self {
+        Self::Seeded { seed, counter: 0 }
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let mut bytes = [0u8; N];
+        match self {
+            Self::System(rng) => rng
+                .fill(&mut bytes)
+                .map_err(|_| anyhow::anyhow!("Failed to generate random bytes"))?,
+            Self::Seeded { seed, counter } => {
+                for chunk in bytes.chunks_mut(32) {
+                    let block = Sha256::new().chain_update(*seed).chain_update(counter.to_be_bytes()).finalize();
+                    chunk.copy_from_slice(&block[..chunk.len()]);
+                    *counter += 1;
+                }
+            }
+        }
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aes::cipher::BlockDecrypt;
+    use lopdf::content::{Content, Operation};
+    use lopdf::Stream;
+
+    fn hex(text: &str) -> Vec<u8> {
+        (0..text.len()).step_by(2).map(|i| u8::from_str_radix(&text[i..i + 2], 16).unwrap()).collect()
+    }
+
+    /// CBC decryption without padding.
+    fn aes256_cbc_decrypt(key: &[u8], iv: &[u8], data: &[u8]) -> Vec<u8> {
+        let cipher = Aes256::new(GenericArray::from_slice(key));
+        let mut previous = iv.to_vec();
+        let mut output = Vec::new();
+        for chunk in data.chunks_exact(16) {
+            let mut block = GenericArray::clone_from_slice(chunk);
+            cipher.decrypt_block(&mut block);
+            output.extend(block.iter().zip(&previous).map(|(b, p)| b ^ p));
+            previous = chunk.to_vec();
+        }
+        output
+    }
+
+    /// The file key, as a reader derives it from a user or owner password
+    /// (algorithms 2.A and 13 of ISO 32000-2).
+    fn open(encrypt: &Dictionary, password: &str) -> Option<Vec<u8>> {
+        let string = |key: &[u8]| encrypt.get(key).unwrap().as_str().unwrap().to_vec();
+        let (u, ue, o, oe) = (string(b"U"), string(b"UE"), string(b"O"), string(b"OE"));
+        let password = password.as_bytes();
+        let key = if hash_2b(password, &u[32..40], &[]) == u[..32] {
+            aes256_cbc_decrypt(&hash_2b(password, &u[40..48], &[]), &[0; 16], &ue)
+        } else if hash_2b(password, &o[32..40], &u[..48]) == o[..32] {
+            aes256_cbc_decrypt(&hash_2b(password, &o[40..48], &u[..48]), &[0; 16], &oe)
+        } else {
+            return None;
+        };
+
+        let perms = aes256_cbc_decrypt(&key, &[0; 16], &string(b"Perms"));
+        assert_eq!(&perms[9..12], b"adb");
+        let p = encrypt.get(b"P").unwrap().as_i64().unwrap() as i32 as u32;
+        assert_eq!(perms[..4], p.to_le_bytes());
+        Some(key)
+    }
+
+    fn sample_pdf() -> Vec<u8> {
+        let mut doc = Document::with_version("1.7");
+        let content = Content { operations: vec![Operation::new("BT", vec![]), Operation::new("ET", vec![])] };
+        let contents = doc.add_object(Stream::new(dictionary! {}, content.encode().unwrap()));
+        let pages = doc.new_object_id();
+        let page = doc.add_object(dictionary! { "Type" => "Page", "Parent" => pages, "Contents" => contents });
+        doc.objects.insert(pages, Object::Dictionary(dictionary! { "Type" => "Pages", "Kids" => vec![page.into()], "Count" => 1 }));
+        let info = doc.add_object(dictionary! { "Title" => Object::string_literal("Relatório") });
+        let catalog = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages });
+        doc.trailer.set("Root", catalog);
+        doc.trailer.set("Info", info);
+        save(&mut doc).unwrap()
+    }
+
+    fn security(user: Option<&str>, owner: Option<&str>, permissions: Vec<Permission>) -> Security {
+        Security {
+            user_password: user.map(str::to_string),
+            owner_password: owner.map(str::to_string),
+            permissions,
+            algorithm: EncryptionAlgorithm::Aes256,
+        }
+    }
+
+    #[test]
+    fn hash_2b_matches_reference_vectors() {
+        // From an independent implementation of algorithm 2.B
+        let salt: Vec<u8> = (0..16).collect();
+        let user_key: Vec<u8> = (0..48).collect();
+        assert_eq!(
+            hash_2b(b"user", &salt[..8], &[]).to_vec(),
+            hex("731758c09c8b0160a34721d18bdd24220abada0070aa3f05b8103fd5b8d05f17")
+        );
+        assert_eq!(
+            hash_2b(b"owner", &salt[8..], &user_key).to_vec(),
+            hex("400c13628b144fe2fbb850b65729e9ecb63c00fbb817c685725f25de85af0521")
+        );
+        assert_eq!(
+            hash_2b("sénha".as_bytes(), &[0xFF; 8], &[]).to_vec(),
+            hex("f3063dfed13733da41467ee7c19a81e2c3bfb565eb4dc90b341dea2faf24b73b")
+        );
+    }
+
+    #[test]
+    fn opens_with_the_user_or_owner_password_only() {
+        let security = security(Some("user"), Some("owner"), vec![Permission::Print]);
+        let output = security.apply(&sample_pdf(), false).unwrap();
+        let doc = Document::load_mem(&output).unwrap();
+        let encrypt = doc.get_dictionary(doc.trailer.get(b"Encrypt").unwrap().as_reference().unwrap()).unwrap();
+        assert_eq!(encrypt.get(b"R").unwrap().as_i64().unwrap(), 6);
+
+        let user_key = open(encrypt, "user").expect("user password");
+        let owner_key = open(encrypt, "owner").expect("owner password");
+        assert_eq!(user_key, owner_key);
+        assert!(open(encrypt, "wrong").is_none());
+        assert!(open(encrypt, "").is_none());
+
+        // Print only: bits 3 and 12, plus the reserved and accessibility bits
+        let p = encrypt.get(b"P").unwrap().as_i64().unwrap() as i32 as u32;
+        assert_eq!(p, 0xFFFF_F0C0 | 1 << 9 | 1 << 2 | 1 << 11);
+
+        // Strings and streams decrypt with the file key
+        let info = doc.get_dictionary(doc.trailer.get(b"Info").unwrap().as_reference().unwrap()).unwrap();
+        let title = info.get(b"Title").unwrap().as_str().unwrap();
+        let title = aes256_cbc_decrypt(&user_key, &title[..16], &title[16..]);
+        assert_eq!(&title[..title.len() - *title.last().unwrap() as usize], "Relatório".as_bytes());
+        let stream = doc
+            .objects
+            .values()
+            .find_map(|o| o.as_stream().ok().filter(|s| s.dict.get(b"Type").is_err()))
+            .unwrap();
+        let content = aes256_cbc_decrypt(&user_key, &stream.content[..16], &stream.content[16..]);
+        assert!(content.starts_with(b"BT\nET"));
+    }
+
+    #[test]
+    fn opens_without_a_prompt_when_there_is_no_user_password() {
+        let output = security(None, Some("owner"), all_permissions()).apply(&sample_pdf(), true).unwrap();
+        let doc = Document::load_mem(&output).unwrap();
+        let encrypt = doc.get_dictionary(doc.trailer.get(b"Encrypt").unwrap().as_reference().unwrap()).unwrap();
+        assert_eq!(open(encrypt, ""), open(encrypt, "owner"));
+        assert!(open(encrypt, "").is_some());
+        assert!(open(encrypt, "user").is_none());
+
+        // Deterministic output repeats
+        assert_eq!(security(None, Some("owner"), all_permissions()).apply(&sample_pdf(), true).unwrap(), output);
+    }
+}
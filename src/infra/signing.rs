@@ -0,0 +1,700 @@
+use anyhow::Result;
+use chrono::{DateTime, FixedOffset, Utc};
+use lopdf::{dictionary, text_string, Dictionary, Document, IncrementalDocument, Object, ObjectId, Stream, StringFormat};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use crate::infra::cms::{signed_data_certificates, SignaturePolicy, SigningKey};
+use crate::infra::deterministic::Deterministic;
+use crate::infra::dss::LongTermValidation;
+use crate::infra::linearize::is_linearized;
+use crate::infra::pdf_objects::save;
+use crate::infra::truetype::OutlineFont;
+use crate::infra::tsa::{TsaClient, DEFAULT_TOKEN_RESERVE};
+
+const DEFAULT_KEYS_DIR: &str = "/app/signing-keys";
+const DEFAULT_TSA_TIMEOUT_SECS: u64 = 10;
+
+/// Written in `/ByteRange` until the offsets are known; wide enough for any of them.
+const BYTE_RANGE_PLACEHOLDER: i64 = 9_999_999_999;
+/// Environment variable carrying a keystore password to `openssl`.
+const PASSWORD_VAR: &str = "PDFSYNTH_KEYSTORE_PASSWORD";
+const APPEARANCE_FONT: &str = "sans-serif";
+
+#[derive(Error, Debug)]
+pub enum SigningError {
+    /// Options or key selection the request got wrong; reported as a client error.
+    #[error("{0}")]
+    Invalid(String),
+    #[error(transparent)]
+    Internal(#[from] anyhow::Error),
+}
+
+/// PAdES baseline level.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PadesLevel {
+    /// Basic signature.
+    #[default]
+    #[serde(rename = "b-b")]
+    BB,
+    /// With a signature timestamp from the configured TSA.
+    #[serde(rename = "b-t")]
+    BT,
+    /// B-T plus the validation data (certificates and CRLs) and a
+    /// document timestamp over the whole signed file.
+    #[serde(rename = "b-lta")]
+    BLta,
+}
+
+impl PadesLevel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::BB => "b-b",
+            Self::BT => "b-t",
+            Self::BLta => "b-lta",
+        }
+    }
+}
+
+/// Where the visible signature goes, in points from the bottom-left corner
+/// of the page.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct SignatureAppearance {
+    /// 1-based page number.
+    #[serde(default = "default_page")]
+    pub page: usize,
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+fn default_page() -> usize {
+    1
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct SignatureOptions {
+    /// Keystore to sign with; the server default when omitted.
+    #[serde(default)]
+    pub key_id: Option<String>,
+    #[serde(default)]
+    pub level: PadesLevel,
+    #[serde(default)]
+    pub reason: Option<String>,
+    #[serde(default)]
+    pub location: Option<String>,
+    #[serde(default)]
+    pub contact_info: Option<String>,
+    /// Visible signature box; the signature is invisible when omitted.
+    #[serde(default)]
+    pub appearance: Option<SignatureAppearance>,
+}
+
+impl SignatureOptions {
+    pub fn validate(&self) -> Result<()> {
+        if let Some(appearance) = &self.appearance {
+            if appearance.page == 0 {
+                return Err(anyhow::anyhow!("'appearance.page' is 1-based"));
+            }
+            if !(appearance.width > 0.0 && appearance.height > 0.0) {
+                return Err(anyhow::anyhow!("'appearance.width' and 'appearance.height' must be positive"));
+            }
+            if !(appearance.x.is_finite() && appearance.y.is_finite()) {
+                return Err(anyhow::anyhow!("'appearance.x' and 'appearance.y' must be numbers"));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Signs PDFs with the PKCS#12 keystores in the keys directory: each
+/// `<id>.p12` (or `.pfx`) is a key, unlocked by the password in
+/// `<id>.password` when that file exists.
+#[derive(Clone)]
+pub struct Signer {
+    dir: PathBuf,
+    keys: Arc<BTreeMap<String, Arc<SigningKey>>>,
+    default_key: Option<String>,
+    tsa: Option<TsaClient>,
+    policy: Option<SignaturePolicy>,
+}
+
+impl Signer {
+    /// Loads every keystore in `SIGNING_KEYS_DIR`. `SIGNING_DEFAULT_KEY`
+    /// names the key used when a request gives none (the only key when
+    /// there is just one), `SIGNING_TSA_URL` enables PAdES-B-T, and
+    /// `SIGNING_POLICY_OID` with `SIGNING_POLICY_HASH` (hex SHA-256)
+    /// adds a signature policy such as ICP-Brasil's.
+    pub fn from_env() -> Result<Self> {
+        let dir = std::env::var("SIGNING_KEYS_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(|_| PathBuf::from(DEFAULT_KEYS_DIR));
+
+        let mut keys = BTreeMap::new();
+        if dir.is_dir() {
+            let entries = std::fs::read_dir(&dir)
+                .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", dir.display(), e))?;
+            for path in entries.filter_map(|e| e.ok()).map(|e| e.path()) {
+                let is_keystore = path
+                    .extension()
+                    .and_then(|e| e.to_str())
+                    .map(|e| matches!(e.to_ascii_lowercase().as_str(), "p12" | "pfx"))
+                    .unwrap_or(false);
+                let Some(id) = path.file_stem().and_then(|s| s.to_str()).filter(|_| is_keystore) else {
+                    continue;
+                };
+                let key = Self::load_keystore(&path)
+                    .map_err(|e| anyhow::anyhow!("Keystore {}: {}", path.display(), e))?;
+                tracing::info!(
+                    event = "signing_key_loaded",
+                    key_id = %id,
+                    subject = %key.certificate.subject,
+                    not_after = %key.certificate.not_after,
+                    chain_length = key.chain.len(),
+                    "Signing key loaded"
+                );
+                keys.insert(id.to_string(), Arc::new(key));
+            }
+        }
+
+        let default_key = match std::env::var("SIGNING_DEFAULT_KEY").ok().filter(|k| !k.is_empty()) {
+            Some(id) if !keys.contains_key(&id) => {
+                return Err(anyhow::anyhow!("SIGNING_DEFAULT_KEY '{}' is not in {}", id, dir.display()));
+            }
+            Some(id) => Some(id),
+            None if keys.len() == 1 => keys.keys().next().cloned(),
+            None => None,
+        };
+
+        let timeout = std::env::var("SIGNING_TSA_TIMEOUT_SECS")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(DEFAULT_TSA_TIMEOUT_SECS);
+        let tsa = std::env::var("SIGNING_TSA_URL")
+            .ok()
+            .filter(|url| !url.is_empty())
+            .map(|url| TsaClient::new(url, Duration::from_secs(timeout)));
+
+        let policy = match std::env::var("SIGNING_POLICY_OID").ok().filter(|oid| !oid.is_empty()) {
+            Some(oid) => {
+                let hash = std::env::var("SIGNING_POLICY_HASH")
+                    .ok()
+                    .and_then(|h| decode_hex(h.trim()))
+                    .filter(|h| h.len() == 32)
+                    .ok_or_else(|| anyhow::anyhow!("SIGNING_POLICY_OID requires SIGNING_POLICY_HASH, the hex SHA-256 of the policy"))?;
+                let uri = std::env::var("SIGNING_POLICY_URI").ok().filter(|uri| !uri.is_empty());
+                Some(SignaturePolicy { oid, hash, uri })
+            }
+            None => None,
+        };
+
+        Ok(Self { dir, keys: Arc::new(keys), default_key, tsa, policy })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn key_ids(&self) -> Vec<&str> {
+        self.keys.keys().map(String::as_str).collect()
+    }
+
+    pub fn default_key(&self) -> Option<&str> {
+        self.default_key.as_deref()
+    }
+
+    pub fn tsa_url(&self) -> Option<&str> {
+        self.tsa.as_ref().map(TsaClient::url)
+    }
+
+    pub fn has_policy(&self) -> bool {
+        self.policy.is_some()
+    }
+
+    /// Unlocks a keystore with `openssl`, which reads every PKCS#12
+    /// variant in use, including the legacy RC2 ones.
+    fn load_keystore(path: &Path) -> Result<SigningKey> {
+        let password_file = path.with_extension("password");
+        let password = match std::fs::read_to_string(&password_file) {
+            Ok(password) => password.trim_end_matches(['\r', '\n']).to_string(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(anyhow::anyhow!("Failed to read {}: {}", password_file.display(), e)),
+        };
+
+        let run = |legacy: bool| {
+            let mut command = Command::new("openssl");
+            command.arg("pkcs12");
+            if legacy {
+                command.arg("-legacy");
+            }
+            command
+                .arg("-in")
+                .arg(path)
+                .args(["-nodes", "-passin", &format!("env:{}", PASSWORD_VAR)])
+                .env(PASSWORD_VAR, &password)
+                .output()
+                .map_err(|e| anyhow::anyhow!("Failed to execute openssl: {}", e))
+        };
+        let mut output = run(false)?;
+        if !output.status.success() {
+            // OpenSSL 3 needs the legacy provider for RC2 and 3DES keystores
+            let legacy = run(true)?;
+            if legacy.status.success() {
+                output = legacy;
+            }
+        }
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(anyhow::anyhow!(
+                "openssl could not open the keystore (wrong password?): {}",
+                stderr.lines().next().unwrap_or("").trim()
+            ));
+        }
+        SigningKey::from_pem(&String::from_utf8_lossy(&output.stdout))
+    }
+
+    /// The key `options` selects, checked for use right now.
+    pub fn key(&self, options: &SignatureOptions) -> Result<Arc<SigningKey>, SigningError> {
+        if self.keys.is_empty() {
+            return Err(SigningError::Invalid(format!(
+                "Signing is not configured: no keystores in {}",
+                self.dir.display()
+            )));
+        }
+        let id = options
+            .key_id
+            .as_deref()
+            .or(self.default_key.as_deref())
+            .ok_or_else(|| SigningError::Invalid(format!(
+                "'key_id' is required: available keys are {}",
+                self.key_ids().join(", ")
+            )))?;
+        let key = self
+            .keys
+            .get(id)
+            .ok_or_else(|| SigningError::Invalid(format!("Unknown signing key '{}'", id)))?;
+        let now = Utc::now();
+        if key.certificate.not_before > now {
+            return Err(SigningError::Invalid(format!(
+                "The certificate of key '{}' is not valid until {}",
+                id,
+                key.certificate.not_before
+            )));
+        }
+        if key.certificate.not_after < now {
+            return Err(SigningError::Invalid(format!(
+                "The certificate of key '{}' expired on {}",
+                id,
+                key.certificate.not_after
+            )));
+        }
+        if options.level != PadesLevel::BB && self.tsa.is_none() {
+            return Err(SigningError::Invalid(format!(
+                "PAdES-{} needs a time-stamp authority: set SIGNING_TSA_URL",
+                options.level.as_str().to_uppercase()
+            )));
+        }
+        Ok(key.clone())
+    }
+
+    /// Signs `pdf_data` as PAdES with a CAdES detached signature. The
+    /// document is rewritten once with the signature field and placeholders,
+    /// or appended to when linearized, so this must be the last step that
+    /// changes it.
+    pub async fn sign(
+        &self,
+        pdf_data: &[u8],
+        options: &SignatureOptions,
+        signing_time: DateTime<FixedOffset>,
+    ) -> Result<Vec<u8>, SigningError> {
+        let start = Instant::now();
+        options.validate().map_err(|e| SigningError::Invalid(e.to_string()))?;
+        let key = self.key(options)?;
+
+        let doc = Document::load_mem(pdf_data)
+            .map_err(|e| anyhow::anyhow!("Failed to parse PDF: {}", e))?;
+        if doc.is_encrypted() {
+            return Err(SigningError::Invalid("Encrypted PDFs cannot be signed".to_string()));
+        }
+        let pages: Vec<ObjectId> = doc.get_pages().into_values().collect();
+        let page_index = options.appearance.as_ref().map(|a| a.page - 1).unwrap_or(0);
+        let page_id = *pages.get(page_index).ok_or_else(|| {
+            SigningError::Invalid(format!(
+                "'appearance.page' is {} but the document has {} page(s)",
+                page_index + 1,
+                pages.len()
+            ))
+        })?;
+        let appearance = match &options.appearance {
+            Some(appearance) => {
+                // fc-match runs a process
+                let (appearance, task_options, task_key) = (appearance.clone(), options.clone(), key.clone());
+                let ops = tokio::task::spawn_blocking(move || {
+                    Self::appearance_ops(&appearance, &task_options, &task_key, signing_time)
+                })
+                .await
+                .map_err(|e| anyhow::anyhow!("Appearance task failed: {}", e))?;
+                Some(ops.map_err(|e| SigningError::Invalid(e.to_string()))?)
+            }
+            None => None,
+        };
+
+        let timestamped = options.level != PadesLevel::BB;
+        let reserve = key.estimated_size() + if timestamped { DEFAULT_TOKEN_RESERVE } else { 0 };
+        let dictionary = Self::signature_dictionary(options, &key, signing_time, reserve);
+        let (mut output, placeholder) = Self::prepare(pdf_data, doc, page_id, options, appearance, dictionary, reserve)?;
+
+        let signature = key.sign(&placeholder.digest(&output), self.policy.as_ref())?;
+        let token = match &self.tsa {
+            Some(tsa) if timestamped => Some(tsa.timestamp(&Sha256::digest(signature.signature_value())).await?),
+            _ => None,
+        };
+        let cms = signature.to_der(token.as_ref().map(|t| t.der.as_slice()));
+        placeholder.fill(&mut output, &cms)?;
+
+        tracing::debug!(
+            event = "pdf_signed",
+            subject = %key.certificate.subject,
+            level = options.level.as_str(),
+            visible = options.appearance.is_some(),
+            signature_size_bytes = cms.len(),
+            timestamp = ?token.as_ref().map(|t| t.time),
+            duration_ms = start.elapsed().as_millis() as u64,
+            "PDF signed"
+        );
+
+        match (&self.tsa, options.level) {
+            (Some(tsa), PadesLevel::BLta) => {
+                let mut certificates = vec![key.certificate.clone()];
+                certificates.extend(key.chain.iter().cloned());
+                if let Some(token) = &token {
+                    certificates.extend(signed_data_certificates(&token.der)?);
+                }
+                Ok(LongTermValidation::new(tsa).apply(output, certificates).await?)
+            }
+            _ => Ok(output),
+        }
+    }
+
+    /// Writes the signature field with a `/ByteRange` already pointing
+    /// around a zero-filled `/Contents` of `reserve` bytes. A linearized
+    /// file gets the field as an incremental update, keeping its layout.
+    fn prepare(
+        pdf_data: &[u8],
+        mut doc: Document,
+        page_id: ObjectId,
+        options: &SignatureOptions,
+        appearance: Option<String>,
+        signature: Dictionary,
+        reserve: usize,
+    ) -> Result<(Vec<u8>, Placeholder)> {
+        if !is_linearized(pdf_data) {
+            Self::add_signature_field(&mut doc, page_id, options, appearance, signature)?;
+            let mut output = save(&mut doc)?;
+            let placeholder = Placeholder::locate(&mut output, 0, reserve)?;
+            return Ok((output, placeholder));
+        }
+
+        let version = doc.version.clone();
+        let mut update = IncrementalDocument::create_from(pdf_data.to_vec(), doc);
+        // lopdf would otherwise mark the update as 1.4
+        update.new_document.version = version;
+        Self::clone_for_field(&mut update, page_id)?;
+        Self::add_signature_field(&mut update.new_document, page_id, options, appearance, signature)?;
+        let mut output = Vec::new();
+        update
+            .save_to(&mut output)
+            .map_err(|e| anyhow::anyhow!("Failed to write the incremental update: {}", e))?;
+        let placeholder = Placeholder::locate(&mut output, pdf_data.len(), reserve)?;
+        Ok((output, placeholder))
+    }
+
+    /// The `/Sig` dictionary, its byte range and contents still placeholders.
+    fn signature_dictionary(
+        options: &SignatureOptions,
+        key: &SigningKey,
+        signing_time: DateTime<FixedOffset>,
+        reserve: usize,
+    ) -> Dictionary {
+        let mut signature = dictionary! {
+            "Type" => "Sig",
+            "Filter" => "Adobe.PPKLite",
+            "SubFilter" => "ETSI.CAdES.detached",
+            "M" => Object::string_literal(Deterministic::pdf_date(signing_time)),
+            "Name" => text_string(&key.certificate.subject),
+        };
+        for (name, value) in [("Reason", &options.reason), ("Location", &options.location), ("ContactInfo", &options.contact_info)] {
+            if let Some(value) = value {
+                signature.set(name, text_string(value));
+            }
+        }
+        signature.set("ByteRange", Placeholder::byte_range());
+        signature.set("Contents", Placeholder::contents(reserve));
+        signature
+    }
+
+    fn add_signature_field(
+        doc: &mut Document,
+        page_id: ObjectId,
+        options: &SignatureOptions,
+        appearance: Option<String>,
+        signature: Dictionary,
+    ) -> Result<()> {
+        let signature_id = doc.add_object(signature);
+
+        let rect = match &options.appearance {
+            Some(a) => [a.x, a.y, a.x + a.width, a.y + a.height],
+            // Invisible: a zero-size widget with an empty appearance
+            None => [0.0; 4],
+        };
+        let form = Stream::new(
+            dictionary! {
+                "Type" => "XObject",
+                "Subtype" => "Form",
+                "BBox" => vec![0.into(), 0.into(), (rect[2] - rect[0]).into(), (rect[3] - rect[1]).into()],
+                "Resources" => dictionary! {},
+            },
+            appearance.unwrap_or_default().into_bytes(),
+        );
+        let form_id = doc.add_object(form);
+
+        let field_count = Self::field_count(doc);
+        let widget_id = doc.add_object(dictionary! {
+            "Type" => "Annot",
+            "Subtype" => "Widget",
+            "FT" => "Sig",
+            "T" => text_string(&format!("Signature{}", field_count + 1)),
+            "V" => signature_id,
+            // Print and Locked
+            "F" => 132,
+            "P" => page_id,
+            "Rect" => rect.iter().map(|v| Object::Real(*v)).collect::<Vec<_>>(),
+            "AP" => dictionary! { "N" => form_id },
+        });
+
+        Self::append_annotation(doc, page_id, widget_id)?;
+        Self::add_field(doc, widget_id)
+    }
+
+    /// Copies into `update` the objects a new signature field changes: the
+    /// catalog, `page_id` with its annotations, and the form with its fields.
+    pub(crate) fn clone_for_field(update: &mut IncrementalDocument, page_id: ObjectId) -> Result<()> {
+        let previous = update.get_prev_documents();
+        let catalog_id = previous.trailer.get(b"Root")?.as_reference()?;
+        let mut ids = vec![catalog_id, page_id];
+        if let Ok(Object::Reference(id)) = previous.get_dictionary(page_id)?.get(b"Annots") {
+            ids.push(*id);
+        }
+        if let Ok(Object::Reference(form_id)) = previous.catalog()?.get(b"AcroForm") {
+            ids.push(*form_id);
+            if let Ok(Object::Reference(id)) = previous.get_dictionary(*form_id)?.get(b"Fields") {
+                ids.push(*id);
+            }
+        }
+        for id in ids {
+            update.opt_clone_object_to_new_document(id)?;
+        }
+        Ok(())
+    }
+
+    /// Path operators for the visible signature: signer, time and the
+    /// optional reason and location, scaled to fit the box.
+    fn appearance_ops(
+        appearance: &SignatureAppearance,
+        options: &SignatureOptions,
+        key: &SigningKey,
+        signing_time: DateTime<FixedOffset>,
+    ) -> Result<String> {
+        let font = OutlineFont::find(APPEARANCE_FONT)?;
+        let mut lines = vec![
+            format!("Digitally signed by {}", key.certificate.subject),
+            format!("Date: {}", signing_time.format("%Y-%m-%d %H:%M:%S %:z")),
+        ];
+        if let Some(reason) = &options.reason {
+            lines.push(format!("Reason: {}", reason));
+        }
+        if let Some(location) = &options.location {
+            lines.push(format!("Location: {}", location));
+        }
+
+        let padding = (appearance.width.min(appearance.height) * 0.08).min(6.0);
+        let inner_width = appearance.width - 2.0 * padding;
+        let inner_height = appearance.height - 2.0 * padding;
+        let widest = lines.iter().map(|l| font.text_width(l, 1.0)).fold(0.0, f32::max);
+        let size = (inner_height / (lines.len() as f32 * 1.2))
+            .min(if widest > 0.0 { inner_width / widest } else { f32::MAX })
+            .max(0.0);
+        let ascent = font.ascender() / font.units_per_em() * size;
+
+        let mut ops = String::new();
+        let _ = writeln!(
+            ops,
+            "q 0.5 w 0.4 G 0.25 0.25 {:.2} {:.2} re S Q",
+            appearance.width - 0.5,
+            appearance.height - 0.5
+        );
+        ops.push_str("0 g\n");
+        for (index, line) in lines.iter().enumerate() {
+            let baseline = appearance.height - padding - ascent - index as f32 * size * 1.2;
+            font.draw_text(line, size, padding, baseline, &mut ops)
+                .map_err(|e| anyhow::anyhow!("Signature appearance: {}", e))?;
+        }
+        ops.push_str("f\n");
+        Ok(ops)
+    }
+
+    pub(crate) fn field_count(doc: &Document) -> usize {
+        doc.catalog()
+            .ok()
+            .and_then(|c| c.get(b"AcroForm").ok())
+            .and_then(|f| doc.dereference(f).ok())
+            .and_then(|(_, f)| f.as_dict().ok())
+            .and_then(|f| f.get(b"Fields").ok())
+            .and_then(|f| doc.dereference(f).ok())
+            .and_then(|(_, f)| f.as_array().ok())
+            .map(Vec::len)
+            .unwrap_or(0)
+    }
+
+    pub(crate) fn append_annotation(doc: &mut Document, page_id: ObjectId, widget_id: ObjectId) -> Result<()> {
+        let annots = doc.get_dictionary(page_id)?.get(b"Annots").ok().cloned();
+        match annots {
+            Some(Object::Reference(id)) => {
+                if let Object::Array(annots) = doc.get_object_mut(id)? {
+                    annots.push(widget_id.into());
+                }
+            }
+            Some(Object::Array(mut annots)) => {
+                annots.push(widget_id.into());
+                doc.get_dictionary_mut(page_id)?.set("Annots", annots);
+            }
+            _ => {
+                doc.get_dictionary_mut(page_id)?.set("Annots", vec![widget_id.into()]);
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds the field to the form, creating it, with `SigFlags` marking
+    /// the document as signed and append-only.
+    pub(crate) fn add_field(doc: &mut Document, widget_id: ObjectId) -> Result<()> {
+        let form = doc.catalog()?.get(b"AcroForm").ok().cloned();
+        let form_id = match form {
+            Some(Object::Reference(id)) => id,
+            Some(Object::Dictionary(form)) => {
+                let id = doc.add_object(form);
+                doc.catalog_mut()?.set("AcroForm", id);
+                id
+            }
+            _ => {
+                let id = doc.add_object(dictionary! { "Fields" => Vec::<Object>::new() });
+                doc.catalog_mut()?.set("AcroForm", id);
+                id
+            }
+        };
+
+        let fields = doc.get_dictionary(form_id)?.get(b"Fields").ok().cloned();
+        let mut fields = match fields {
+            Some(Object::Reference(id)) => doc.get_object(id)?.as_array()?.clone(),
+            Some(Object::Array(fields)) => fields,
+            _ => Vec::new(),
+        };
+        fields.push(widget_id.into());
+        let form = doc.get_dictionary_mut(form_id)?;
+        form.set("Fields", fields);
+        form.set("SigFlags", 3);
+        // Incompatible with PDF/A and with a fixed appearance
+        form.remove(b"NeedAppearances");
+        Ok(())
+    }
+}
+
+/// The `/ByteRange` and `/Contents` of a signature dictionary in a
+/// serialized file, the contents still zero-filled.
+pub struct Placeholder {
+    /// The hex string, angle brackets included.
+    contents: Range<usize>,
+}
+
+impl Placeholder {
+    pub fn byte_range() -> Object {
+        vec![0.into(), BYTE_RANGE_PLACEHOLDER.into(), BYTE_RANGE_PLACEHOLDER.into(), BYTE_RANGE_PLACEHOLDER.into()].into()
+    }
+
+    /// Zero-filled `/Contents` for a CMS of up to `reserve` bytes.
+    pub fn contents(reserve: usize) -> Object {
+        Object::String(vec![0; reserve], StringFormat::Hexadecimal)
+    }
+
+    /// Finds the placeholders written at or after `from` and sets the
+    /// byte range around the contents, padded to keep every offset.
+    pub fn locate(output: &mut [u8], from: usize, reserve: usize) -> Result<Self> {
+        let contents_hex = format!("<{}>", "0".repeat(reserve * 2));
+        let start = find(&output[from..], contents_hex.as_bytes())
+            .map(|at| from + at)
+            .ok_or_else(|| anyhow::anyhow!("Signature placeholder not found in the output"))?;
+        let end = start + contents_hex.len();
+
+        let placeholder = format!("[0 {0} {0} {0}]", BYTE_RANGE_PLACEHOLDER);
+        let range_start = find(&output[from..], placeholder.as_bytes())
+            .map(|at| from + at)
+            .ok_or_else(|| anyhow::anyhow!("Byte range placeholder not found in the output"))?;
+        let byte_range = format!("[0 {} {} {}]", start, end, output.len() - end);
+        let padded = format!("{:<width$}", byte_range, width = placeholder.len());
+        output[range_start..range_start + padded.len()].copy_from_slice(padded.as_bytes());
+
+        Ok(Self { contents: start..end })
+    }
+
+    /// SHA-256 of the signed bytes: everything but the contents.
+    pub fn digest(&self, output: &[u8]) -> Vec<u8> {
+        let mut hasher = Sha256::new();
+        hasher.update(&output[..self.contents.start]);
+        hasher.update(&output[self.contents.end..]);
+        hasher.finalize().to_vec()
+    }
+
+    /// Writes `der` as hex into the contents.
+    pub fn fill(&self, output: &mut [u8], der: &[u8]) -> Result<()> {
+        // Between the angle brackets
+        let capacity = self.contents.len() - 2;
+        if der.len() * 2 > capacity {
+            return Err(anyhow::anyhow!(
+                "The signature ({} bytes) does not fit the {} bytes reserved for it",
+                der.len(),
+                capacity / 2
+            ));
+        }
+        let mut hex = String::with_capacity(der.len() * 2);
+        for byte in der {
+            let _ = write!(hex, "{:02X}", byte);
+        }
+        let start = self.contents.start + 1;
+        output[start..start + hex.len()].copy_from_slice(hex.as_bytes());
+        Ok(())
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
@@ -0,0 +1,301 @@
+use anyhow::Result;
+use lopdf::{Document, Object, ObjectId};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::ops::Range;
+use std::time::Instant;
+use thiserror::Error;
+use crate::infra::outline::{Outline, OutlineEntry};
+use crate::infra::pdf_objects::{flatten_inherited, metadata_id, read_xmp, resolve_named_destinations, save, write_xmp};
+use crate::infra::xmp::replace_property;
+
+/// Longest file name stem derived from a bookmark title.
+const MAX_NAME_CHARS: usize = 60;
+
+/// How a document is cut into parts.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum SplitMode {
+    /// One part per range: `"3"`, `"1-4"` or `"7-"` (to the end), 1-based.
+    Ranges(Vec<String>),
+    /// Consecutive parts of this many pages.
+    Every(usize),
+    /// A part starts at each bookmark down to `level` (1 = top level).
+    Bookmarks {
+        #[serde(default = "default_level")]
+        level: u8,
+    },
+}
+
+fn default_level() -> u8 {
+    1
+}
+
+/// One output document.
+#[derive(Debug, Clone)]
+pub struct SplitPart {
+    /// File name, unique within the split.
+    pub name: String,
+    /// 0-based page range in the source document.
+    pub pages: Range<usize>,
+    pub data: Vec<u8>,
+}
+
+#[derive(Error, Debug)]
+pub enum SplitError {
+    /// The document or the mode cannot be split; reported as a client error.
+    #[error("{0}")]
+    Invalid(String),
+    #[error(transparent)]
+    Internal(#[from] anyhow::Error),
+}
+
+pub struct PdfSplitter;
+
+impl PdfSplitter {
+    pub fn split(pdf_data: &[u8], mode: &SplitMode) -> Result<Vec<SplitPart>, SplitError> {
+        let start = Instant::now();
+        let mut doc = Document::load_mem(pdf_data)
+            .map_err(|e| SplitError::Invalid(format!("Failed to parse PDF: {}", e)))?;
+        if doc.is_encrypted() {
+            return Err(SplitError::Invalid("Encrypted PDFs cannot be split".to_string()));
+        }
+
+        // Named destinations would point at pages a part may not have
+        resolve_named_destinations(&mut doc);
+        flatten_inherited(&mut doc);
+        let normalized = save(&mut doc)?;
+        let entries = Outline::extract(&normalized)?;
+        let page_count = doc.get_pages().len();
+
+        let segments = Self::segments(mode, page_count, &entries).map_err(|e| SplitError::Invalid(e.to_string()))?;
+        let mut parts = Vec::with_capacity(segments.len());
+        for (name, pages) in segments {
+            let data = Self::extract(&doc, pages.clone(), &entries)?;
+            parts.push(SplitPart { name, pages, data });
+        }
+
+        tracing::debug!(
+            event = "pdf_split",
+            page_count = page_count,
+            part_count = parts.len(),
+            duration_ms = start.elapsed().as_millis() as u64,
+            "PDF split"
+        );
+
+        Ok(parts)
+    }
+
+    /// Named page ranges for `mode`.
+    fn segments(mode: &SplitMode, page_count: usize, entries: &[OutlineEntry]) -> Result<Vec<(String, Range<usize>)>> {
+        let range_name = |pages: &Range<usize>| format!("pages-{}-{}.pdf", pages.start + 1, pages.end);
+
+        let segments = match mode {
+            SplitMode::Ranges(ranges) => {
+                if ranges.is_empty() {
+                    return Err(anyhow::anyhow!("'ranges' must not be empty"));
+                }
+                let parsed = ranges
+                    .iter()
+                    .map(|r| Self::parse_range(r, page_count))
+                    .collect::<Result<Vec<_>>>()?;
+                // Equal ranges would produce the same file name
+                for (index, pages) in parsed.iter().enumerate() {
+                    if let Some(first) = parsed[..index].iter().position(|other| other == pages) {
+                        return Err(anyhow::anyhow!(
+                            "Ranges '{}' and '{}' select the same pages",
+                            ranges[first],
+                            ranges[index]
+                        ));
+                    }
+                }
+                parsed.into_iter().map(|pages| (range_name(&pages), pages)).collect()
+            }
+            SplitMode::Every(size) => {
+                if *size == 0 {
+                    return Err(anyhow::anyhow!("'every' must be at least 1"));
+                }
+                (0..page_count)
+                    .step_by(*size)
+                    .map(|first| first..(first + size).min(page_count))
+                    .map(|pages| (range_name(&pages), pages))
+                    .collect()
+            }
+            SplitMode::Bookmarks { level } => {
+                let mut starts: Vec<(usize, &str)> = Vec::new();
+                for entry in entries.iter().filter(|e| e.level <= *level && e.page_index < page_count) {
+                    // Several bookmarks on one page start a single part
+                    if !starts.iter().any(|(page, _)| *page == entry.page_index) {
+                        starts.push((entry.page_index, entry.title.as_str()));
+                    }
+                }
+                if starts.is_empty() {
+                    return Err(anyhow::anyhow!("The document has no bookmarks at level {} or above", level));
+                }
+                starts.sort_by_key(|(page, _)| *page);
+                // Pages before the first bookmark form their own part
+                if starts[0].0 > 0 {
+                    starts.insert(0, (0, "front-matter"));
+                }
+
+                starts
+                    .iter()
+                    .enumerate()
+                    .map(|(index, (first, title))| {
+                        let end = starts.get(index + 1).map(|(next, _)| *next).unwrap_or(page_count);
+                        (format!("{:02}-{}.pdf", index + 1, Self::slug(title)), *first..end)
+                    })
+                    .collect()
+            }
+        };
+
+        Ok(segments)
+    }
+
+    /// Parses a 1-based, inclusive range such as `"3"`, `"1-4"` or `"7-"`.
+    pub fn parse_range(range: &str, page_count: usize) -> Result<Range<usize>> {
+        let invalid = || anyhow::anyhow!("Invalid page range '{}': use '3', '1-4' or '7-'", range);
+        let page = |text: &str| text.trim().parse::<usize>().map_err(|_| invalid());
+
+        let (first, last) = match range.split_once('-') {
+            Some((first, "")) => (page(first)?, page_count),
+            Some((first, last)) => (page(first)?, page(last)?),
+            None => {
+                let page = page(range)?;
+                (page, page)
+            }
+        };
+        if first == 0 || first > last {
+            return Err(invalid());
+        }
+        if last > page_count {
+            return Err(anyhow::anyhow!(
+                "Page range '{}' is outside the document, which has {} page(s)",
+                range,
+                page_count
+            ));
+        }
+        Ok(first - 1..last)
+    }
+
+    /// Copies `doc` keeping only the pages in `range`. Links and bookmarks
+    /// into other pages are dropped; structure trees and forms, which span
+    /// the whole document, are removed.
+    fn extract(doc: &Document, range: Range<usize>, entries: &[OutlineEntry]) -> Result<Vec<u8>> {
+        let mut part = doc.clone();
+        let pages: Vec<ObjectId> = part.get_pages().into_values().collect();
+        let kept = pages[range.clone()].to_vec();
+        let kept_set: HashSet<ObjectId> = kept.iter().copied().collect();
+        let all_pages: HashSet<ObjectId> = pages.iter().copied().collect();
+
+        let catalog = part.catalog_mut()?;
+        for key in [&b"Outlines"[..], b"StructTreeRoot", b"MarkInfo", b"AcroForm", b"OpenAction", b"Dests"] {
+            catalog.remove(key);
+        }
+        let pages_id = catalog.get(b"Pages").and_then(Object::as_reference)?;
+        // Already resolved; the name tree would keep every page alive
+        let names_id = match catalog.get_mut(b"Names") {
+            Ok(Object::Dictionary(names)) => {
+                names.remove(b"Dests");
+                None
+            }
+            Ok(Object::Reference(id)) => Some(*id),
+            _ => None,
+        };
+        if let Some(names) = names_id.and_then(|id| part.get_dictionary_mut(id).ok()) {
+            names.remove(b"Dests");
+        }
+
+        for page_id in &kept {
+            let annots = part
+                .get_dictionary(*page_id)?
+                .get(b"Annots")
+                .ok()
+                .and_then(|a| part.dereference(a).ok())
+                .and_then(|(_, a)| a.as_array().ok().cloned());
+            // Links into pages of another part would dangle
+            let annots = annots.map(|annots| {
+                annots
+                    .into_iter()
+                    .filter(|annot| match Self::link_target(&part, annot) {
+                        Some(target) => !all_pages.contains(&target) || kept_set.contains(&target),
+                        None => true,
+                    })
+                    .collect::<Vec<_>>()
+            });
+
+            let page = part.get_dictionary_mut(*page_id)?;
+            page.set("Parent", pages_id);
+            page.remove(b"StructParents");
+            if let Some(annots) = annots {
+                page.set("Annots", annots);
+            }
+        }
+
+        let page_tree = part.get_dictionary_mut(pages_id)?;
+        page_tree.set("Kids", kept.iter().map(|id| Object::Reference(*id)).collect::<Vec<_>>());
+        page_tree.set("Count", kept.len() as i64);
+        for key in [&b"Parent"[..], b"Resources", b"MediaBox", b"CropBox", b"Rotate"] {
+            page_tree.remove(key);
+        }
+
+        Self::downgrade_conformance(&mut part)?;
+        part.prune_objects();
+        let data = save(&mut part)?;
+
+        let entries: Vec<OutlineEntry> = entries
+            .iter()
+            .filter(|e| range.contains(&e.page_index))
+            .map(|e| OutlineEntry { page_index: e.page_index - range.start, ..e.clone() })
+            .collect();
+        if entries.is_empty() {
+            return Ok(data);
+        }
+        Outline::apply(&data, &entries)
+    }
+
+    /// PDF/A level A requires the structure tree the part no longer has, so
+    /// a part of a `1a`, `2a` or `3a` document claims level B instead.
+    fn downgrade_conformance(part: &mut Document) -> Result<()> {
+        let Some(id) = metadata_id(part) else {
+            return Ok(());
+        };
+        let xmp = read_xmp(part, id)?;
+        let level_a = ["pdfaid:conformance>A<", "pdfaid:conformance='A'", "pdfaid:conformance=\"A\""]
+            .iter()
+            .any(|claim| xmp.contains(claim));
+        if level_a {
+            write_xmp(part, id, replace_property(&xmp, "pdfaid:conformance", "B"))?;
+        }
+        Ok(())
+    }
+
+    /// Page targeted by a link annotation, from `/Dest` or a GoTo action.
+    fn link_target(doc: &Document, annot: &Object) -> Option<ObjectId> {
+        let annot = doc.dereference(annot).ok()?.1.as_dict().ok()?;
+        let dest = match annot.get(b"Dest") {
+            Ok(dest) => dest,
+            Err(_) => {
+                let action = doc.dereference(annot.get(b"A").ok()?).ok()?.1.as_dict().ok()?;
+                action.get(b"D").ok()?
+            }
+        };
+        doc.dereference(dest).ok()?.1.as_array().ok()?.first()?.as_reference().ok()
+    }
+
+    fn slug(title: &str) -> String {
+        let slug: String = title
+            .chars()
+            .flat_map(char::to_lowercase)
+            .map(|c| if c.is_alphanumeric() { c } else { '-' })
+            .collect::<String>()
+            .split('-')
+            .filter(|s| !s.is_empty())
+            .collect::<Vec<_>>()
+            .join("-")
+            .chars()
+            .take(MAX_NAME_CHARS)
+            .collect();
+        if slug.is_empty() { "untitled".to_string() } else { slug }
+    }
+}
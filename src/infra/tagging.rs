@@ -0,0 +1,166 @@
+use anyhow::Result;
+use lopdf::{dictionary, Document, Object, Stream, StringFormat};
+use sha2::{Digest, Sha256};
+use std::path::Path;
+use std::time::Instant;
+use thiserror::Error;
+use crate::infra::pdf_objects::{save, uses_transparency};
+use crate::infra::pdfa::PdfaLevel;
+use crate::infra::xmp::XmpPacket;
+
+#[derive(Error, Debug)]
+pub enum TaggingError {
+    /// The page cannot meet the requested level; reported as a client error.
+    #[error("{0}")]
+    Invalid(String),
+    #[error(transparent)]
+    Internal(#[from] anyhow::Error),
+}
+
+/// Finishing steps for tagged PDFs produced by Chrome's `generateTaggedPDF`.
+///
+/// Ghostscript's pdfwrite re-interprets every content stream and drops the
+/// marked-content operators and the `/StructTreeRoot` they point into, so a
+/// tagged document cannot go through [`Ghostscript::convert_to_pdfa`]. Instead
+/// the catalog is edited in place and, for PDF/A, the OutputIntent and XMP
+/// identification are added natively. Nothing Ghostscript would fix on the
+/// way (transparency, fonts) is changed, so a page that cannot meet the
+/// level is rejected rather than mislabeled.
+///
+/// [`Ghostscript::convert_to_pdfa`]: crate::infra::ghostscript::Ghostscript::convert_to_pdfa
+pub struct TaggedPdf;
+
+impl TaggedPdf {
+    /// Sets `/Lang`, `/MarkInfo` and `/ViewerPreferences` on a tagged PDF.
+    pub fn finalize(pdf_data: &[u8], lang: Option<&str>) -> Result<Vec<u8>> {
+        let mut doc = Self::load_tagged(pdf_data)?;
+        Self::mark_catalog(&mut doc, lang)?;
+        save(&mut doc)
+    }
+
+    /// Produces a PDF/A document from a tagged PDF without rewriting its
+    /// content streams. `icc_profile` is the sRGB profile of the OutputIntent.
+    pub fn convert_to_pdfa(pdf_data: &[u8], lang: Option<&str>, level: PdfaLevel, icc_profile: &Path) -> Result<Vec<u8>, TaggingError> {
+        let start = Instant::now();
+
+        tracing::debug!(
+            event = "tagged_pdfa_started",
+            input_size_bytes = pdf_data.len(),
+            pdf_a_level = level.as_str(),
+            "Starting native PDF/A conversion of tagged PDF"
+        );
+
+        let mut doc = Self::load_tagged(pdf_data)?;
+        Self::mark_catalog(&mut doc, lang)?;
+
+        if !level.allows_transparency() && Self::has_transparency(&doc) {
+            return Err(TaggingError::Invalid(format!(
+                "The page uses transparency (opacity, shadows, blend modes or images with alpha), which PDF/A-{} forbids. \
+                 Tagged output is not passed through Ghostscript, which would drop the structure tree, so it cannot be flattened; \
+                 use pdf_a_level '2a' or '2b', or remove the transparency from the template",
+                level.as_str()
+            )));
+        }
+
+        doc.version = level.pdf_version().to_string();
+
+        let icc = std::fs::read(icc_profile)
+            .map_err(|e| anyhow::anyhow!("Failed to read ICC profile {}: {}", icc_profile.display(), e))?;
+        let icc_id = doc.add_object(Stream::new(dictionary! { "N" => 3 }, icc));
+        let output_intent = dictionary! {
+            "Type" => "OutputIntent",
+            "S" => "GTS_PDFA1",
+            "OutputConditionIdentifier" => Object::string_literal("sRGB"),
+            "OutputCondition" => Object::string_literal("sRGB IEC61966-2.1"),
+            "RegistryName" => Object::string_literal("http://www.color.org"),
+            "Info" => Object::string_literal("sRGB IEC61966-2.1"),
+            "DestOutputProfile" => icc_id,
+        };
+
+        let xmp = XmpPacket::from_info(&mut doc, lang, Some((level.part(), level.conformance())));
+        // PDF/A-1 forbids filters on the metadata stream
+        let mut metadata = Stream::new(
+            dictionary! { "Type" => "Metadata", "Subtype" => "XML" },
+            xmp.to_xml().into_bytes(),
+        );
+        metadata.allows_compression = false;
+        let metadata_id = doc.add_object(metadata);
+
+        let catalog = doc.catalog_mut().map_err(anyhow::Error::from)?;
+        catalog.set("OutputIntents", vec![Object::Dictionary(output_intent)]);
+        catalog.set("Metadata", metadata_id);
+
+        if doc.trailer.get(b"ID").is_err() {
+            let id = Object::String(Self::document_id(pdf_data), StringFormat::Hexadecimal);
+            doc.trailer.set("ID", vec![id.clone(), id]);
+        }
+
+        let output = save(&mut doc)?;
+        let duration = start.elapsed();
+
+        tracing::debug!(
+            event = "tagged_pdfa_complete",
+            duration_ms = duration.as_millis() as u64,
+            input_size_bytes = pdf_data.len(),
+            output_size_bytes = output.len(),
+            pdf_a_level = level.as_str(),
+            "Native PDF/A conversion completed"
+        );
+
+        Ok(output)
+    }
+
+    /// Reads the `lang` attribute of the root `<html>` element.
+    pub fn html_lang(html: &str) -> Option<String> {
+        let lower = html.to_ascii_lowercase();
+        let tag_start = lower.find("<html")?;
+        let tag_end = tag_start + lower[tag_start..].find('>')?;
+        let tag = &html[tag_start..tag_end];
+        let attr = lower[tag_start..tag_end].find("lang=")?;
+        let value = &tag[attr + "lang=".len()..];
+        let value = value.trim_start_matches(['"', '\'']);
+        let lang: String = value
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric() || *c == '-')
+            .collect();
+        (!lang.is_empty()).then_some(lang)
+    }
+
+    fn load_tagged(pdf_data: &[u8]) -> Result<Document> {
+        let doc = Document::load_mem(pdf_data)
+            .map_err(|e| anyhow::anyhow!("Failed to parse PDF: {}", e))?;
+        if doc.catalog()?.get(b"StructTreeRoot").is_err() {
+            return Err(anyhow::anyhow!("PDF has no structure tree; was it printed with tagging enabled?"));
+        }
+        Ok(doc)
+    }
+
+    fn mark_catalog(doc: &mut Document, lang: Option<&str>) -> Result<()> {
+        let catalog = doc.catalog_mut()?;
+        catalog.set("MarkInfo", dictionary! { "Marked" => true });
+        catalog.set("ViewerPreferences", dictionary! { "DisplayDocTitle" => true });
+        match lang {
+            Some(lang) => catalog.set("Lang", Object::string_literal(lang)),
+            None if catalog.get(b"Lang").is_err() => {
+                tracing::warn!(
+                    event = "tagged_pdf_missing_lang",
+                    "No document language given; set the html lang attribute or the lang option"
+                );
+            }
+            None => {}
+        }
+        Ok(())
+    }
+
+    fn has_transparency(doc: &Document) -> bool {
+        doc.objects.values().any(|object| match object {
+            Object::Dictionary(dict) => uses_transparency(doc, dict),
+            Object::Stream(stream) => uses_transparency(doc, &stream.dict),
+            _ => false,
+        })
+    }
+
+    fn document_id(pdf_data: &[u8]) -> Vec<u8> {
+        Sha256::digest(pdf_data)[..16].to_vec()
+    }
+}
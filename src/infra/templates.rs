@@ -1,4 +1,6 @@
+use chrono::{DateTime, FixedOffset, Utc};
 use tera::Tera;
+use std::collections::HashMap;
 use std::time::Instant;
 use std::error::Error;
 
@@ -11,7 +13,14 @@ impl TemplateEngine {
         Ok(Self)
     }
 
-    pub fn render(&self, template_str: &str, context: &tera::Context) -> std::result::Result<String, tera::Error> {
+    /// Renders `template_str`. With `now` set, the template `now()` function
+    /// returns that instant instead of the wall clock.
+    pub fn render(
+        &self,
+        template_str: &str,
+        context: &tera::Context,
+        now: Option<DateTime<FixedOffset>>,
+    ) -> std::result::Result<String, tera::Error> {
         let start = Instant::now();
         let template_size = template_str.len();
         
@@ -36,7 +45,12 @@ impl TemplateEngine {
             "Template content preview"
         );
 
-        match Tera::one_off(template_str, context, false) {
+        let mut tera = Tera::default();
+        if let Some(now) = now {
+            tera.register_function("now", Self::pinned_now(now));
+        }
+
+        match tera.render_str(template_str, context) {
             Ok(result) => {
                 let duration = start.elapsed();
                 tracing::info!(
@@ -79,4 +93,25 @@ impl TemplateEngine {
             }
         }
     }
+
+    fn pinned_now(now: DateTime<FixedOffset>) -> impl tera::Function {
+        move |args: &HashMap<String, tera::Value>| -> tera::Result<tera::Value> {
+            let flag = |name: &str| match args.get(name) {
+                Some(value) => value.as_bool().ok_or_else(|| {
+                    tera::Error::msg(format!("Function `now` received {}={} but `{}` can only be a boolean", name, value, name))
+                }),
+                None => Ok(false),
+            };
+
+            if flag("timestamp")? {
+                return Ok(tera::Value::from(now.timestamp()));
+            }
+            let formatted = if flag("utc")? {
+                now.with_timezone(&Utc).to_rfc3339()
+            } else {
+                now.to_rfc3339()
+            };
+            Ok(tera::Value::from(formatted))
+        }
+    }
 }
@@ -0,0 +1,664 @@
+use anyhow::Result;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::path::Path;
+
+/// Components nested deeper than this are ignored.
+const MAX_COMPOSITE_DEPTH: u8 = 8;
+
+/// A TrueType (`glyf`) font read just far enough to turn text into paths.
+///
+/// Drawing outlines instead of embedding the font keeps watermarks small
+/// and out of text extraction, and needs no font resources, so it is valid
+/// in every PDF/A part.
+pub struct OutlineFont {
+    data: Vec<u8>,
+    tables: HashMap<[u8; 4], (usize, usize)>,
+    units_per_em: f32,
+    ascender: f32,
+    descender: f32,
+    long_loca: bool,
+    glyph_count: u16,
+    h_metric_count: u16,
+    cmap: Cmap,
+}
+
+/// A glyph outline as a list of closed contours, in font units.
+pub type Contours = Vec<Vec<PathSegment>>;
+
+#[derive(Debug, Clone, Copy)]
+pub enum PathSegment {
+    Move(f32, f32),
+    Line(f32, f32),
+    /// Control point and end point.
+    Quad(f32, f32, f32, f32),
+}
+
+enum Cmap {
+    /// Offset of a format 4 subtable.
+    Segmented(usize),
+    /// Offset of a format 12 subtable.
+    Groups(usize),
+}
+
+impl OutlineFont {
+    /// Loads the face fontconfig picks for `pattern`, e.g. `sans-serif:bold`.
+    pub fn find(pattern: &str) -> Result<Self> {
+        let output = std::process::Command::new("fc-match")
+            .arg("--format")
+            .arg("%{file}")
+            .arg(pattern)
+            .output()
+            .map_err(|e| anyhow::anyhow!("Failed to execute fc-match: {}", e))?;
+        let file = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        if !output.status.success() || file.is_empty() {
+            return Err(anyhow::anyhow!("No font matches '{}'", pattern));
+        }
+        Self::load(Path::new(&file))
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let data = std::fs::read(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read font {}: {}", path.display(), e))?;
+        Self::parse(data).map_err(|e| anyhow::anyhow!("Font {}: {}", path.display(), e))
+    }
+
+    pub fn parse(data: Vec<u8>) -> Result<Self> {
+        if data.get(0..4) == Some(b"ttcf") {
+            return Err(anyhow::anyhow!("font collections are not supported"));
+        }
+        let table_count = read_u16(&data, 4)? as usize;
+        let mut tables = HashMap::new();
+        for index in 0..table_count {
+            let record = 12 + index * 16;
+            let tag: [u8; 4] = data
+                .get(record..record + 4)
+                .and_then(|t| t.try_into().ok())
+                .ok_or_else(|| anyhow::anyhow!("truncated table directory"))?;
+            let offset = read_u32(&data, record + 8)? as usize;
+            let length = read_u32(&data, record + 12)? as usize;
+            if offset.checked_add(length).map(|end| end > data.len()).unwrap_or(true) {
+                return Err(anyhow::anyhow!("table {} is out of bounds", String::from_utf8_lossy(&tag)));
+            }
+            tables.insert(tag, (offset, length));
+        }
+        for tag in [b"glyf", b"loca"] {
+            if !tables.contains_key(tag) {
+                return Err(anyhow::anyhow!("not a TrueType outline font (CFF fonts are not supported)"));
+            }
+        }
+
+        let table = |tag: &[u8; 4]| {
+            tables
+                .get(tag)
+                .map(|(offset, _)| *offset)
+                .ok_or_else(|| anyhow::anyhow!("missing '{}' table", String::from_utf8_lossy(tag)))
+        };
+        let head = table(b"head")?;
+        let hhea = table(b"hhea")?;
+        let maxp = table(b"maxp")?;
+        let cmap = Self::find_cmap(&data, table(b"cmap")?)?;
+
+        let units_per_em = read_u16(&data, head + 18)?;
+        if units_per_em == 0 {
+            return Err(anyhow::anyhow!("invalid unitsPerEm"));
+        }
+        Ok(Self {
+            units_per_em: f32::from(units_per_em),
+            ascender: f32::from(read_i16(&data, hhea + 4)?),
+            descender: f32::from(read_i16(&data, hhea + 6)?),
+            h_metric_count: read_u16(&data, hhea + 34)?,
+            long_loca: read_i16(&data, head + 50)? == 1,
+            glyph_count: read_u16(&data, maxp + 4)?,
+            tables,
+            cmap,
+            data,
+        })
+    }
+
+    pub fn units_per_em(&self) -> f32 {
+        self.units_per_em
+    }
+
+    pub fn ascender(&self) -> f32 {
+        self.ascender
+    }
+
+    /// Negative, below the baseline.
+    pub fn descender(&self) -> f32 {
+        self.descender
+    }
+
+    /// Glyph for `c`, or None when the font does not cover it.
+    pub fn glyph_index(&self, c: char) -> Option<u16> {
+        let code = c as u32;
+        let glyph = match self.cmap {
+            Cmap::Segmented(offset) => {
+                let code = u16::try_from(code).ok()?;
+                let seg_count = read_u16(&self.data, offset + 6).ok()? as usize / 2;
+                let ends = offset + 14;
+                let starts = ends + seg_count * 2 + 2;
+                let deltas = starts + seg_count * 2;
+                let range_offsets = deltas + seg_count * 2;
+                let segment = (0..seg_count).find(|i| read_u16(&self.data, ends + i * 2).map(|end| end >= code).unwrap_or(false))?;
+                let start = read_u16(&self.data, starts + segment * 2).ok()?;
+                if start > code {
+                    return None;
+                }
+                let delta = read_u16(&self.data, deltas + segment * 2).ok()?;
+                let range_offset = read_u16(&self.data, range_offsets + segment * 2).ok()? as usize;
+                if range_offset == 0 {
+                    code.wrapping_add(delta)
+                } else {
+                    let at = range_offsets + segment * 2 + range_offset + (code - start) as usize * 2;
+                    match read_u16(&self.data, at).ok()? {
+                        0 => 0,
+                        glyph => glyph.wrapping_add(delta),
+                    }
+                }
+            }
+            Cmap::Groups(offset) => {
+                let group_count = read_u32(&self.data, offset + 12).ok()? as usize;
+                (0..group_count).find_map(|i| {
+                    let group = offset + 16 + i * 12;
+                    let start = read_u32(&self.data, group).ok()?;
+                    let end = read_u32(&self.data, group + 4).ok()?;
+                    let first_glyph = read_u32(&self.data, group + 8).ok()?;
+                    (start..=end).contains(&code).then(|| (first_glyph + code - start) as u16)
+                })?
+            }
+        };
+        (glyph != 0 && glyph < self.glyph_count).then_some(glyph)
+    }
+
+    pub fn advance(&self, glyph: u16) -> f32 {
+        let Some(&(hmtx, _)) = self.tables.get(b"hmtx") else { return 0.0 };
+        // Glyphs past the last metric share its advance
+        let index = glyph.min(self.h_metric_count.saturating_sub(1)) as usize;
+        read_u16(&self.data, hmtx + index * 4).map(f32::from).unwrap_or(0.0)
+    }
+
+    /// Appends path operators that draw `text` at `size` points with the
+    /// baseline starting at `(x, y)`, and returns its width. The caller
+    /// picks the fill operator.
+    pub fn draw_text(&self, text: &str, size: f32, x: f32, y: f32, ops: &mut String) -> Result<f32> {
+        let scale = size / self.units_per_em;
+        let mut pen = 0.0;
+        for c in text.chars() {
+            let Some(glyph) = self.glyph_index(c) else {
+                if c.is_whitespace() {
+                    pen += self.units_per_em / 4.0;
+                    continue;
+                }
+                return Err(anyhow::anyhow!("no glyph for '{}'", c));
+            };
+            let point = |gx: f32, gy: f32| (x + (pen + gx) * scale, y + gy * scale);
+            for contour in self.outline(glyph)? {
+                let mut current = (0.0, 0.0);
+                for segment in contour {
+                    match segment {
+                        PathSegment::Move(gx, gy) => {
+                            current = point(gx, gy);
+                            let _ = writeln!(ops, "{:.2} {:.2} m", current.0, current.1);
+                        }
+                        PathSegment::Line(gx, gy) => {
+                            current = point(gx, gy);
+                            let _ = writeln!(ops, "{:.2} {:.2} l", current.0, current.1);
+                        }
+                        PathSegment::Quad(cx, cy, gx, gy) => {
+                            // Quadratic to cubic: controls 2/3 of the way to the quadratic one
+                            let control = point(cx, cy);
+                            let end = point(gx, gy);
+                            let c1 = (current.0 + 2.0 / 3.0 * (control.0 - current.0), current.1 + 2.0 / 3.0 * (control.1 - current.1));
+                            let c2 = (end.0 + 2.0 / 3.0 * (control.0 - end.0), end.1 + 2.0 / 3.0 * (control.1 - end.1));
+                            let _ = writeln!(ops, "{:.2} {:.2} {:.2} {:.2} {:.2} {:.2} c", c1.0, c1.1, c2.0, c2.1, end.0, end.1);
+                            current = end;
+                        }
+                    }
+                }
+                ops.push_str("h\n");
+            }
+            pen += self.advance(glyph);
+        }
+        Ok(pen * scale)
+    }
+
+    /// Width of `text` at `size` points, as `draw_text` would draw it.
+    pub fn text_width(&self, text: &str, size: f32) -> f32 {
+        let advance: f32 = text
+            .chars()
+            .map(|c| match self.glyph_index(c) {
+                Some(glyph) => self.advance(glyph),
+                None => self.units_per_em / 4.0,
+            })
+            .sum();
+        advance * size / self.units_per_em
+    }
+
+    pub fn outline(&self, glyph: u16) -> Result<Contours> {
+        let mut contours = Vec::new();
+        self.append_outline(glyph, [1.0, 0.0, 0.0, 1.0, 0.0, 0.0], 0, &mut contours)?;
+        Ok(contours)
+    }
+
+    fn append_outline(&self, glyph: u16, transform: [f32; 6], depth: u8, contours: &mut Contours) -> Result<()> {
+        let Some((start, end)) = self.glyph_range(glyph)? else {
+            // Empty glyph, e.g. a space
+            return Ok(());
+        };
+        let data = &self.data;
+        let contour_count = read_i16(data, start)?;
+        if contour_count >= 0 {
+            self.append_simple(start, contour_count as usize, end, transform, contours)
+        } else if depth < MAX_COMPOSITE_DEPTH {
+            self.append_composite(start + 10, transform, depth, contours)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn glyph_range(&self, glyph: u16) -> Result<Option<(usize, usize)>> {
+        let (loca, _) = self.tables[b"loca"];
+        let (glyf, glyf_len) = self.tables[b"glyf"];
+        let index = glyph as usize;
+        let (start, end) = if self.long_loca {
+            (read_u32(&self.data, loca + index * 4)? as usize, read_u32(&self.data, loca + index * 4 + 4)? as usize)
+        } else {
+            (read_u16(&self.data, loca + index * 2)? as usize * 2, read_u16(&self.data, loca + index * 2 + 2)? as usize * 2)
+        };
+        if start >= end {
+            return Ok(None);
+        }
+        if end > glyf_len {
+            return Err(anyhow::anyhow!("glyph {} is out of bounds", glyph));
+        }
+        Ok(Some((glyf + start, glyf + end)))
+    }
+
+    fn append_simple(&self, start: usize, contour_count: usize, end: usize, transform: [f32; 6], contours: &mut Contours) -> Result<()> {
+        let data = &self.data[..end];
+        let mut end_points = Vec::with_capacity(contour_count);
+        for i in 0..contour_count {
+            end_points.push(read_u16(data, start + 10 + i * 2)? as usize);
+        }
+        let point_count = end_points.last().map(|last| last + 1).unwrap_or(0);
+        let instructions = read_u16(data, start + 10 + contour_count * 2)? as usize;
+        let mut at = start + 12 + contour_count * 2 + instructions;
+
+        let mut flags = Vec::with_capacity(point_count);
+        while flags.len() < point_count {
+            let flag = *data.get(at).ok_or_else(|| anyhow::anyhow!("truncated glyph"))?;
+            at += 1;
+            flags.push(flag);
+            if flag & 0x08 != 0 {
+                let repeat = *data.get(at).ok_or_else(|| anyhow::anyhow!("truncated glyph"))?;
+                at += 1;
+                flags.extend(std::iter::repeat_n(flag, repeat as usize));
+            }
+        }
+        flags.truncate(point_count);
+
+        // x and y are deltas, short (1 byte) or long (2 bytes) per flag bits
+        let mut read_coords = |short_bit: u8, same_bit: u8| -> Result<Vec<f32>> {
+            let mut value = 0i32;
+            let mut coords = Vec::with_capacity(point_count);
+            for flag in &flags {
+                if flag & short_bit != 0 {
+                    let delta = i32::from(*data.get(at).ok_or_else(|| anyhow::anyhow!("truncated glyph"))?);
+                    at += 1;
+                    value += if flag & same_bit != 0 { delta } else { -delta };
+                } else if flag & same_bit == 0 {
+                    value += i32::from(read_i16(data, at)?);
+                    at += 2;
+                }
+                coords.push(value as f32);
+            }
+            Ok(coords)
+        };
+        let xs = read_coords(0x02, 0x10)?;
+        let ys = read_coords(0x04, 0x20)?;
+
+        let mut first = 0;
+        for last in end_points {
+            if last < first || last >= point_count {
+                break;
+            }
+            let points: Vec<(f32, f32, bool)> = (first..=last)
+                .map(|i| {
+                    let (x, y) = apply(transform, xs[i], ys[i]);
+                    (x, y, flags[i] & 0x01 != 0)
+                })
+                .collect();
+            contours.push(Self::contour(&points));
+            first = last + 1;
+        }
+        Ok(())
+    }
+
+    /// Turns TrueType points into segments; two consecutive off-curve points
+    /// imply an on-curve point halfway between them.
+    fn contour(points: &[(f32, f32, bool)]) -> Vec<PathSegment> {
+        let mid = |a: (f32, f32, bool), b: (f32, f32, bool)| ((a.0 + b.0) / 2.0, (a.1 + b.1) / 2.0);
+        let Some(start_index) = points.iter().position(|p| p.2) else {
+            // All points off-curve: start between the first two
+            let Some(&first) = points.first() else { return Vec::new() };
+            let start = mid(first, *points.last().unwrap_or(&first));
+            let mut segments = vec![PathSegment::Move(start.0, start.1)];
+            for (i, point) in points.iter().enumerate() {
+                let next = points[(i + 1) % points.len()];
+                let end = mid(*point, next);
+                segments.push(PathSegment::Quad(point.0, point.1, end.0, end.1));
+            }
+            return segments;
+        };
+
+        let start = points[start_index];
+        let mut segments = vec![PathSegment::Move(start.0, start.1)];
+        let mut control: Option<(f32, f32, bool)> = None;
+        for offset in 1..=points.len() {
+            let point = points[(start_index + offset) % points.len()];
+            match (point.2, control) {
+                (true, None) => segments.push(PathSegment::Line(point.0, point.1)),
+                (true, Some(c)) => {
+                    segments.push(PathSegment::Quad(c.0, c.1, point.0, point.1));
+                    control = None;
+                }
+                (false, None) => control = Some(point),
+                (false, Some(c)) => {
+                    let end = mid(c, point);
+                    segments.push(PathSegment::Quad(c.0, c.1, end.0, end.1));
+                    control = Some(point);
+                }
+            }
+        }
+        segments
+    }
+
+    fn append_composite(&self, mut at: usize, transform: [f32; 6], depth: u8, contours: &mut Contours) -> Result<()> {
+        const WORDS: u16 = 0x0001;
+        const XY_VALUES: u16 = 0x0002;
+        const SCALE: u16 = 0x0008;
+        const MORE: u16 = 0x0020;
+        const XY_SCALE: u16 = 0x0040;
+        const TWO_BY_TWO: u16 = 0x0080;
+        let data = &self.data;
+        let f2dot14 = |at: usize| read_i16(data, at).map(|v| f32::from(v) / 16384.0);
+
+        loop {
+            let flags = read_u16(data, at)?;
+            let glyph = read_u16(data, at + 2)?;
+            at += 4;
+            let (dx, dy) = if flags & WORDS != 0 {
+                at += 4;
+                (f32::from(read_i16(data, at - 4)?), f32::from(read_i16(data, at - 2)?))
+            } else {
+                at += 2;
+                let [dx, dy] = read_u16(data, at - 2)?.to_be_bytes();
+                (f32::from(dx as i8), f32::from(dy as i8))
+            };
+            let (a, b, c, d) = if flags & SCALE != 0 {
+                at += 2;
+                let s = f2dot14(at - 2)?;
+                (s, 0.0, 0.0, s)
+            } else if flags & XY_SCALE != 0 {
+                at += 4;
+                (f2dot14(at - 4)?, 0.0, 0.0, f2dot14(at - 2)?)
+            } else if flags & TWO_BY_TWO != 0 {
+                at += 8;
+                (f2dot14(at - 8)?, f2dot14(at - 6)?, f2dot14(at - 4)?, f2dot14(at - 2)?)
+            } else {
+                (1.0, 0.0, 0.0, 1.0)
+            };
+            // Point-matched components are rare in text fonts; place them unshifted
+            let (dx, dy) = if flags & XY_VALUES != 0 { (dx, dy) } else { (0.0, 0.0) };
+
+            let component = [a, b, c, d, dx, dy];
+            self.append_outline(glyph, concat(component, transform), depth + 1, contours)?;
+            if flags & MORE == 0 {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Prefers a full Unicode (3,10) subtable, then the BMP (3,1) or Unicode platform ones.
+    fn find_cmap(data: &[u8], cmap: usize) -> Result<Cmap> {
+        let count = read_u16(data, cmap + 2)? as usize;
+        let mut best: Option<(u8, Cmap)> = None;
+        for i in 0..count {
+            let record = cmap + 4 + i * 8;
+            let platform = read_u16(data, record)?;
+            let encoding = read_u16(data, record + 2)?;
+            let offset = cmap + read_u32(data, record + 4)? as usize;
+            let rank = match (platform, encoding, read_u16(data, offset)?) {
+                (3, 10, 12) => (3, Cmap::Groups(offset)),
+                (0, _, 12) => (2, Cmap::Groups(offset)),
+                (3, 1, 4) => (1, Cmap::Segmented(offset)),
+                (0, _, 4) => (0, Cmap::Segmented(offset)),
+                _ => continue,
+            };
+            if best.as_ref().map(|(r, _)| rank.0 > *r).unwrap_or(true) {
+                best = Some(rank);
+            }
+        }
+        best.map(|(_, cmap)| cmap).ok_or_else(|| anyhow::anyhow!("no Unicode cmap"))
+    }
+}
+
+/// `inner` applied first, then `outer`.
+fn concat(inner: [f32; 6], outer: [f32; 6]) -> [f32; 6] {
+    let [a, b, c, d, e, f] = inner;
+    let [a2, b2, c2, d2, e2, f2] = outer;
+    [
+        a * a2 + b * c2,
+        a * b2 + b * d2,
+        c * a2 + d * c2,
+        c * b2 + d * d2,
+        e * a2 + f * c2 + e2,
+        e * b2 + f * d2 + f2,
+    ]
+}
+
+fn apply(m: [f32; 6], x: f32, y: f32) -> (f32, f32) {
+    (m[0] * x + m[2] * y + m[4], m[1] * x + m[3] * y + m[5])
+}
+
+fn read_u16(data: &[u8], at: usize) -> Result<u16> {
+    data.get(at..at + 2)
+        .map(|b| u16::from_be_bytes([b[0], b[1]]))
+        .ok_or_else(|| anyhow::anyhow!("unexpected end of font data"))
+}
+
+fn read_i16(data: &[u8], at: usize) -> Result<i16> {
+    read_u16(data, at).map(|v| v as i16)
+}
+
+fn read_u32(data: &[u8], at: usize) -> Result<u32> {
+    data.get(at..at + 4)
+        .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or_else(|| anyhow::anyhow!("unexpected end of font data"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(tag: &[u8; 4], data: Vec<u8>) -> ([u8; 4], Vec<u8>) {
+        (*tag, data)
+    }
+
+    fn be16(values: &[u16]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_be_bytes()).collect()
+    }
+
+    /// Square from (0, 0) to (100, 100), with repeated flags and long
+    /// coordinates.
+    fn square() -> Vec<u8> {
+        let mut glyph = be16(&[1, 0, 0, 100, 100, 3, 0]);
+        glyph.extend([0x09, 3]);
+        glyph.extend(be16(&[0, 100, 0, (-100i16) as u16]));
+        glyph.extend(be16(&[0, 0, 100, 0]));
+        glyph
+    }
+
+    /// Glyph 1 moved by (200, 50), then glyph 1 at half size moved by
+    /// (10, -10) with byte offsets.
+    fn composite() -> Vec<u8> {
+        let mut glyph = be16(&[(-1i16) as u16, 0, 0, 0, 0]);
+        glyph.extend(be16(&[0x0023, 1, 200, 50]));
+        glyph.extend(be16(&[0x000A, 1]));
+        glyph.extend([10, (-10i8) as u8]);
+        glyph.extend(be16(&[0x2000]));
+        glyph
+    }
+
+    /// A composite that contains itself.
+    fn recursive() -> Vec<u8> {
+        let mut glyph = be16(&[(-1i16) as u16, 0, 0, 0, 0]);
+        glyph.extend(be16(&[0x0002, 3, 0]));
+        glyph
+    }
+
+    /// 'A' by delta, 'B' and 'C' through the glyph array.
+    fn cmap_format_4() -> Vec<u8> {
+        let mut subtable = be16(&[4, 0, 0, 6, 4, 1, 2]);
+        subtable.extend(be16(&[0x41, 0x43, 0xFFFF, 0]));
+        subtable.extend(be16(&[0x41, 0x42, 0xFFFF]));
+        subtable.extend(be16(&[1u16.wrapping_sub(0x41), 0, 1]));
+        subtable.extend(be16(&[0, 4, 0]));
+        subtable.extend(be16(&[2, 3]));
+        let length = subtable.len() as u16;
+        subtable[2..4].copy_from_slice(&length.to_be_bytes());
+        subtable
+    }
+
+    /// 'A' and U+1F600, outside the BMP.
+    fn cmap_format_12() -> Vec<u8> {
+        let groups: [[u32; 3]; 2] = [[0x41, 0x41, 1], [0x1F600, 0x1F601, 2]];
+        let mut subtable = be16(&[12, 0]);
+        subtable.extend((16 + 12 * groups.len() as u32).to_be_bytes());
+        subtable.extend(0u32.to_be_bytes());
+        subtable.extend((groups.len() as u32).to_be_bytes());
+        subtable.extend(groups.iter().flatten().flat_map(|v| v.to_be_bytes()));
+        subtable
+    }
+
+    fn cmap(subtables: &[(u16, u16, Vec<u8>)]) -> Vec<u8> {
+        let mut header = be16(&[0, subtables.len() as u16]);
+        let mut body: Vec<u8> = Vec::new();
+        let mut offset = 4 + 8 * subtables.len() as u32;
+        for (platform, encoding, subtable) in subtables {
+            header.extend(be16(&[*platform, *encoding]));
+            header.extend(offset.to_be_bytes());
+            offset += subtable.len() as u32;
+            body.extend(subtable);
+        }
+        header.extend(body);
+        header
+    }
+
+    fn font(cmap: Vec<u8>) -> Vec<u8> {
+        let glyphs = [Vec::new(), square(), composite(), recursive()];
+        let mut glyf = Vec::new();
+        let mut loca = Vec::new();
+        for glyph in &glyphs {
+            loca.extend(be16(&[(glyf.len() / 2) as u16]));
+            glyf.extend(glyph);
+        }
+        loca.extend(be16(&[(glyf.len() / 2) as u16]));
+
+        let mut head = vec![0; 54];
+        head[18..20].copy_from_slice(&1000u16.to_be_bytes());
+        let mut hhea = vec![0; 36];
+        hhea[4..6].copy_from_slice(&800i16.to_be_bytes());
+        hhea[6..8].copy_from_slice(&(-200i16).to_be_bytes());
+        hhea[34..36].copy_from_slice(&4u16.to_be_bytes());
+        let maxp = [0x0000_5000u32.to_be_bytes().to_vec(), be16(&[4])].concat();
+        let hmtx = be16(&[500, 0, 600, 0, 700, 0, 800, 0]);
+
+        let tables = [
+            table(b"cmap", cmap),
+            table(b"glyf", glyf),
+            table(b"head", head),
+            table(b"hhea", hhea),
+            table(b"hmtx", hmtx),
+            table(b"loca", loca),
+            table(b"maxp", maxp),
+        ];
+        let mut data = [0x0001_0000u32.to_be_bytes().to_vec(), be16(&[tables.len() as u16, 0, 0, 0])].concat();
+        let mut offset = 12 + 16 * tables.len();
+        let mut body: Vec<u8> = Vec::new();
+        for (tag, table) in &tables {
+            data.extend(tag);
+            data.extend(0u32.to_be_bytes());
+            data.extend((offset as u32).to_be_bytes());
+            data.extend((table.len() as u32).to_be_bytes());
+            offset += table.len();
+            body.extend(table);
+        }
+        data.extend(body);
+        data
+    }
+
+    #[test]
+    fn reads_metrics_and_format_4_cmaps() {
+        let font = OutlineFont::parse(font(cmap(&[(3, 1, cmap_format_4())]))).unwrap();
+        assert_eq!((font.units_per_em(), font.ascender(), font.descender()), (1000.0, 800.0, -200.0));
+        assert_eq!(font.glyph_index('A'), Some(1));
+        assert_eq!(font.glyph_index('B'), Some(2));
+        assert_eq!(font.glyph_index('C'), Some(3));
+        assert_eq!(font.glyph_index('D'), None);
+        assert_eq!(font.glyph_index('@'), None);
+        assert_eq!(font.glyph_index('😀'), None);
+        assert_eq!(font.advance(1), 600.0);
+        // Past the last metric
+        assert_eq!(font.advance(9), 800.0);
+        assert_eq!(font.text_width("AB", 10.0), 13.0);
+    }
+
+    #[test]
+    fn prefers_format_12_cmaps() {
+        let font = OutlineFont::parse(font(cmap(&[(3, 1, cmap_format_4()), (3, 10, cmap_format_12())]))).unwrap();
+        assert_eq!(font.glyph_index('A'), Some(1));
+        assert_eq!(font.glyph_index('😀'), Some(2));
+        assert_eq!(font.glyph_index('😁'), Some(3));
+        assert_eq!(font.glyph_index('B'), None);
+    }
+
+    #[test]
+    fn draws_simple_and_composite_glyphs() {
+        let font = OutlineFont::parse(font(cmap(&[(3, 1, cmap_format_4())]))).unwrap();
+        assert_eq!(
+            format!("{:?}", font.outline(1).unwrap()),
+            "[[Move(0.0, 0.0), Line(100.0, 0.0), Line(100.0, 100.0), Line(0.0, 100.0), Line(0.0, 0.0)]]"
+        );
+        assert_eq!(
+            format!("{:?}", font.outline(2).unwrap()),
+            "[[Move(200.0, 50.0), Line(300.0, 50.0), Line(300.0, 150.0), Line(200.0, 150.0), Line(200.0, 50.0)], \
+             [Move(10.0, -10.0), Line(60.0, -10.0), Line(60.0, 40.0), Line(10.0, 40.0), Line(10.0, -10.0)]]"
+        );
+        // Nesting stops at MAX_COMPOSITE_DEPTH
+        assert!(font.outline(3).unwrap().is_empty());
+        assert!(font.outline(0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejects_malformed_fonts() {
+        let data = font(cmap(&[(3, 1, cmap_format_4())]));
+        assert!(OutlineFont::parse(data[..40].to_vec()).is_err());
+        assert!(OutlineFont::parse(b"ttcf\0\x02\0\0".to_vec()).is_err());
+        assert!(OutlineFont::parse(font(cmap(&[(1, 0, cmap_format_4())]))).is_err());
+
+        let mut no_glyf = data.clone();
+        no_glyf[12 + 16..12 + 16 + 4].copy_from_slice(b"CFF ");
+        let error = OutlineFont::parse(no_glyf).err().unwrap();
+        assert!(error.to_string().contains("CFF"), "{}", error);
+
+        // A loca entry past the end of glyf
+        let font = OutlineFont::parse(data.clone()).unwrap();
+        let (loca, _) = font.tables[b"loca"];
+        let mut data = data;
+        data[loca + 8..loca + 10].copy_from_slice(&0x4000u16.to_be_bytes());
+        let font = OutlineFont::parse(data).unwrap();
+        assert!(font.outline(3).is_err());
+    }
+}
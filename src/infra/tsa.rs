@@ -0,0 +1,221 @@
+use anyhow::Result;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use ring::rand::{SecureRandom, SystemRandom};
+use std::io::Read;
+use std::time::{Duration, Instant};
+use crate::infra::cms;
+use crate::infra::der::{self, Tlv};
+
+const ID_SHA256: &str = "2.16.840.1.101.3.4.2.1";
+const ID_TST_INFO: &str = "1.2.840.113549.1.9.16.1.4";
+const ID_KP_TIME_STAMPING: &str = "1.3.6.1.5.5.7.3.8";
+
+/// Largest time-stamp response accepted.
+const MAX_RESPONSE_BYTES: u64 = 1024 * 1024;
+
+/// Default upper bound of a token's DER size, TSA certificates included.
+pub const DEFAULT_TOKEN_RESERVE: usize = 16 * 1024;
+
+/// An RFC 3161 time-stamp token and the time it attests.
+#[derive(Debug, Clone)]
+pub struct TimestampToken {
+    /// `ContentInfo` with the TSA's `SignedData`.
+    pub der: Vec<u8>,
+    pub time: DateTime<Utc>,
+}
+
+/// RFC 3161 client over HTTP.
+#[derive(Debug, Clone)]
+pub struct TsaClient {
+    url: String,
+    timeout: Duration,
+}
+
+impl TsaClient {
+    pub fn new(url: String, timeout: Duration) -> Self {
+        Self { url, timeout }
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    /// Requests a token over a SHA-256 `digest`, asking for the TSA
+    /// certificate so the token can be verified on its own.
+    pub async fn timestamp(&self, digest: &[u8]) -> Result<TimestampToken> {
+        let start = Instant::now();
+        let mut nonce = [0u8; 8];
+        SystemRandom::new()
+            .fill(&mut nonce)
+            .map_err(|_| anyhow::anyhow!("Failed to generate a time-stamp nonce"))?;
+        // Positive and non-zero
+        let nonce = (u64::from_be_bytes(nonce) >> 1) | 1;
+
+        let request = der::sequence(&[
+            &der::integer(1),
+            &der::sequence(&[&der::algorithm(ID_SHA256, true), &der::octet_string(digest)]),
+            &der::integer(nonce),
+            &der::boolean(true),
+        ]);
+
+        let url = self.url.clone();
+        let timeout = self.timeout;
+        let response = tokio::task::spawn_blocking(move || -> Result<Vec<u8>> {
+            let response = ureq::post(&url)
+                .timeout(timeout)
+                .set("Content-Type", "application/timestamp-query")
+                .send_bytes(&request)
+                .map_err(|e| anyhow::anyhow!("Time-stamp request to {} failed: {}", url, e))?;
+            let mut body = Vec::new();
+            response
+                .into_reader()
+                .take(MAX_RESPONSE_BYTES)
+                .read_to_end(&mut body)
+                .map_err(|e| anyhow::anyhow!("Failed to read the time-stamp response: {}", e))?;
+            Ok(body)
+        })
+        .await
+        .map_err(|e| anyhow::anyhow!("Time-stamp task failed: {}", e))??;
+
+        let token = Self::parse_response(&response, digest, nonce)?;
+        tracing::debug!(
+            event = "timestamp_received",
+            tsa_url = %self.url,
+            token_size_bytes = token.der.len(),
+            time = %token.time,
+            duration_ms = start.elapsed().as_millis() as u64,
+            "Time-stamp token received"
+        );
+        Ok(token)
+    }
+
+    /// Checks a `TimeStampResp` and returns its token, which must cover
+    /// `digest`, echo `nonce` and be signed by the time-stamping
+    /// certificate it carries.
+    fn parse_response(response: &[u8], digest: &[u8], nonce: u64) -> Result<TimestampToken> {
+        let invalid = |e: anyhow::Error| anyhow::anyhow!("Invalid time-stamp response: {}", e);
+        let (response, _) = Tlv::parse(response).map_err(invalid)?;
+        let fields = response.expect(der::SEQUENCE).and_then(|r| r.children()).map_err(invalid)?;
+        let status = fields
+            .first()
+            .and_then(|s| s.children().ok())
+            .and_then(|s| s.first().copied())
+            .filter(|s| s.tag == der::INTEGER)
+            .ok_or_else(|| anyhow::anyhow!("Invalid time-stamp response: missing status"))?;
+        // granted or grantedWithMods
+        if !matches!(status.content, [0] | [1]) {
+            let reason = fields[0]
+                .children()
+                .ok()
+                .and_then(|s| s.get(1).and_then(|text| text.children().ok()))
+                .and_then(|texts| texts.first().and_then(Tlv::text))
+                .unwrap_or_default();
+            return Err(anyhow::anyhow!("The TSA rejected the request (status {:?}) {}", status.content, reason));
+        }
+        let token = fields.get(1).ok_or_else(|| anyhow::anyhow!("Invalid time-stamp response: no token"))?;
+        let info = Self::tst_info(token).map_err(invalid)?;
+        let signer = cms::verify_signed_data(token.raw, ID_TST_INFO)
+            .map_err(|e| anyhow::anyhow!("Invalid time-stamp token: {}", e))?;
+        if !signer.extended_key_usages.iter().any(|usage| usage == ID_KP_TIME_STAMPING) {
+            return Err(anyhow::anyhow!(
+                "The time-stamp token signer '{}' is not a time-stamping certificate",
+                signer.subject
+            ));
+        }
+
+        // TSTInfo: version, policy, messageImprint, serialNumber, genTime, [accuracy], [ordering], [nonce]
+        let imprint = info
+            .get(2)
+            .and_then(|i| i.children().ok())
+            .and_then(|i| i.get(1).map(|hash| hash.content.to_vec()));
+        if imprint.as_deref() != Some(digest) {
+            return Err(anyhow::anyhow!("The time-stamp token does not cover the requested data"));
+        }
+        let echoed = info.iter().skip(5).find(|f| f.tag == der::INTEGER).map(|n| n.raw.to_vec());
+        if echoed != Some(der::integer(nonce)) {
+            return Err(anyhow::anyhow!("The time-stamp token does not echo the request nonce"));
+        }
+        let time = info
+            .get(4)
+            .filter(|t| t.tag == der::GENERALIZED_TIME)
+            .and_then(|t| std::str::from_utf8(t.content).ok())
+            .and_then(|t| t.strip_suffix('Z'))
+            // Fractions of a second are optional
+            .and_then(|t| NaiveDateTime::parse_from_str(t, "%Y%m%d%H%M%S%.f").ok())
+            .ok_or_else(|| anyhow::anyhow!("Invalid time-stamp token: unreadable time"))?;
+
+        Ok(TimestampToken { der: token.raw.to_vec(), time: time.and_utc() })
+    }
+
+    /// Fields of the `TSTInfo` inside a token.
+    fn tst_info<'a>(token: &Tlv<'a>) -> Result<Vec<Tlv<'a>>> {
+        let malformed = || anyhow::anyhow!("malformed token");
+        let content_info = token.children()?;
+        let signed_data = content_info.get(1).ok_or_else(malformed)?.children()?;
+        let signed_data = signed_data.first().ok_or_else(malformed)?.children()?;
+        let encapsulated = signed_data.get(2).ok_or_else(malformed)?.children()?;
+        if encapsulated.first().and_then(Tlv::oid).as_deref() != Some(ID_TST_INFO) {
+            return Err(anyhow::anyhow!("the token does not hold a TSTInfo"));
+        }
+        let content = encapsulated.get(1).ok_or_else(malformed)?.children()?;
+        let octets = content.first().ok_or_else(malformed)?.expect(der::OCTET_STRING)?;
+        let (info, _) = Tlv::parse(octets.content)?;
+        info.children()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::infra::cms::tests::{extension, signed_data, test_certificate, ID_KP_TIME_STAMPING};
+
+    const ID_EXTENDED_KEY_USAGE: &str = "2.5.29.37";
+
+    /// A granted `TimeStampResp` over `digest`, signed by `pkcs8`.
+    fn response(pkcs8: &[u8], certificate: &[u8], digest: &[u8], nonce: u64) -> Vec<u8> {
+        let info = der::sequence(&[
+            &der::integer(1),
+            &der::oid("1.2.3.4.1"),
+            &der::sequence(&[&der::algorithm(ID_SHA256, true), &der::octet_string(digest)]),
+            &der::integer(42),
+            &der::tlv(der::GENERALIZED_TIME, b"20261018120000.5Z"),
+            &der::integer(nonce),
+        ]);
+        let token = signed_data(pkcs8, certificate, ID_TST_INFO, &info, None);
+        der::sequence(&[&der::sequence(&[&der::integer(0)]), &token])
+    }
+
+    fn time_stamping_certificate() -> (Vec<u8>, Vec<u8>) {
+        let usages = der::sequence(&[&der::oid(ID_KP_TIME_STAMPING)]);
+        test_certificate("pdfsynth TSA", &[&extension(ID_EXTENDED_KEY_USAGE, &usages)])
+    }
+
+    #[test]
+    fn accepts_a_signed_token() {
+        let (pkcs8, certificate) = time_stamping_certificate();
+        let token = TsaClient::parse_response(&response(&pkcs8, &certificate, &[5; 32], 77), &[5; 32], 77).unwrap();
+        assert_eq!(token.time.to_rfc3339(), "2026-10-18T12:00:00.500+00:00");
+
+        assert!(TsaClient::parse_response(&response(&pkcs8, &certificate, &[5; 32], 77), &[6; 32], 77).is_err());
+        assert!(TsaClient::parse_response(&response(&pkcs8, &certificate, &[5; 32], 77), &[5; 32], 78).is_err());
+    }
+
+    #[test]
+    fn rejects_tokens_not_signed_by_their_certificate() {
+        let (_, certificate) = time_stamping_certificate();
+        let (other_key, _) = time_stamping_certificate();
+        let error = TsaClient::parse_response(&response(&other_key, &certificate, &[5; 32], 77), &[5; 32], 77).unwrap_err();
+        assert!(error.to_string().starts_with("Invalid time-stamp token"), "{}", error);
+    }
+
+    #[test]
+    fn requires_the_time_stamping_key_usage() {
+        let (pkcs8, certificate) = test_certificate("pdfsynth signer", &[]);
+        let error = TsaClient::parse_response(&response(&pkcs8, &certificate, &[5; 32], 77), &[5; 32], 77).unwrap_err();
+        assert!(error.to_string().contains("not a time-stamping certificate"), "{}", error);
+    }
+}
@@ -0,0 +1,80 @@
+use std::sync::Arc;
+use url::Url;
+
+/// Hosts that URL-mode renders are allowed to navigate to.
+///
+/// Entries are exact host names (`dashboard.internal`) or wildcard suffixes
+/// (`*.example.com`, which matches subdomains but not `example.com` itself).
+/// An empty allowlist disables URL mode entirely.
+#[derive(Clone, Debug, Default)]
+pub struct UrlAllowlist {
+    hosts: Arc<Vec<String>>,
+}
+
+impl UrlAllowlist {
+    pub fn new(hosts: Vec<String>) -> Self {
+        let hosts = hosts
+            .into_iter()
+            .map(|h| h.trim().to_ascii_lowercase())
+            .filter(|h| !h.is_empty())
+            .collect();
+        Self { hosts: Arc::new(hosts) }
+    }
+
+    /// Reads a comma-separated list from `URL_ALLOWED_HOSTS`.
+    pub fn from_env() -> Self {
+        let hosts = std::env::var("URL_ALLOWED_HOSTS")
+            .map(|v| v.split(',').map(str::to_string).collect())
+            .unwrap_or_default();
+        Self::new(hosts)
+    }
+
+    pub fn hosts(&self) -> &[String] {
+        &self.hosts
+    }
+
+    /// Parses `raw` and checks that it is an http(s) URL whose host is allowed.
+    pub fn check(&self, raw: &str) -> std::result::Result<Url, String> {
+        if self.hosts.is_empty() {
+            return Err("URL rendering is disabled: URL_ALLOWED_HOSTS is not configured".to_string());
+        }
+
+        let url = Url::parse(raw).map_err(|e| format!("Invalid URL '{}': {}", raw, e))?;
+
+        if url.scheme() != "http" && url.scheme() != "https" {
+            return Err(format!("URL scheme '{}' is not allowed, only http and https", url.scheme()));
+        }
+
+        let host = url
+            .host_str()
+            .map(|h| h.to_ascii_lowercase())
+            .ok_or_else(|| format!("URL '{}' has no host", raw))?;
+
+        if self.hosts.iter().any(|allowed| Self::host_matches(allowed, &host)) {
+            Ok(url)
+        } else {
+            Err(format!("Host '{}' is not in the URL allowlist", host))
+        }
+    }
+
+    /// Checks that a cookie `domain` names an allowed host, so a render
+    /// never plants a cookie for a host it cannot reach.
+    pub fn check_cookie_domain(&self, domain: &str) -> std::result::Result<(), String> {
+        let host = domain.trim_start_matches('.').to_ascii_lowercase();
+        if self.hosts.iter().any(|allowed| Self::host_matches(allowed, &host)) {
+            Ok(())
+        } else {
+            Err(format!("Cookie domain '{}' is not in the URL allowlist", domain))
+        }
+    }
+
+    fn host_matches(allowed: &str, host: &str) -> bool {
+        match allowed.strip_prefix("*.") {
+            Some(suffix) => host
+                .strip_suffix(suffix)
+                .map(|prefix| prefix.ends_with('.'))
+                .unwrap_or(false),
+            None => allowed == host,
+        }
+    }
+}
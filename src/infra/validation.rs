@@ -0,0 +1,482 @@
+use std::io::Write;
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use anyhow::Result;
+use lopdf::{Dictionary, Document, Object, ObjectId};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tempfile::NamedTempFile;
+use tokio::process::Command;
+use tokio::sync::Semaphore;
+use crate::infra::font_coverage::FontCoverage;
+use crate::infra::pdf_objects::{dict_text, metadata_id, read_xmp, uses_transparency};
+use crate::infra::pdfa::PdfaLevel;
+
+/// Longest rule description kept in a report.
+const MAX_DESCRIPTION_CHARS: usize = 300;
+
+/// Whether, and how strictly, PDF/A output is validated.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ValidationMode {
+    #[default]
+    Off,
+    /// Validate and report the result in response headers.
+    Report,
+    /// Validate and fail the render when the output is not compliant.
+    Strict,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct ValidationReport {
+    /// `verapdf` or `native`.
+    pub validator: &'static str,
+    /// PDF/A level checked, e.g. `2b`.
+    pub profile: &'static str,
+    pub compliant: bool,
+    pub failures: Vec<RuleFailure>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct RuleFailure {
+    /// veraPDF `clause-testNumber`, or a native rule id such as `metadata.pdfaid`.
+    pub rule: String,
+    pub description: String,
+    /// Failed checks, when the validator counts them.
+    pub count: Option<u64>,
+}
+
+/// PDF/A validator.
+///
+/// Uses the veraPDF CLI when `VERAPDF_PATH` (default `verapdf`) runs;
+/// otherwise checks a native subset of the rules that Chrome and Ghostscript
+/// output is known to break. veraPDF runs get a deadline and a cap on
+/// concurrent processes, since each one starts a JVM.
+#[derive(Clone, Debug)]
+pub struct PdfaValidator {
+    verapdf: Option<String>,
+    permits: Arc<Semaphore>,
+    max_concurrency: usize,
+    timeout: Duration,
+}
+
+impl PdfaValidator {
+    /// Reads `VERAPDF_PATH`, `VERAPDF_MAX_CONCURRENCY` (default 2) and
+    /// `VERAPDF_TIMEOUT_SECS` (default 60).
+    pub fn detect() -> Self {
+        let var = |name: &str| std::env::var(name).ok().and_then(|v| v.parse::<u64>().ok());
+        let binary = std::env::var("VERAPDF_PATH").unwrap_or_else(|_| "verapdf".to_string());
+        let available = std::process::Command::new(&binary)
+            .arg("--version")
+            .output()
+            .map(|o| o.status.success())
+            .unwrap_or(false);
+        let max_concurrency = var("VERAPDF_MAX_CONCURRENCY").unwrap_or(2).max(1) as usize;
+
+        Self {
+            verapdf: available.then_some(binary),
+            permits: Arc::new(Semaphore::new(max_concurrency)),
+            max_concurrency,
+            timeout: Duration::from_secs(var("VERAPDF_TIMEOUT_SECS").unwrap_or(60)),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        if self.verapdf.is_some() { "verapdf" } else { "native" }
+    }
+
+    pub fn max_concurrency(&self) -> usize {
+        self.max_concurrency
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    pub async fn validate(&self, pdf_data: &[u8], level: PdfaLevel) -> Result<ValidationReport> {
+        let start = Instant::now();
+        let report = match &self.verapdf {
+            Some(binary) => self.run_verapdf(binary, pdf_data, level).await?,
+            None => {
+                // Parsing a large document would stall a runtime worker
+                let pdf_data = pdf_data.to_vec();
+                tokio::task::spawn_blocking(move || NativeRules::check(&pdf_data, level))
+                    .await
+                    .map_err(|e| anyhow::anyhow!("PDF/A validation task failed: {}", e))??
+            }
+        };
+
+        tracing::info!(
+            event = "pdfa_validation_complete",
+            validator = report.validator,
+            profile = report.profile,
+            compliant = report.compliant,
+            failure_count = report.failures.len(),
+            duration_ms = start.elapsed().as_millis() as u64,
+            "PDF/A validation completed"
+        );
+
+        Ok(report)
+    }
+
+    async fn run_verapdf(&self, binary: &str, pdf_data: &[u8], level: PdfaLevel) -> Result<ValidationReport> {
+        let _permit = self.permits
+            .acquire()
+            .await
+            .map_err(|_| anyhow::anyhow!("veraPDF permits closed"))?;
+
+        let mut input_file = NamedTempFile::new()?;
+        input_file.write_all(pdf_data)?;
+
+        let child = Command::new(binary)
+            .arg("--format")
+            .arg("json")
+            .arg("--flavour")
+            .arg(level.as_str())
+            .arg(input_file.path())
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .process_group(0)
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| anyhow::anyhow!("Failed to execute veraPDF: {}", e))?;
+        let mut group = ProcessGroup(child.id());
+
+        // On expiry the child is dropped, and the group with it
+        let output = match tokio::time::timeout(self.timeout, child.wait_with_output()).await {
+            Ok(output) => {
+                group.0 = None;
+                output?
+            }
+            Err(_) => {
+                tracing::error!(
+                    event = "verapdf_timeout",
+                    timeout_secs = self.timeout.as_secs(),
+                    input_size_bytes = pdf_data.len(),
+                    "veraPDF exceeded its deadline and was killed"
+                );
+                return Err(anyhow::anyhow!("veraPDF timed out after {}s", self.timeout.as_secs()));
+            }
+        };
+
+        // veraPDF exits with 1 for non-compliant files; anything else is an error
+        if !matches!(output.status.code(), Some(0) | Some(1)) {
+            return Err(anyhow::anyhow!(
+                "veraPDF failed with status: {}. Stderr: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr)
+            ));
+        }
+
+        let json: Value = serde_json::from_slice(&output.stdout)
+            .map_err(|e| anyhow::anyhow!("Failed to parse veraPDF report: {}", e))?;
+        Self::parse_verapdf(&json, level)
+    }
+
+    /// Reads the first job of a veraPDF JSON report. `validationResult` is an
+    /// object in older releases and an array in newer ones.
+    fn parse_verapdf(json: &Value, level: PdfaLevel) -> Result<ValidationReport> {
+        let job = &json["report"]["jobs"][0];
+        let result = match &job["validationResult"] {
+            Value::Array(results) => results.first().cloned().unwrap_or(Value::Null),
+            result => result.clone(),
+        };
+        let compliant = result["compliant"]
+            .as_bool()
+            .ok_or_else(|| anyhow::anyhow!("veraPDF report has no validation result"))?;
+
+        let failures = result["details"]["ruleSummaries"]
+            .as_array()
+            .map(|rules| {
+                rules
+                    .iter()
+                    .filter(|r| r["status"].as_str() != Some("passed"))
+                    .map(|r| RuleFailure {
+                        rule: format!(
+                            "{}-{}",
+                            r["clause"].as_str().unwrap_or("?"),
+                            r["testNumber"].as_u64().unwrap_or(0)
+                        ),
+                        description: r["description"]
+                            .as_str()
+                            .unwrap_or_default()
+                            .chars()
+                            .take(MAX_DESCRIPTION_CHARS)
+                            .collect(),
+                        count: r["failedChecks"].as_u64(),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Ok(ValidationReport { validator: "verapdf", profile: level.as_str(), compliant, failures })
+    }
+}
+
+/// Kills a process group when dropped. The veraPDF CLI is a launcher
+/// script, so killing only the script would leave its JVM running.
+struct ProcessGroup(Option<u32>);
+
+impl Drop for ProcessGroup {
+    fn drop(&mut self) {
+        if let Some(id) = self.0 {
+            unsafe {
+                libc::killpg(id as libc::pid_t, libc::SIGKILL);
+            }
+        }
+    }
+}
+
+/// The native rule subset.
+struct NativeRules {
+    failures: Vec<RuleFailure>,
+}
+
+impl NativeRules {
+    fn check(pdf_data: &[u8], level: PdfaLevel) -> Result<ValidationReport> {
+        let doc = Document::load_mem(pdf_data)
+            .map_err(|e| anyhow::anyhow!("Failed to parse PDF: {}", e))?;
+        let catalog = doc.catalog()?;
+        let mut rules = NativeRules { failures: Vec::new() };
+
+        if level.part() == 1 && doc.version.as_str() > "1.4" {
+            rules.fail("header.version", format!("PDF/A-1 requires PDF 1.4 or lower, found {}", doc.version));
+        }
+        rules.require(doc.trailer.get(b"ID").is_ok(), "trailer.id", "The trailer has no /ID");
+        rules.require(doc.trailer.get(b"Encrypt").is_err(), "trailer.encrypt", "Encryption is not allowed");
+
+        Self::check_metadata(&doc, level, &mut rules);
+        Self::check_output_intent(&doc, catalog, &mut rules);
+        Self::check_embedded_files(&doc, level, &mut rules);
+
+        let (fonts, _) = FontCoverage::scan_pdf(pdf_data)?;
+        for font in fonts.iter().filter(|f| !f.embedded) {
+            rules.fail("font.embedded", format!("Font '{}' is not embedded", font.name));
+        }
+
+        if level.requires_tagging() {
+            rules.require(catalog.get(b"StructTreeRoot").is_ok(), "structure.tree", "Level A requires a structure tree");
+            let marked = catalog
+                .get(b"MarkInfo")
+                .and_then(|m| doc.dereference(m))
+                .and_then(|(_, m)| m.as_dict())
+                .and_then(|m| m.get(b"Marked"))
+                .and_then(Object::as_bool)
+                .unwrap_or(false);
+            rules.require(marked, "structure.marked", "Level A requires /MarkInfo << /Marked true >>");
+            rules.require(catalog.get(b"Lang").is_ok(), "structure.lang", "Level A requires the catalog /Lang");
+        }
+
+        for object in doc.objects.values() {
+            let dict = match object {
+                Object::Dictionary(d) => d,
+                Object::Stream(s) => &s.dict,
+                _ => continue,
+            };
+            if matches!(dict.get(b"S").and_then(Object::as_name), Ok(b"JavaScript") | Ok(b"Launch")) {
+                rules.fail("action.forbidden", "JavaScript and Launch actions are not allowed".to_string());
+            }
+            if Self::has_filter(dict, b"LZWDecode") {
+                rules.fail("filter.lzw", "LZWDecode filters are not allowed".to_string());
+            }
+            if !level.allows_transparency() && uses_transparency(&doc, dict) {
+                rules.fail("transparency", "PDF/A-1 forbids soft masks, transparency groups, opacity below 1 and blend modes".to_string());
+            }
+        }
+
+        let failures = rules.merge_counts();
+        Ok(ValidationReport {
+            validator: "native",
+            profile: level.as_str(),
+            compliant: failures.is_empty(),
+            failures,
+        })
+    }
+
+    fn check_metadata(doc: &Document, level: PdfaLevel, rules: &mut NativeRules) {
+        let Some(id) = metadata_id(doc) else {
+            rules.fail("metadata.present", "The catalog has no XMP /Metadata stream".to_string());
+            return;
+        };
+        if level.part() == 1 {
+            let filtered = doc
+                .get_object(id)
+                .and_then(Object::as_stream)
+                .map(|s| s.dict.has(b"Filter"))
+                .unwrap_or(false);
+            rules.require(!filtered, "metadata.filter", "PDF/A-1 forbids filters on the metadata stream");
+        }
+
+        let Ok(xmp) = read_xmp(doc, id) else {
+            rules.fail("metadata.present", "The XMP metadata stream cannot be read".to_string());
+            return;
+        };
+        let part = Self::xmp_value(&xmp, "pdfaid:part");
+        let conformance = Self::xmp_value(&xmp, "pdfaid:conformance");
+        if part.as_deref() != Some(&level.part().to_string()) || conformance.as_deref() != Some(&level.conformance().to_string()) {
+            rules.fail(
+                "metadata.pdfaid",
+                format!(
+                    "XMP identifies the file as part {} conformance {}, expected {}",
+                    part.unwrap_or_else(|| "?".to_string()),
+                    conformance.unwrap_or_else(|| "?".to_string()),
+                    level.as_str()
+                ),
+            );
+        }
+
+        // DocInfo entries must match their XMP equivalents
+        let Some(info) = doc
+            .trailer
+            .get(b"Info")
+            .and_then(|i| doc.dereference(i))
+            .ok()
+            .and_then(|(_, i)| i.as_dict().ok())
+        else {
+            return;
+        };
+        for (key, property) in [
+            (&b"Title"[..], "dc:title"),
+            (b"Author", "dc:creator"),
+            (b"Subject", "dc:description"),
+            (b"Keywords", "pdf:Keywords"),
+            (b"Creator", "xmp:CreatorTool"),
+            (b"Producer", "pdf:Producer"),
+        ] {
+            let Some(value) = dict_text(doc, info, key) else { continue };
+            let matches = Self::xmp_value(&xmp, property)
+                .map(|xmp_value| xmp_value == crate::infra::xmp::escape_xml(&value) || xmp_value == value)
+                .unwrap_or(false);
+            if !matches {
+                rules.fail(
+                    "metadata.info_sync",
+                    format!("/{} is not mirrored by {} in XMP", String::from_utf8_lossy(key), property),
+                );
+            }
+        }
+    }
+
+    fn check_output_intent(doc: &Document, catalog: &Dictionary, rules: &mut NativeRules) {
+        let intents = catalog
+            .get(b"OutputIntents")
+            .and_then(|o| doc.dereference(o))
+            .and_then(|(_, o)| o.as_array())
+            .cloned()
+            .unwrap_or_default();
+        let pdfa_intent = intents.iter().any(|intent| {
+            doc.dereference(intent)
+                .ok()
+                .and_then(|(_, i)| i.as_dict().ok())
+                .map(|i| {
+                    matches!(i.get(b"S").and_then(Object::as_name), Ok(b"GTS_PDFA1"))
+                        && i.get(b"DestOutputProfile").is_ok()
+                })
+                .unwrap_or(false)
+        });
+        rules.require(
+            pdfa_intent,
+            "output_intent",
+            "No GTS_PDFA1 OutputIntent with a DestOutputProfile",
+        );
+    }
+
+    /// PDF/A-1 forbids embedded files and PDF/A-2 only allows PDF/A ones,
+    /// told apart here by MIME type. PDF/A-3 allows any file that is
+    /// associated with the document or a part of it through `/AF`.
+    fn check_embedded_files(doc: &Document, level: PdfaLevel, rules: &mut NativeRules) {
+        let dicts = || doc.objects.iter().filter_map(|(id, object)| match object {
+            Object::Dictionary(d) => Some((id, d)),
+            Object::Stream(s) => Some((id, &s.dict)),
+            _ => None,
+        });
+        let associated: Vec<ObjectId> = dicts()
+            .filter_map(|(_, d)| d.get(b"AF").and_then(|a| doc.dereference(a)).and_then(|(_, a)| a.as_array()).ok())
+            .flatten()
+            .filter_map(|f| f.as_reference().ok())
+            .collect();
+
+        for (id, spec) in dicts() {
+            let Ok(files) = spec.get(b"EF").and_then(|e| doc.dereference(e)).and_then(|(_, e)| e.as_dict()) else {
+                continue;
+            };
+            let mime = files
+                .get(b"F")
+                .or_else(|_| files.get(b"UF"))
+                .and_then(|f| doc.dereference(f))
+                .and_then(|(_, f)| f.as_stream())
+                .and_then(|s| s.dict.get(b"Subtype"))
+                .and_then(Object::as_name)
+                .ok();
+            match level.part() {
+                1 => rules.fail("embedded_file.forbidden", "PDF/A-1 forbids embedded files".to_string()),
+                2 => rules.require(mime == Some(b"application/pdf"), "embedded_file.pdfa", "PDF/A-2 only allows embedded PDF/A files"),
+                _ => {
+                    rules.require(mime.is_some(), "embedded_file.mime", "Embedded files need a MIME type in /Subtype");
+                    rules.require(spec.has(b"AFRelationship"), "embedded_file.relationship", "File specifications need an /AFRelationship");
+                    rules.require(spec.has(b"F") && spec.has(b"UF"), "embedded_file.name", "File specifications need /F and /UF");
+                    rules.require(associated.contains(id), "embedded_file.associated", "Embedded files must be referenced from an /AF array");
+                }
+            }
+        }
+    }
+
+    /// Text of an XMP property: a plain element, the first `rdf:li` of an
+    /// array, or an attribute.
+    fn xmp_value(xmp: &str, name: &str) -> Option<String> {
+        let open = format!("<{}>", name);
+        let close = format!("</{}>", name);
+        if let Some(start) = xmp.find(&open) {
+            let content = &xmp[start + open.len()..];
+            let content = &content[..content.find(&close)?];
+            let content = match content.find("<rdf:li") {
+                Some(li) => {
+                    let li = &content[li..];
+                    let body = &li[li.find('>')? + 1..];
+                    &body[..body.find("</rdf:li>")?]
+                }
+                None => content,
+            };
+            return Some(content.trim().to_string());
+        }
+        for quote in ['\'', '"'] {
+            let attr = format!("{}={}", name, quote);
+            if let Some(start) = xmp.find(&attr) {
+                let value = &xmp[start + attr.len()..];
+                return Some(value[..value.find(quote)?].to_string());
+            }
+        }
+        None
+    }
+
+    fn has_filter(dict: &Dictionary, filter: &[u8]) -> bool {
+        match dict.get(b"Filter") {
+            Ok(Object::Name(name)) => name == filter,
+            Ok(Object::Array(names)) => names.iter().any(|n| n.as_name().map(|n| n == filter).unwrap_or(false)),
+            _ => false,
+        }
+    }
+
+    fn require(&mut self, ok: bool, rule: &str, description: &str) {
+        if !ok {
+            self.fail(rule, description.to_string());
+        }
+    }
+
+    fn fail(&mut self, rule: &str, description: String) {
+        self.failures.push(RuleFailure { rule: rule.to_string(), description, count: Some(1) });
+    }
+
+    /// Collapses repeated failures of the same rule and description.
+    fn merge_counts(self) -> Vec<RuleFailure> {
+        let mut merged: Vec<RuleFailure> = Vec::new();
+        for failure in self.failures {
+            match merged.iter_mut().find(|f| f.rule == failure.rule && f.description == failure.description) {
+                Some(existing) => existing.count = Some(existing.count.unwrap_or(0) + 1),
+                None => merged.push(failure),
+            }
+        }
+        merged
+    }
+}
@@ -0,0 +1,488 @@
+use anyhow::Result;
+use base64::Engine as _;
+use lopdf::{dictionary, Dictionary, Document, Object, ObjectId, Stream};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fmt::Write as _;
+use std::time::Instant;
+use crate::infra::image::RasterImage;
+use crate::infra::pdf_objects::{flatten_inherited, save};
+use crate::infra::split::PdfSplitter;
+use crate::infra::truetype::OutlineFont;
+
+/// Resource names, unlikely to clash with the renderer's own.
+const XOBJECT_NAME: &str = "PdfsynthWatermark";
+const GSTATE_NAME: &str = "PdfsynthWatermarkGs";
+const MAX_TEXT_CHARS: usize = 200;
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WatermarkPosition {
+    #[default]
+    Center,
+    Top,
+    Bottom,
+    Left,
+    Right,
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl WatermarkPosition {
+    /// Horizontal and vertical anchor: -1 start, 0 center, 1 end.
+    fn anchor(&self) -> (i8, i8) {
+        match self {
+            Self::Center => (0, 0),
+            Self::Top => (0, 1),
+            Self::Bottom => (0, -1),
+            Self::Left => (-1, 0),
+            Self::Right => (1, 0),
+            Self::TopLeft => (-1, 1),
+            Self::TopRight => (1, 1),
+            Self::BottomLeft => (-1, -1),
+            Self::BottomRight => (1, -1),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum WatermarkLayer {
+    /// Drawn on top of the page content.
+    #[default]
+    Over,
+    /// Drawn first, so opaque page content hides it.
+    Under,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PageSet {
+    #[default]
+    All,
+    First,
+    Last,
+    Odd,
+    Even,
+}
+
+/// Pages that get the watermark: a named set or 1-based ranges like those
+/// of `/split`.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum WatermarkPages {
+    Set(PageSet),
+    Ranges(Vec<String>),
+}
+
+impl Default for WatermarkPages {
+    fn default() -> Self {
+        Self::Set(PageSet::All)
+    }
+}
+
+/// The `watermark` option: a line of text or an image stamped on the pages.
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Watermark {
+    #[serde(default)]
+    pub text: Option<String>,
+    /// Base64-encoded PNG or JPEG.
+    #[serde(default)]
+    pub image: Option<String>,
+    /// Fontconfig pattern for the text, e.g. `DejaVu Serif:italic`.
+    #[serde(default = "default_font")]
+    pub font: String,
+    /// In points.
+    #[serde(default = "default_font_size")]
+    pub font_size: f32,
+    /// `#rrggbb` or `#rgb`.
+    #[serde(default = "default_color")]
+    pub color: String,
+    /// Image width in points; by default one pixel is 0.75pt, as in CSS.
+    #[serde(default)]
+    pub width: Option<f32>,
+    #[serde(default = "default_opacity")]
+    pub opacity: f32,
+    /// Counterclockwise, in degrees.
+    #[serde(default)]
+    pub rotation: f32,
+    #[serde(default)]
+    pub position: WatermarkPosition,
+    /// Distance from the page edges for positions other than `center`, in points.
+    #[serde(default = "default_margin")]
+    pub margin: f32,
+    #[serde(default)]
+    pub pages: WatermarkPages,
+    #[serde(default)]
+    pub layer: WatermarkLayer,
+}
+
+fn default_font() -> String {
+    "sans-serif:bold".to_string()
+}
+
+fn default_font_size() -> f32 {
+    72.0
+}
+
+fn default_color() -> String {
+    "#808080".to_string()
+}
+
+fn default_opacity() -> f32 {
+    0.3
+}
+
+fn default_margin() -> f32 {
+    36.0
+}
+
+enum Drawing {
+    /// Content stream operators, in stamp space.
+    Paths(Vec<u8>),
+    Image(RasterImage),
+}
+
+/// A watermark with its font outlines or image decoded, ready to be
+/// applied to any number of documents.
+pub struct Stamp {
+    width: f32,
+    height: f32,
+    drawing: Drawing,
+    /// None when drawn opaque.
+    opacity: Option<f32>,
+    settings: Watermark,
+}
+
+impl Watermark {
+    pub fn validate(&self) -> Result<()> {
+        match (&self.text, &self.image) {
+            (Some(_), Some(_)) | (None, None) => {
+                return Err(anyhow::anyhow!("'watermark' needs exactly one of 'text' and 'image'"));
+            }
+            (Some(text), None) if text.trim().is_empty() || text.chars().count() > MAX_TEXT_CHARS || text.contains('\n') => {
+                return Err(anyhow::anyhow!("'watermark.text' must be a single line of 1 to {} characters", MAX_TEXT_CHARS));
+            }
+            _ => {}
+        }
+        if !(1.0..=1000.0).contains(&self.font_size) {
+            return Err(anyhow::anyhow!("'watermark.font_size' must be between 1 and 1000"));
+        }
+        if !(0.0..=1.0).contains(&self.opacity) {
+            return Err(anyhow::anyhow!("'watermark.opacity' must be between 0 and 1"));
+        }
+        if !self.rotation.is_finite() {
+            return Err(anyhow::anyhow!("'watermark.rotation' must be a number of degrees"));
+        }
+        if !(0.0..=1000.0).contains(&self.margin) {
+            return Err(anyhow::anyhow!("'watermark.margin' must be between 0 and 1000"));
+        }
+        if let Some(width) = self.width {
+            if !(1.0..=10000.0).contains(&width) {
+                return Err(anyhow::anyhow!("'watermark.width' must be between 1 and 10000"));
+            }
+        }
+        Self::parse_color(&self.color)?;
+        if let WatermarkPages::Ranges(ranges) = &self.pages {
+            if ranges.is_empty() {
+                return Err(anyhow::anyhow!("'watermark.pages' must not be empty"));
+            }
+            for range in ranges {
+                PdfSplitter::parse_range(range, usize::MAX)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Loads the font or decodes the image. Without `transparency`
+    /// (PDF/A-1), text opacity is emulated with a lighter color, and images
+    /// must be opaque.
+    pub fn prepare(&self, transparency: bool) -> Result<Stamp> {
+        let opaque = self.opacity >= 1.0;
+        let (width, height, drawing, opacity) = match (&self.text, &self.image) {
+            (Some(text), _) => {
+                let mut color = Self::parse_color(&self.color)?;
+                let opacity = match (opaque, transparency) {
+                    (true, _) => None,
+                    (false, true) => Some(self.opacity),
+                    (false, false) => {
+                        // Blend with a white page
+                        color = color.map(|c| 1.0 - self.opacity * (1.0 - c));
+                        None
+                    }
+                };
+                let (width, height, paths) = self.text_paths(text, color)?;
+                (width, height, Drawing::Paths(paths), opacity)
+            }
+            (None, Some(encoded)) => {
+                let data = base64::engine::general_purpose::STANDARD
+                    .decode(encoded.trim())
+                    .map_err(|e| anyhow::anyhow!("'watermark.image' is not valid base64: {}", e))?;
+                let image = RasterImage::decode(&data)?;
+                if !transparency && (!opaque || image.has_alpha()) {
+                    return Err(anyhow::anyhow!(
+                        "PDF/A-1 forbids transparency: use an opaque image without alpha and 'opacity' 1, or PDF/A-2"
+                    ));
+                }
+                let width = self.width.unwrap_or(image.width as f32 * 0.75);
+                let height = width * image.height as f32 / image.width as f32;
+                (width, height, Drawing::Image(image), (!opaque).then_some(self.opacity))
+            }
+            (None, None) => return Err(anyhow::anyhow!("'watermark' needs 'text' or 'image'")),
+        };
+
+        Ok(Stamp { width, height, drawing, opacity, settings: self.clone() })
+    }
+
+    /// Glyph outlines for `text`, with the baseline above the descender.
+    fn text_paths(&self, text: &str, color: [f32; 3]) -> Result<(f32, f32, Vec<u8>)> {
+        let font = OutlineFont::find(&self.font)?;
+        let scale = self.font_size / font.units_per_em();
+        let baseline = -font.descender();
+
+        let mut ops = format!("{:.3} {:.3} {:.3} rg\n", color[0], color[1], color[2]);
+        let width = font
+            .draw_text(text, self.font_size, 0.0, baseline * scale, &mut ops)
+            .map_err(|e| anyhow::anyhow!("Font '{}': {}", self.font, e))?;
+        // TrueType outlines use the nonzero winding rule
+        ops.push_str("f\n");
+
+        let height = (font.ascender() - font.descender()) * scale;
+        Ok((width, height, ops.into_bytes()))
+    }
+
+    fn parse_color(color: &str) -> Result<[f32; 3]> {
+        let invalid = || anyhow::anyhow!("'watermark.color' must be '#rrggbb' or '#rgb', got '{}'", color);
+        let hex = color.strip_prefix('#').ok_or_else(invalid)?;
+        if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(invalid());
+        }
+        let channel = |digits: &str| u8::from_str_radix(digits, 16).map(|v| f32::from(v) / 255.0).map_err(|_| invalid());
+        match hex.len() {
+            6 => Ok([channel(&hex[0..2])?, channel(&hex[2..4])?, channel(&hex[4..6])?]),
+            3 => {
+                let double = |i: usize| hex[i..i + 1].repeat(2);
+                Ok([channel(&double(0))?, channel(&double(1))?, channel(&double(2))?])
+            }
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl Stamp {
+    /// Stamps the selected pages. In tagged PDFs the watermark is marked as
+    /// a pagination artifact, so assistive technologies skip it.
+    pub fn apply(&self, pdf_data: &[u8]) -> Result<Vec<u8>> {
+        let start = Instant::now();
+        let mut doc = Document::load_mem(pdf_data)
+            .map_err(|e| anyhow::anyhow!("Failed to parse PDF: {}", e))?;
+        flatten_inherited(&mut doc);
+        let tagged = doc.catalog()?.has(b"StructTreeRoot");
+
+        let pages: Vec<ObjectId> = doc.get_pages().into_values().collect();
+        let selected = self.selected_pages(pages.len());
+        if selected.is_empty() {
+            return Ok(pdf_data.to_vec());
+        }
+
+        let form_id = self.add_form(&mut doc)?;
+        let gstate_id = self.opacity.map(|opacity| {
+            doc.add_object(dictionary! { "Type" => "ExtGState", "ca" => opacity, "CA" => opacity })
+        });
+
+        for index in &selected {
+            let page_id = pages[*index];
+            let [x0, y0, x1, y1] = Self::page_box(&doc, page_id)?;
+            let matrix = self.placement(x0, y0, x1, y1);
+
+            let mut content = String::from("q\n");
+            if tagged {
+                content.push_str("/Artifact <</Type /Pagination /Subtype /Watermark>> BDC\n");
+            }
+            let _ = writeln!(
+                content,
+                "{:.4} {:.4} {:.4} {:.4} {:.2} {:.2} cm",
+                matrix[0], matrix[1], matrix[2], matrix[3], matrix[4], matrix[5]
+            );
+            if gstate_id.is_some() {
+                let _ = writeln!(content, "/{} gs", GSTATE_NAME);
+            }
+            let _ = writeln!(content, "/{} Do", XOBJECT_NAME);
+            if tagged {
+                content.push_str("EMC\n");
+            }
+            content.push_str("Q\n");
+
+            Self::add_resource(&mut doc, page_id, b"XObject", XOBJECT_NAME, form_id)?;
+            if let Some(gstate_id) = gstate_id {
+                Self::add_resource(&mut doc, page_id, b"ExtGState", GSTATE_NAME, gstate_id)?;
+            }
+            self.add_content(&mut doc, page_id, content.into_bytes())?;
+        }
+
+        tracing::debug!(
+            event = "watermark_applied",
+            page_count = selected.len(),
+            layer = ?self.settings.layer,
+            kind = if self.settings.text.is_some() { "text" } else { "image" },
+            duration_ms = start.elapsed().as_millis() as u64,
+            "Watermark applied"
+        );
+
+        save(&mut doc)
+    }
+
+    fn selected_pages(&self, page_count: usize) -> BTreeSet<usize> {
+        match &self.settings.pages {
+            WatermarkPages::Set(set) => (0..page_count)
+                .filter(|index| match set {
+                    PageSet::All => true,
+                    PageSet::First => *index == 0,
+                    PageSet::Last => *index + 1 == page_count,
+                    // 1-based page numbers
+                    PageSet::Odd => index % 2 == 0,
+                    PageSet::Even => index % 2 == 1,
+                })
+                .collect(),
+            // Ranges past the last page are cut short
+            WatermarkPages::Ranges(ranges) => ranges
+                .iter()
+                .filter_map(|range| PdfSplitter::parse_range(range, usize::MAX).ok())
+                .flat_map(|range| range.start.min(page_count)..range.end.min(page_count))
+                .collect(),
+        }
+    }
+
+    /// The stamp as a form XObject with a `[0 0 width height]` box.
+    fn add_form(&self, doc: &mut Document) -> Result<ObjectId> {
+        let (content, resources) = match &self.drawing {
+            Drawing::Paths(ops) => (ops.clone(), Dictionary::new()),
+            Drawing::Image(image) => {
+                let image_id = image.add_to(doc)?;
+                (
+                    format!("{:.4} 0 0 {:.4} 0 0 cm\n/Im Do\n", self.width, self.height).into_bytes(),
+                    dictionary! { "XObject" => dictionary! { "Im" => image_id } },
+                )
+            }
+        };
+        let mut form = Stream::new(
+            dictionary! {
+                "Type" => "XObject",
+                "Subtype" => "Form",
+                "BBox" => vec![0.into(), 0.into(), self.width.into(), self.height.into()],
+                "Resources" => resources,
+            },
+            content,
+        );
+        form.compress()?;
+        Ok(doc.add_object(form))
+    }
+
+    /// CropBox, or MediaBox, as `[x0 y0 x1 y1]`.
+    fn page_box(doc: &Document, page_id: ObjectId) -> Result<[f32; 4]> {
+        let page = doc.get_dictionary(page_id)?;
+        let rect = page
+            .get(b"CropBox")
+            .or_else(|_| page.get(b"MediaBox"))
+            .map_err(|_| anyhow::anyhow!("Page has no MediaBox"))?;
+        let rect = doc.dereference(rect)?.1.as_array()?;
+        let values: Vec<f32> = rect
+            .iter()
+            .filter_map(|v| doc.dereference(v).ok().and_then(|(_, v)| v.as_float().ok()))
+            .collect();
+        let [a, b, c, d] = values[..] else {
+            return Err(anyhow::anyhow!("Invalid page box"));
+        };
+        Ok([a.min(c), b.min(d), a.max(c), b.max(d)])
+    }
+
+    /// Matrix that rotates the stamp about its center and places it on the
+    /// page; the rotated bounding box keeps the margin.
+    fn placement(&self, x0: f32, y0: f32, x1: f32, y1: f32) -> [f32; 6] {
+        let (sin, cos) = self.settings.rotation.to_radians().sin_cos();
+        let (w, h) = (self.width, self.height);
+        let bound_w = (w * cos).abs() + (h * sin).abs();
+        let bound_h = (w * sin).abs() + (h * cos).abs();
+        let margin = self.settings.margin;
+
+        let axis = |anchor: i8, low: f32, high: f32, size: f32| match anchor {
+            -1 => low + margin + size / 2.0,
+            1 => high - margin - size / 2.0,
+            _ => (low + high) / 2.0,
+        };
+        let (h_anchor, v_anchor) = self.settings.position.anchor();
+        let center_x = axis(h_anchor, x0, x1, bound_w);
+        let center_y = axis(v_anchor, y0, y1, bound_h);
+
+        // Adding zero turns -0 into 0 for the content stream
+        [
+            cos,
+            sin,
+            -sin,
+            cos,
+            center_x - (cos * w / 2.0 - sin * h / 2.0),
+            center_y - (sin * w / 2.0 + cos * h / 2.0),
+        ]
+        .map(|v| v + 0.0)
+    }
+
+    /// Adds `name` to a resource category of the page, following references.
+    fn add_resource(doc: &mut Document, page_id: ObjectId, category: &[u8], name: &str, id: ObjectId) -> Result<()> {
+        let resources_id = match doc.get_dictionary(page_id)?.get(b"Resources") {
+            Ok(Object::Reference(id)) => Some(*id),
+            Ok(_) => None,
+            Err(_) => {
+                doc.get_dictionary_mut(page_id)?.set("Resources", Dictionary::new());
+                None
+            }
+        };
+        let resources = match resources_id {
+            Some(id) => doc.get_dictionary_mut(id)?,
+            None => doc.get_dictionary_mut(page_id)?.get_mut(b"Resources")?.as_dict_mut()?,
+        };
+        let category_id = match resources.get(category) {
+            Ok(Object::Reference(id)) => Some(*id),
+            Ok(_) => None,
+            Err(_) => {
+                resources.set(category, Dictionary::new());
+                None
+            }
+        };
+        let entries = match category_id {
+            Some(id) => doc.get_dictionary_mut(id)?,
+            None => resources.get_mut(category)?.as_dict_mut()?,
+        };
+        entries.set(name, id);
+        Ok(())
+    }
+
+    /// Puts the stamp over or under the page content. Over it, the original
+    /// content is wrapped in `q`/`Q` so its graphics state cannot leak.
+    fn add_content(&self, doc: &mut Document, page_id: ObjectId, stamp: Vec<u8>) -> Result<()> {
+        let existing: Vec<Object> = match doc.get_dictionary(page_id)?.get(b"Contents") {
+            Ok(contents) => match doc.dereference(contents)? {
+                (_, Object::Array(items)) => items.clone(),
+                _ => vec![contents.clone()],
+            },
+            Err(_) => Vec::new(),
+        };
+        let stamp_id = doc.add_object(Stream::new(Dictionary::new(), stamp));
+
+        let contents = match self.settings.layer {
+            WatermarkLayer::Under => std::iter::once(Object::Reference(stamp_id)).chain(existing).collect(),
+            WatermarkLayer::Over => {
+                let open_id = doc.add_object(Stream::new(Dictionary::new(), b"q\n".to_vec()));
+                let close_id = doc.add_object(Stream::new(Dictionary::new(), b"\nQ\n".to_vec()));
+                std::iter::once(Object::Reference(open_id))
+                    .chain(existing)
+                    .chain([Object::Reference(close_id), Object::Reference(stamp_id)])
+                    .collect::<Vec<_>>()
+            }
+        };
+        doc.get_dictionary_mut(page_id)?.set("Contents", contents);
+        Ok(())
+    }
+}
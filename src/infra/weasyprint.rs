@@ -0,0 +1,213 @@
+use std::process::Stdio;
+use std::time::{Duration, Instant};
+use anyhow::Result;
+use tempfile::NamedTempFile;
+use tokio::process::Command;
+use crate::core::renderer::{Engine, Renderer};
+use crate::infra::browser::{EmulationOptions, PageSource, PrintOptions, PrintedPdf};
+use crate::infra::url_allowlist::UrlAllowlist;
+
+/// Page box used by the Chrome engine, applied as a user stylesheet so any
+/// `@page` rule in the template still wins.
+const DEFAULT_PAGE_CSS: &str = "@page { size: A4 landscape; margin: 0; }";
+
+/// Runs WeasyPrint with a URL fetcher that only reaches allowlisted http(s)
+/// hosts, following redirects only when they stay on the allowlist. `file:`
+/// and every other scheme are refused; `data:` URIs are decoded as usual.
+/// The HTML is read from stdin with no base URL, so relative links resolve
+/// to nothing. Arguments: output path, media type, page CSS, allowed hosts.
+const WRAPPER: &str = r#"
+import sys, urllib.request
+from urllib.parse import urlsplit
+from weasyprint import CSS, HTML, default_url_fetcher
+
+output, media_type, page_css, hosts = sys.argv[1:5]
+hosts = [h for h in hosts.split(",") if h]
+
+def check(url):
+    parts = urlsplit(url)
+    host = (parts.hostname or "").lower()
+    if parts.scheme not in ("http", "https") or not any(
+        host.endswith(h[1:]) if h.startswith("*.") else host == h for h in hosts
+    ):
+        raise ValueError("URL is not allowed: " + url)
+
+class Redirects(urllib.request.HTTPRedirectHandler):
+    def redirect_request(self, req, fp, code, msg, headers, newurl):
+        check(newurl)
+        return super().redirect_request(req, fp, code, msg, headers, newurl)
+
+opener = urllib.request.build_opener(Redirects)
+
+def url_fetcher(url, timeout=10, ssl_context=None):
+    if url.startswith("data:"):
+        return default_url_fetcher(url)
+    check(url)
+    response = opener.open(url, timeout=timeout)
+    return {
+        "string": response.read(),
+        "mime_type": response.headers.get_content_type(),
+        "encoding": response.headers.get_param("charset"),
+        "redirected_url": response.geturl(),
+    }
+
+html = sys.stdin.buffer.read().decode("utf-8")
+HTML(string=html, url_fetcher=url_fetcher, media_type=media_type).write_pdf(
+    output, stylesheets=[CSS(string=page_css, url_fetcher=url_fetcher)]
+)
+"#;
+
+/// WeasyPrint renderer, run through Python so its URL fetcher can be
+/// restricted to the URL allowlist.
+///
+/// WeasyPrint does not run JavaScript and fetches resources itself, so it
+/// only accepts template HTML and ignores the browser emulation options that
+/// have no meaning without a browser.
+#[derive(Clone, Debug)]
+pub struct WeasyPrint {
+    python: String,
+    timeout: Duration,
+    allowlist: UrlAllowlist,
+}
+
+impl WeasyPrint {
+    /// Uses `WEASYPRINT_PYTHON` (default `python3`) if it can import
+    /// `weasyprint`, otherwise returns `None` so the engine is reported as
+    /// unavailable. `WEASYPRINT_TIMEOUT_SECS` (default 60) bounds each render.
+    pub fn detect(allowlist: &UrlAllowlist) -> Option<Self> {
+        let python = std::env::var("WEASYPRINT_PYTHON").unwrap_or_else(|_| "python3".to_string());
+        let output = std::process::Command::new(&python)
+            .arg("-c")
+            .arg("import weasyprint; print(weasyprint.__version__)")
+            .output()
+            .ok()?;
+        if !output.status.success() {
+            return None;
+        }
+        let timeout_secs = std::env::var("WEASYPRINT_TIMEOUT_SECS")
+            .ok()
+            .and_then(|v| v.parse::<u64>().ok())
+            .unwrap_or(60);
+
+        tracing::info!(
+            event = "weasyprint_detected",
+            python = %python,
+            version = %String::from_utf8_lossy(&output.stdout).trim(),
+            timeout_secs = timeout_secs,
+            "WeasyPrint engine available"
+        );
+        Some(Self { python, timeout: Duration::from_secs(timeout_secs), allowlist: allowlist.clone() })
+    }
+
+    async fn run(&self, html: &str, media_type: &str) -> Result<Vec<u8>> {
+        let start = Instant::now();
+        let output_file = NamedTempFile::new()?;
+
+        let mut child = Command::new(&self.python)
+            .arg("-c")
+            .arg(WRAPPER)
+            .arg(output_file.path())
+            .arg(media_type)
+            .arg(DEFAULT_PAGE_CSS)
+            .arg(self.allowlist.hosts().join(","))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| {
+                tracing::error!(
+                    event = "weasyprint_execute_failed",
+                    error = %e,
+                    "Failed to execute WeasyPrint"
+                );
+                anyhow::anyhow!("Failed to execute weasyprint: {}", e)
+            })?;
+
+        let mut stdin = child.stdin.take().ok_or_else(|| anyhow::anyhow!("WeasyPrint stdin unavailable"))?;
+        let html = html.as_bytes().to_vec();
+        let run = async move {
+            tokio::io::AsyncWriteExt::write_all(&mut stdin, &html).await?;
+            drop(stdin);
+            child.wait_with_output().await
+        };
+        let output = match tokio::time::timeout(self.timeout, run).await {
+            Ok(output) => output?,
+            Err(_) => {
+                tracing::error!(
+                    event = "weasyprint_timeout",
+                    timeout_secs = self.timeout.as_secs(),
+                    "WeasyPrint timed out, process killed"
+                );
+                return Err(anyhow::anyhow!("WeasyPrint timed out after {}s", self.timeout.as_secs()));
+            }
+        };
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            tracing::error!(
+                event = "weasyprint_pdf_failed",
+                duration_ms = start.elapsed().as_millis() as u64,
+                exit_code = output.status.code(),
+                stderr = %stderr,
+                "WeasyPrint PDF generation failed"
+            );
+            return Err(anyhow::anyhow!("WeasyPrint failed with status: {}. Stderr: {}", output.status, stderr));
+        }
+
+        Ok(tokio::fs::read(output_file.path()).await?)
+    }
+}
+
+impl Renderer for WeasyPrint {
+    fn engine(&self) -> Engine {
+        Engine::Weasyprint
+    }
+
+    fn check_support(&self, source: &PageSource<'_>, emulation: &EmulationOptions, print: &PrintOptions) -> Result<()> {
+        if matches!(source, PageSource::Url { .. }) {
+            return Err(anyhow::anyhow!("URL mode is only supported by the chrome engine"));
+        }
+        if print.tagged || print.outline.is_some() {
+            return Err(anyhow::anyhow!("'tagged' and 'outline' are only supported by the chrome engine"));
+        }
+        if emulation.viewport_width.is_some()
+            || emulation.viewport_height.is_some()
+            || emulation.device_scale_factor.is_some()
+            || emulation.color_scheme.is_some()
+        {
+            return Err(anyhow::anyhow!(
+                "Viewport, device_scale_factor and color_scheme are only supported by the chrome engine"
+            ));
+        }
+        Ok(())
+    }
+
+    fn render(&self, source: PageSource<'_>, emulation: &EmulationOptions, _print: &PrintOptions) -> Result<PrintedPdf> {
+        let PageSource::Html(html) = source else {
+            return Err(anyhow::anyhow!("URL mode is only supported by the chrome engine"));
+        };
+
+        let start = Instant::now();
+        tracing::debug!(
+            event = "weasyprint_pdf_started",
+            html_size_bytes = html.len(),
+            "Starting WeasyPrint PDF generation"
+        );
+
+        // The trait is synchronous; let the runtime move other tasks off this worker
+        let media_type = emulation.media_type.as_deref().unwrap_or("print");
+        let data = tokio::task::block_in_place(|| {
+            tokio::runtime::Handle::current().block_on(self.run(html, media_type))
+        })?;
+        tracing::debug!(
+            event = "weasyprint_pdf_complete",
+            duration_ms = start.elapsed().as_millis() as u64,
+            pdf_size_bytes = data.len(),
+            "WeasyPrint PDF generation completed"
+        );
+
+        // Fallback runs come from Chrome; missing glyphs are still found in the PDF
+        Ok(PrintedPdf { data, headings: Vec::new(), font_fallbacks: Vec::new() })
+    }
+}
@@ -0,0 +1,220 @@
+use lopdf::{text_string, Document};
+use crate::infra::pdf_objects::{dict_text, info_dict_mut};
+
+/// Builder for the XMP packet embedded as the document `/Metadata` stream.
+///
+/// PDF/A requires the XMP properties to mirror the `/Info` dictionary, so
+/// callers fill this from the same values they write to DocInfo.
+#[derive(Debug, Clone, Default)]
+pub struct XmpPacket {
+    pub title: Option<String>,
+    /// `/Author`, written as the single `dc:creator` entry.
+    pub author: Option<String>,
+    /// `/Subject`, written as `dc:description`.
+    pub subject: Option<String>,
+    /// `/Keywords`, written as `pdf:Keywords`.
+    pub keywords: Option<String>,
+    pub creator_tool: Option<String>,
+    pub producer: Option<String>,
+    /// XMP date, see [`pdf_date_to_xmp`].
+    pub create_date: Option<String>,
+    pub modify_date: Option<String>,
+    /// BCP 47 tag for `dc:language`.
+    pub language: Option<String>,
+    /// PDF/A identification: part number and conformance letter.
+    pub pdfa: Option<(u8, char)>,
+}
+
+impl XmpPacket {
+    /// Builds the packet from `/Info`, writing back normalized text so both
+    /// stay in sync as PDF/A requires.
+    pub fn from_info(doc: &mut Document, language: Option<&str>, pdfa: Option<(u8, char)>) -> Self {
+        let info = info_dict_mut(doc).clone();
+        let text = |key: &[u8]| dict_text(doc, &info, key);
+
+        let xmp = XmpPacket {
+            title: text(b"Title"),
+            author: text(b"Author"),
+            subject: text(b"Subject"),
+            keywords: text(b"Keywords"),
+            creator_tool: text(b"Creator"),
+            producer: text(b"Producer"),
+            create_date: text(b"CreationDate").and_then(|d| pdf_date_to_xmp(&d)),
+            modify_date: text(b"ModDate").and_then(|d| pdf_date_to_xmp(&d)),
+            language: language.map(str::to_string),
+            pdfa,
+        };
+
+        let info = info_dict_mut(doc);
+        for (key, value) in [
+            (b"Title".as_slice(), &xmp.title),
+            (b"Author", &xmp.author),
+            (b"Subject", &xmp.subject),
+            (b"Keywords", &xmp.keywords),
+            (b"Creator", &xmp.creator_tool),
+            (b"Producer", &xmp.producer),
+        ] {
+            if let Some(value) = value {
+                info.set(key, text_string(value));
+            }
+        }
+
+        xmp
+    }
+
+    pub fn to_xml(&self) -> String {
+        let mut description = String::new();
+
+        if let Some(title) = &self.title {
+            description.push_str(&format!(
+                "      <dc:title><rdf:Alt><rdf:li xml:lang='x-default'>{}</rdf:li></rdf:Alt></dc:title>\n",
+                escape_xml(title)
+            ));
+        }
+        if let Some(author) = &self.author {
+            description.push_str(&format!(
+                "      <dc:creator><rdf:Seq><rdf:li>{}</rdf:li></rdf:Seq></dc:creator>\n",
+                escape_xml(author)
+            ));
+        }
+        if let Some(subject) = &self.subject {
+            description.push_str(&format!(
+                "      <dc:description><rdf:Alt><rdf:li xml:lang='x-default'>{}</rdf:li></rdf:Alt></dc:description>\n",
+                escape_xml(subject)
+            ));
+        }
+        if let Some(language) = &self.language {
+            description.push_str(&format!(
+                "      <dc:language><rdf:Bag><rdf:li>{}</rdf:li></rdf:Bag></dc:language>\n",
+                escape_xml(language)
+            ));
+        }
+        if let Some(keywords) = &self.keywords {
+            description.push_str(&format!("      <pdf:Keywords>{}</pdf:Keywords>\n", escape_xml(keywords)));
+        }
+        if let Some(tool) = &self.creator_tool {
+            description.push_str(&format!("      <xmp:CreatorTool>{}</xmp:CreatorTool>\n", escape_xml(tool)));
+        }
+        if let Some(date) = &self.create_date {
+            description.push_str(&format!("      <xmp:CreateDate>{}</xmp:CreateDate>\n", date));
+        }
+        if let Some(date) = &self.modify_date {
+            description.push_str(&format!("      <xmp:ModifyDate>{}</xmp:ModifyDate>\n", date));
+        }
+        if let Some(producer) = &self.producer {
+            description.push_str(&format!("      <pdf:Producer>{}</pdf:Producer>\n", escape_xml(producer)));
+        }
+        if let Some((part, conformance)) = self.pdfa {
+            description.push_str(&format!("      <pdfaid:part>{}</pdfaid:part>\n", part));
+            description.push_str(&format!("      <pdfaid:conformance>{}</pdfaid:conformance>\n", conformance));
+        }
+
+        format!(
+            "<?xpacket begin='\u{feff}' id='W5M0MpCehiHzreSzNTczkc9d'?>\n\
+<x:xmpmeta xmlns:x='adobe:ns:meta/'>\n\
+  <rdf:RDF xmlns:rdf='http://www.w3.org/1999/02/22-rdf-syntax-ns#'>\n\
+    <rdf:Description rdf:about=''\n\
+        xmlns:dc='http://purl.org/dc/elements/1.1/'\n\
+        xmlns:xmp='http://ns.adobe.com/xap/1.0/'\n\
+        xmlns:pdf='http://ns.adobe.com/pdf/1.3/'\n\
+        xmlns:pdfaid='http://www.aiim.org/pdfa/ns/id/'>\n\
+{}    </rdf:Description>\n\
+  </rdf:RDF>\n\
+</x:xmpmeta>\n\
+<?xpacket end='w'?>",
+            description
+        )
+    }
+}
+
+/// Converts a PDF date (`D:YYYYMMDDHHmmSSOHH'mm'`) to the ISO 8601 form XMP uses.
+pub fn pdf_date_to_xmp(date: &str) -> Option<String> {
+    let digits = date.strip_prefix("D:").unwrap_or(date);
+    let field = |range: std::ops::Range<usize>, default: &'static str| -> Option<String> {
+        match digits.get(range) {
+            Some(s) if s.chars().all(|c| c.is_ascii_digit()) => Some(s.to_string()),
+            Some(_) => None,
+            None => Some(default.to_string()),
+        }
+    };
+
+    let year = digits.get(0..4).filter(|s| s.chars().all(|c| c.is_ascii_digit()))?;
+    let month = field(4..6, "01")?;
+    let day = field(6..8, "01")?;
+    let hour = field(8..10, "00")?;
+    let minute = field(10..12, "00")?;
+    let second = field(12..14, "00")?;
+
+    let offset = match digits.get(14..) {
+        None | Some("") | Some("Z") => "Z".to_string(),
+        Some(tz) if tz.starts_with("Z") => "Z".to_string(),
+        Some(tz) => {
+            let sign = tz.chars().next().filter(|c| *c == '+' || *c == '-')?;
+            let rest: String = tz[1..].chars().filter(|c| c.is_ascii_digit()).collect();
+            let hh = rest.get(0..2).unwrap_or("00");
+            let mm = rest.get(2..4).unwrap_or("00");
+            format!("{}{}:{}", sign, hh, mm)
+        }
+    };
+
+    Some(format!("{}-{}-{}T{}:{}:{}{}", year, month, day, hour, minute, second, offset))
+}
+
+/// Replaces the value of an XMP property written either as an element
+/// (`<ns:Name>value</ns:Name>`) or as an attribute (`ns:Name='value'`).
+pub fn replace_property(xmp: &str, name: &str, value: &str) -> String {
+    let open = format!("<{}>", name);
+    let close = format!("</{}>", name);
+    let mut out = xmp.to_string();
+
+    if let Some(start) = out.find(&open) {
+        if let Some(end) = out[start..].find(&close) {
+            let content_start = start + open.len();
+            out.replace_range(content_start..start + end, value);
+        }
+    }
+
+    for quote in ['\'', '"'] {
+        let attr = format!("{}={}", name, quote);
+        if let Some(start) = out.find(&attr) {
+            let value_start = start + attr.len();
+            if let Some(len) = out[value_start..].find(quote) {
+                out.replace_range(value_start..value_start + len, value);
+            }
+        }
+    }
+
+    out
+}
+
+/// Inserts an `rdf:Description` element at the end of the RDF graph,
+/// unless one declaring the same namespaces is already there.
+pub fn add_description(xmp: &str, description: &str) -> String {
+    let declarations: Vec<&str> = description.split_whitespace().filter(|t| t.starts_with("xmlns:")).collect();
+    if !declarations.is_empty() && declarations.iter().all(|d| xmp.contains(d)) {
+        return xmp.to_string();
+    }
+    match xmp.rfind("</rdf:RDF>") {
+        Some(end) => {
+            let mut out = xmp.to_string();
+            out.insert_str(end, description);
+            out
+        }
+        None => xmp.to_string(),
+    }
+}
+
+pub fn escape_xml(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '\'' => out.push_str("&apos;"),
+            '"' => out.push_str("&quot;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
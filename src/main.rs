@@ -1,4 +1,5 @@
 use axum::{routing::get, Router, extract::DefaultBodyLimit};
+use std::sync::Arc;
 use tower_http::trace::TraceLayer;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
 
@@ -38,6 +39,25 @@ async fn main() {
         }
     };
 
+    let url_allowlist = infra::url_allowlist::UrlAllowlist::from_env();
+    tracing::info!(
+        event = "url_allowlist_configured",
+        allowed_hosts = ?url_allowlist.hosts(),
+        url_mode_enabled = !url_allowlist.hosts().is_empty(),
+        "URL source allowlist configured"
+    );
+
+    let mut engines: Vec<Arc<dyn core::renderer::Renderer>> = vec![Arc::new(browser.clone())];
+    if let Some(weasyprint) = infra::weasyprint::WeasyPrint::detect(&url_allowlist) {
+        engines.push(Arc::new(weasyprint));
+    }
+    let renderers = core::renderer::Renderers::new(engines);
+    tracing::info!(
+        event = "renderers_configured",
+        engines = ?renderers.available(),
+        "Rendering engines configured"
+    );
+
     let template_engine = match infra::templates::TemplateEngine::new() {
         Ok(t) => {
             tracing::info!(event = "template_engine_initialized", "Template engine initialized successfully");
@@ -49,7 +69,92 @@ async fn main() {
         }
     };
 
-    let state = api::state::AppState { browser, template_engine };
+    let fonts = match infra::fonts::FontStore::from_env() {
+        Ok(f) => f,
+        Err(e) => {
+            tracing::error!(event = "font_store_init_failed", error = %e, "Failed to configure font store");
+            panic!("Failed to configure font store: {}", e);
+        }
+    };
+    tracing::info!(
+        event = "font_store_configured",
+        shared_dir = %fonts.shared_dir().display(),
+        tenant_dir = %fonts.tenant_dir().display(),
+        shared_uploads = fonts.shared_uploads_enabled(),
+        tenant_count = fonts.tenant_count(),
+        "Font directories configured"
+    );
+
+    let assets = match infra::assets::Assets::from_env() {
+        Ok(a) => {
+            tracing::info!(
+                event = "assets_initialized",
+                dir = %a.dir().display(),
+                embedded = a.is_embedded(),
+                "Assets verified"
+            );
+            a
+        }
+        Err(e) => {
+            tracing::error!(event = "assets_init_failed", error = %e, "Failed to initialize assets");
+            panic!("Failed to initialize assets: {}", e);
+        }
+    };
+
+    let ghostscript = infra::ghostscript::Ghostscript::from_env(assets.clone());
+    tracing::info!(
+        event = "ghostscript_configured",
+        max_concurrency = ghostscript.max_concurrency(),
+        timeout_secs = ghostscript.timeout().as_secs(),
+        memory_limit_bytes = ghostscript.memory_limit(),
+        cpu_limit_secs = ghostscript.cpu_limit(),
+        "Ghostscript limits configured"
+    );
+
+    let icc_profiles = infra::color::IccProfiles::from_env();
+    tracing::info!(
+        event = "icc_profiles_configured",
+        dir = %icc_profiles.dir().display(),
+        installed = ?icc_profiles.installed(),
+        "CMYK output profiles configured"
+    );
+
+    let pdfa_validator = infra::validation::PdfaValidator::detect();
+    tracing::info!(
+        event = "pdfa_validator_configured",
+        validator = pdfa_validator.name(),
+        max_concurrency = pdfa_validator.max_concurrency(),
+        timeout_secs = pdfa_validator.timeout().as_secs(),
+        "PDF/A validator configured"
+    );
+
+    let signer = match infra::signing::Signer::from_env() {
+        Ok(s) => {
+            tracing::info!(
+                event = "signing_configured",
+                keys_dir = %s.dir().display(),
+                key_ids = ?s.key_ids(),
+                default_key = ?s.default_key(),
+                tsa_url = ?s.tsa_url(),
+                policy = s.has_policy(),
+                "Signing keys loaded"
+            );
+            s
+        }
+        Err(e) => {
+            tracing::error!(event = "signing_init_failed", error = %e, "Failed to load signing keys");
+            panic!("Failed to load signing keys: {}", e);
+        }
+    };
+
+    let linearizer = infra::linearize::Linearizer::detect();
+    tracing::info!(
+        event = "linearizer_configured",
+        available = linearizer.is_some(),
+        "Linearized output configured"
+    );
+
+    let state = api::state::AppState { browser, renderers, template_engine, url_allowlist, fonts, assets, ghostscript, icc_profiles, pdfa_validator, signer, linearizer };
 
     // Limite de 100MB para o body (imagens base64 podem ser grandes)
     let body_limit = std::env::var("MAX_BODY_SIZE_MB")
@@ -68,6 +173,9 @@ async fn main() {
         .route("/health", get(api::health::health_check))
         .route("/render/debug", axum::routing::post(api::render::render_html))
         .route("/render", axum::routing::post(api::render::render_pdf))
+        .route("/merge", axum::routing::post(api::merge::merge_pdf))
+        .route("/split", axum::routing::post(api::split::split_pdf))
+        .route("/fonts", get(api::fonts::list_fonts).post(api::fonts::upload_font))
         .with_state(state)
         .layer(DefaultBodyLimit::max(body_limit))
         .layer(TraceLayer::new_for_http());
@@ -16,13 +16,17 @@ echo "1. Testing /health..."
 curl -f "$BASE_URL/health"
 echo ""
 
-echo "2. Testing /render/debug (HTML)..."
-curl -X POST "$BASE_URL/render/debug" \
+echo "2. Testing /render/debug (HTML + browser report)..."
+curl -f -X POST "$BASE_URL/render/debug" \
   -H "Content-Type: application/json" \
   -d '{
-    "template_html": "<!DOCTYPE html><html><body><h1>Hello {{ name }}</h1></body></html>",
+    "template_html": "<!DOCTYPE html><html><body><h1>Hello {{ name }}</h1><script>console.warn(\"debug-marker\"); undefinedFn();</script></body></html>",
     "data": { "name": "Docker World" }
-  }'
+  }' > debug_report.json
+cat debug_report.json
+grep -q "Hello Docker World" debug_report.json
+grep -q "debug-marker" debug_report.json
+grep -q "undefinedFn" debug_report.json
 echo ""
 
 echo "3. Testing /render (PDF)..."
@@ -45,4 +49,417 @@ curl -X POST "$BASE_URL/render" \
   }' --output output_pdfa.pdf
 echo "Generated output_pdfa.pdf"
 
+echo "5. Testing /render (emulation overrides)..."
+curl -f -X POST "$BASE_URL/render" \
+  -H "Content-Type: application/json" \
+  -d '{
+    "template_html": "<!DOCTYPE html><html><body><p id=\"d\"></p><script>document.getElementById(\"d\").textContent = new Date(0).toLocaleString();</script></body></html>",
+    "data": {},
+    "options": { "pdf_a": false, "paper_format": "A4", "media_type": "screen", "viewport_width": 1024, "viewport_height": 768, "device_scale_factor": 2, "color_scheme": "dark", "timezone": "America/Sao_Paulo", "locale": "pt-BR" }
+  }' --output output_emulation.pdf
+echo "Generated output_emulation.pdf"
+
+echo "6. Testing /render (URL mode outside allowlist is rejected)..."
+STATUS=$(curl -s -o /dev/null -w "%{http_code}" -X POST "$BASE_URL/render" \
+  -H "Content-Type: application/json" \
+  -d '{
+    "source": { "url": "https://not-allowed.example.com/" },
+    "options": { "pdf_a": false, "paper_format": "A4" }
+  }')
+if [ "$STATUS" != "403" ]; then
+    echo "Expected 403, got $STATUS"
+    exit 1
+fi
+echo "Got 403 as expected"
+
+echo "7. Testing /render (tagged PDF/A)..."
+curl -f -X POST "$BASE_URL/render" \
+  -H "Content-Type: application/json" \
+  -d '{
+    "template_html": "<!DOCTYPE html><html lang=\"pt-BR\"><head><title>Certificado</title></head><body><h1>Olá {{ name }}</h1><p>Texto</p></body></html>",
+    "data": { "name": "Tagged World" },
+    "options": { "pdf_a": true, "paper_format": "A4", "tagged": true }
+  }' --output output_tagged_pdfa.pdf
+grep -a -q "/StructTreeRoot" output_tagged_pdfa.pdf
+grep -a -q "<pdfaid:conformance>A</pdfaid:conformance>" output_tagged_pdfa.pdf
+echo "Generated output_tagged_pdfa.pdf"
+
+echo "8. Testing /render (document outline)..."
+curl -f -X POST "$BASE_URL/render" \
+  -H "Content-Type: application/json" \
+  -d '{
+    "template_html": "<!DOCTYPE html><html lang=\"en\"><body><h1>Report</h1><h2>Summary</h2><h2 class=\"no-toc\">Hidden</h2><h3>Too deep</h3></body></html>",
+    "data": {},
+    "options": { "pdf_a": true, "paper_format": "A4", "outline": { "max_depth": 2, "exclude_classes": ["no-toc"] } }
+  }' --output output_outline.pdf
+grep -a -q "/Outlines" output_outline.pdf
+if grep -a -q "(Hidden)" output_outline.pdf; then
+    echo "Excluded heading found in outline"
+    exit 1
+fi
+echo "Generated output_outline.pdf"
+
+echo "9. Testing /render (deterministic output)..."
+DETERMINISTIC_PAYLOAD='{
+    "template_html": "<html><body><h1>Issued {{ now() | date(format=\"%Y-%m-%d\") }}</h1><p id=\"js\"></p><script>document.getElementById(\"js\").textContent = new Date().toISOString();</script></body></html>",
+    "data": {},
+    "options": { "pdf_a": true, "paper_format": "A4", "deterministic": true, "timestamp": "2024-05-01T12:00:00Z" }
+  }'
+for run in 1 2; do
+  curl -f -X POST "$BASE_URL/render" \
+    -H "Content-Type: application/json" \
+    -d "$DETERMINISTIC_PAYLOAD" --output "output_deterministic_$run.pdf"
+done
+cmp output_deterministic_1.pdf output_deterministic_2.pdf
+echo "Deterministic renders are byte-identical"
+
+echo "10. Testing /render (weasyprint engine)..."
+curl -f -X POST "$BASE_URL/render" \
+  -H "Content-Type: application/json" \
+  -d '{
+    "template_html": "<html><head><style>@page { size: A5; @bottom-center { content: counter(page) } }</style></head><body><h1>Hello {{ name }}</h1></body></html>",
+    "data": { "name": "WeasyPrint" },
+    "options": { "pdf_a": false, "paper_format": "A4", "engine": "weasyprint" }
+  }' --output output_weasyprint.pdf
+head -c 5 output_weasyprint.pdf | grep -q "%PDF-"
+echo "Generated output_weasyprint.pdf"
+
+echo "11. Testing /fonts (tenant upload and listing)..."
+FONT_FILE=$(fc-match -f '%{file}' "DejaVu Sans" 2>/dev/null || true)
+# Uploads never replace a file, so every run uses a new name
+FONT_NAME="e2e-font-$(date +%s).ttf"
+STATUS=$(curl -s -o /dev/null -w "%{http_code}" "$BASE_URL/fonts" -H "X-Tenant-Id: e2e")
+if [ "$STATUS" != "403" ]; then
+  echo "Expected 403 for a tenant request without its token, got $STATUS"
+  exit 1
+fi
+if [ -n "$FONT_FILE" ] && [ -f "$FONT_FILE" ]; then
+  STATUS=$(curl -s -o /dev/null -w "%{http_code}" -X POST "$BASE_URL/fonts?filename=$FONT_NAME" \
+    --data-binary @"$FONT_FILE")
+  if [ "$STATUS" != "403" ]; then
+    echo "Expected 403 for a shared upload without the operator token, got $STATUS"
+    exit 1
+  fi
+fi
+if [ -n "$E2E_TENANT_TOKEN" ] && [ -n "$E2E_OTHER_TENANT_TOKEN" ]; then
+  if [ -n "$FONT_FILE" ] && [ -f "$FONT_FILE" ]; then
+    curl -f -X POST "$BASE_URL/fonts?filename=$FONT_NAME" \
+      -H "X-Tenant-Id: e2e" -H "Authorization: Bearer $E2E_TENANT_TOKEN" \
+      --data-binary @"$FONT_FILE" | grep -q '"source":"tenant"'
+    curl -f -s "$BASE_URL/fonts" -H "X-Tenant-Id: e2e" -H "Authorization: Bearer $E2E_TENANT_TOKEN" | grep -q "$FONT_NAME"
+    if curl -f -s "$BASE_URL/fonts" -H "X-Tenant-Id: other" -H "Authorization: Bearer $E2E_OTHER_TENANT_TOKEN" | grep -q "$FONT_NAME"; then
+      echo "Tenant font leaked to another tenant"
+      exit 1
+    fi
+    echo "Tenant font isolated"
+    STATUS=$(curl -s -o /dev/null -w "%{http_code}" -X POST "$BASE_URL/fonts?filename=$FONT_NAME" \
+      -H "X-Tenant-Id: e2e" -H "Authorization: Bearer $E2E_OTHER_TENANT_TOKEN" --data-binary @"$FONT_FILE")
+    if [ "$STATUS" != "403" ]; then
+      echo "Expected 403 for an upload with another tenant's token, got $STATUS"
+      exit 1
+    fi
+    STATUS=$(curl -s -o /dev/null -w "%{http_code}" -X POST "$BASE_URL/fonts?filename=$FONT_NAME" \
+      -H "X-Tenant-Id: e2e" -H "Authorization: Bearer $E2E_TENANT_TOKEN" --data-binary @"$FONT_FILE")
+    if [ "$STATUS" != "400" ]; then
+      echo "Expected 400 for an existing font name, got $STATUS"
+      exit 1
+    fi
+  else
+    echo "No local TTF found, skipping font upload"
+  fi
+  STATUS=$(curl -s -o /dev/null -w "%{http_code}" -X POST "$BASE_URL/fonts?filename=bad.ttf" \
+    -H "X-Tenant-Id: e2e" -H "Authorization: Bearer $E2E_TENANT_TOKEN" --data-binary "not a font")
+  if [ "$STATUS" != "400" ]; then
+    echo "Expected 400 for an invalid font, got $STATUS"
+    exit 1
+  fi
+else
+  echo "Set E2E_TENANT_TOKEN and E2E_OTHER_TENANT_TOKEN to the tokens of tenants 'e2e' and 'other' in TENANT_TOKENS_FILE to test tenant fonts"
+fi
+
+echo "12. Testing /render (font check)..."
+curl -f -s -D headers_fonts.txt -X POST "$BASE_URL/render" \
+  -H "Content-Type: application/json" \
+  -d '{
+    "template_html": "<html><body style=\"font-family: Liberation Sans\"><p>Plain text</p></body></html>",
+    "data": {},
+    "options": { "pdf_a": false, "paper_format": "A4", "font_check": "report" }
+  }' --output output_fonts.pdf
+grep -qi "^x-fonts-used: .*Liberation" headers_fonts.txt
+grep -qi "^x-missing-glyphs: 0" headers_fonts.txt
+STATUS=$(curl -s -o /dev/null -w "%{http_code}" -X POST "$BASE_URL/render" \
+  -H "Content-Type: application/json" \
+  -d '{
+    "template_html": "<html><body style=\"font-family: Liberation Sans\"><p>\uE000\uE001</p></body></html>",
+    "data": {},
+    "options": { "pdf_a": false, "paper_format": "A4", "font_check": "strict" }
+  }')
+if [ "$STATUS" != "422" ]; then
+    echo "Expected 422 for uncovered text in strict mode, got $STATUS"
+    exit 1
+fi
+echo "Font check reported coverage"
+
+echo "13. Testing /render (PDF/A-2u level)..."
+curl -f -X POST "$BASE_URL/render" \
+  -H "Content-Type: application/json" \
+  -d '{
+    "template_html": "<html><body><h1>Level {{ level }}</h1></body></html>",
+    "data": { "level": "2u" },
+    "options": { "paper_format": "A4", "pdf_a_level": "2u" }
+  }' --output output_pdfa2u.pdf
+grep -a -q -E "pdfaid:part(>|=.)2" output_pdfa2u.pdf
+grep -a -q -E "pdfaid:conformance(>|=.)U" output_pdfa2u.pdf
+echo "Generated output_pdfa2u.pdf"
+
+echo "14. Testing /render (document metadata)..."
+curl -f -X POST "$BASE_URL/render" \
+  -H "Content-Type: application/json" \
+  -d '{
+    "template_html": "<html><body><h1>Invoice</h1></body></html>",
+    "data": {},
+    "options": { "pdf_a": true, "paper_format": "A4" },
+    "metadata": { "title": "Invoice (42)", "author": "E2E", "keywords": "invoice, e2e", "custom": { "InvoiceId": "42" } }
+  }' --output output_metadata.pdf
+grep -a -q "<dc:creator><rdf:Seq><rdf:li>E2E</rdf:li></rdf:Seq></dc:creator>" output_metadata.pdf
+grep -a -q "<pdf:Keywords>invoice, e2e</pdf:Keywords>" output_metadata.pdf
+grep -a -q "/InvoiceId" output_metadata.pdf
+echo "Generated output_metadata.pdf"
+
+echo "15. Testing /render (PDF/A validation report)..."
+curl -f -s -D validation_headers.txt -X POST "$BASE_URL/render" \
+  -H "Content-Type: application/json" \
+  -d '{
+    "template_html": "<html><body><h1>Validated</h1></body></html>",
+    "data": {},
+    "options": { "paper_format": "A4", "pdf_a_level": "2b", "pdf_a_validation": "report" }
+  }' --output output_validated.pdf
+grep -i -q "^x-pdfa-compliant: " validation_headers.txt
+grep -i -q "^x-pdfa-report: {" validation_headers.txt
+echo "Validation report: $(grep -i '^x-pdfa-compliant' validation_headers.txt | tr -d '\r')"
+
+echo "16. Testing /render (optimize)..."
+curl -f -X POST "$BASE_URL/render" \
+  -H "Content-Type: application/json" \
+  -d '{
+    "template_html": "<html><body>{% for i in range(end=3) %}<h1>Certificate {{ i }}</h1>{% endfor %}</body></html>",
+    "data": {},
+    "options": { "paper_format": "A4", "optimize": { "preset": "ebook", "jpeg_quality": 70 } }
+  }' --output output_optimized.pdf
+head -c 5 output_optimized.pdf | grep -q "%PDF-"
+STATUS=$(curl -s -o /dev/null -w "%{http_code}" -X POST "$BASE_URL/render" \
+  -H "Content-Type: application/json" \
+  -d '{"template_html": "<h1>x</h1>", "data": {}, "options": { "paper_format": "A4", "tagged": true, "optimize": "screen" }}')
+if [ "$STATUS" != "400" ]; then
+    echo "Expected 400 for optimize with tagged output, got $STATUS"
+    exit 1
+fi
+echo "Generated output_optimized.pdf"
+
+echo "17. Testing /merge (uploaded PDF and render request)..."
+printf '{"parts": [{"pdf": "%s", "bookmark": "Cover"}, {"render": {"template_html": "<h1>Body</h1><h2>Section</h2>", "data": {}, "options": {"paper_format": "A4", "outline": {}}}, "bookmark": "Body"}]}' \
+  "$(base64 -w0 output_pdfa2u.pdf)" > merge_request.json
+curl -f -X POST "$BASE_URL/merge" \
+  -H "Content-Type: application/json" \
+  --data-binary @merge_request.json --output output_merged.pdf
+grep -a -q "/Outlines" output_merged.pdf
+grep -a -q "(Body)" output_merged.pdf
+echo "Generated output_merged.pdf"
+
+echo "18. Testing /split (bookmarks as zip, ranges as JSON)..."
+printf '{"pdf": "%s", "mode": {"bookmarks": {}}}' "$(base64 -w0 output_merged.pdf)" > split_request.json
+curl -f -X POST "$BASE_URL/split" \
+  -H "Content-Type: application/json" \
+  --data-binary @split_request.json --output output_split.zip
+head -c 2 output_split.zip | grep -q "PK"
+printf '{"pdf": "%s", "mode": {"ranges": ["1", "2-"]}, "format": "json"}' "$(base64 -w0 output_merged.pdf)" > split_request.json
+curl -f -s -X POST "$BASE_URL/split" \
+  -H "Content-Type: application/json" \
+  --data-binary @split_request.json | grep -q '"name":"pages-2-'
+echo "Generated output_split.zip"
+
+echo "19. Testing /render (text watermark with PDF/A-1b)..."
+curl -f -X POST "$BASE_URL/render" \
+  -H "Content-Type: application/json" \
+  -d '{
+    "template_html": "<html><body><h1>Preview</h1></body></html>",
+    "data": {},
+    "options": { "paper_format": "A4", "pdf_a": true, "watermark": { "text": "CÓPIA", "rotation": 45, "opacity": 0.2 } }
+  }' --output output_watermark.pdf
+head -c 5 output_watermark.pdf | grep -q "%PDF-"
+STATUS=$(curl -s -o /dev/null -w "%{http_code}" -X POST "$BASE_URL/render" \
+  -H "Content-Type: application/json" \
+  -d '{"template_html": "<h1>x</h1>", "data": {}, "options": { "paper_format": "A4", "watermark": { "text": "DRAFT", "image": "iVBORw0KGgo=" } }}')
+if [ "$STATUS" != "400" ]; then
+    echo "Expected 400 for a watermark with both text and image, got $STATUS"
+    exit 1
+fi
+echo "Generated output_watermark.pdf"
+
+echo "20. Testing /render (PAdES signature)..."
+STATUS=$(curl -s -o /dev/null -w "%{http_code}" -X POST "$BASE_URL/render" \
+  -H "Content-Type: application/json" \
+  -d '{"template_html": "<h1>x</h1>", "data": {}, "options": { "paper_format": "A4", "signature": { "key_id": "no-such-key" } }}')
+if [ "$STATUS" != "400" ]; then
+    echo "Expected 400 for an unknown signing key, got $STATUS"
+    exit 1
+fi
+if [ -n "$E2E_SIGNING_KEY" ]; then
+    curl -f -X POST "$BASE_URL/render" \
+      -H "Content-Type: application/json" \
+      -d '{
+        "template_html": "<html><body><h1>Certificate</h1></body></html>",
+        "data": {},
+        "options": { "paper_format": "A4", "pdf_a_level": "2b", "signature": { "key_id": "'"$E2E_SIGNING_KEY"'", "reason": "Issued", "appearance": { "x": 340, "y": 40, "width": 220, "height": 60 } } }
+      }' --output output_signed.pdf
+    grep -a -q "/ETSI.CAdES.detached" output_signed.pdf
+    grep -a -q "/ByteRange" output_signed.pdf
+    echo "Generated output_signed.pdf"
+else
+    echo "Set E2E_SIGNING_KEY to a key id loaded by the service to test signing"
+fi
+
+echo "21. Testing /render (PAdES-B-LTA document timestamp)..."
+if [ -n "$E2E_SIGNING_KEY" ] && [ "$E2E_SIGNING_LEVEL" = "b-lta" ]; then
+    curl -f -X POST "$BASE_URL/render" \
+      -H "Content-Type: application/json" \
+      -d '{
+        "template_html": "<html><body><h1>Contract</h1></body></html>",
+        "data": {},
+        "options": { "paper_format": "A4", "signature": { "key_id": "'"$E2E_SIGNING_KEY"'", "level": "b-lta" } }
+      }' --output output_signed_lta.pdf
+    grep -a -q "/ETSI.CAdES.detached" output_signed_lta.pdf
+    grep -a -q "/DocTimeStamp" output_signed_lta.pdf
+    grep -a -q "/ETSI.RFC3161" output_signed_lta.pdf
+    grep -a -q "/DSS" output_signed_lta.pdf
+    echo "Generated output_signed_lta.pdf"
+else
+    echo "Set E2E_SIGNING_LEVEL=b-lta, with a TSA configured (see tests/mock_tsa.py), to test document timestamps"
+fi
+
+echo "22. Testing /render (encryption)..."
+curl -f -X POST "$BASE_URL/render" \
+  -H "Content-Type: application/json" \
+  -d '{
+    "template_html": "<html><body><h1>Payslip</h1><p>{{ name }}</p></body></html>",
+    "data": { "name": "Maria" },
+    "options": { "paper_format": "A4", "security": { "user_password": "12345678900", "owner_password": "rh-secret", "permissions": ["print"], "algorithm": "AES-256" } }
+  }' --output output_encrypted.pdf
+grep -a -q "/Encrypt" output_encrypted.pdf
+grep -a -q "/AESV3" output_encrypted.pdf
+if command -v qpdf > /dev/null; then
+    qpdf --warning-exit-0 --password=12345678900 --decrypt output_encrypted.pdf /dev/null
+    qpdf --warning-exit-0 --password=rh-secret --decrypt output_encrypted.pdf /dev/null
+    if qpdf --password=wrong --decrypt output_encrypted.pdf /dev/null 2> /dev/null; then
+        echo "Encrypted PDF opened with a wrong password"
+        exit 1
+    fi
+    qpdf --password=12345678900 --show-encryption output_encrypted.pdf | grep -q "print high resolution: allowed"
+    qpdf --password=12345678900 --show-encryption output_encrypted.pdf | grep -q "extract for any purpose: not allowed"
+    echo "Encrypted PDF opens with the user and owner passwords only"
+else
+    echo "qpdf not found, skipping password checks"
+fi
+STATUS=$(curl -s -o /dev/null -w "%{http_code}" -X POST "$BASE_URL/render" \
+  -H "Content-Type: application/json" \
+  -d '{"template_html": "<h1>x</h1>", "data": {}, "options": { "paper_format": "A4", "pdf_a_level": "2b", "security": { "user_password": "x" } }}')
+if [ "$STATUS" != "400" ]; then
+    echo "Expected 400 for encryption with PDF/A, got $STATUS"
+    exit 1
+fi
+echo "Generated output_encrypted.pdf"
+
+echo "23. Testing /render (Factur-X attachment)..."
+FACTURX=$(printf '%s' '<?xml version="1.0" encoding="UTF-8"?><rsm:CrossIndustryInvoice xmlns:rsm="urn:un:unece:uncefact:data:standard:CrossIndustryInvoice:100" xmlns:ram="urn:un:unece:uncefact:data:standard:ReusableAggregateBusinessInformationEntity:100"><rsm:ExchangedDocumentContext><ram:GuidelineSpecifiedDocumentContextParameter><ram:ID>urn:cen.eu:en16931:2017</ram:ID></ram:GuidelineSpecifiedDocumentContextParameter></rsm:ExchangedDocumentContext></rsm:CrossIndustryInvoice>' | base64 | tr -d '\n')
+curl -f -X POST "$BASE_URL/render" \
+  -H "Content-Type: application/json" \
+  -d '{
+    "template_html": "<html><body><h1>Invoice 42</h1></body></html>",
+    "data": {},
+    "options": { "paper_format": "A4", "pdf_a_level": "3b" },
+    "attachments": [ { "filename": "factur-x.xml", "mime": "text/xml", "content_base64": "'"$FACTURX"'" } ]
+  }' --output output_facturx.pdf
+grep -a -q "/AFRelationship" output_facturx.pdf
+grep -a -q "/EmbeddedFiles" output_facturx.pdf
+STATUS=$(curl -s -o /dev/null -w "%{http_code}" -X POST "$BASE_URL/render" \
+  -H "Content-Type: application/json" \
+  -d '{"template_html": "<h1>x</h1>", "data": {}, "options": { "paper_format": "A4", "pdf_a_level": "2b" }, "attachments": [ { "filename": "a.txt", "mime": "text/plain", "content_base64": "eA==" } ]}')
+if [ "$STATUS" != "400" ]; then
+    echo "Expected 400 for attachments with PDF/A-2, got $STATUS"
+    exit 1
+fi
+echo "Generated output_facturx.pdf"
+
+echo "24. Testing /render (linearized PDF/A)..."
+curl -f -X POST "$BASE_URL/render" \
+  -H "Content-Type: application/json" \
+  -d '{
+    "template_html": "<html><body>{% for i in range(end=40) %}<h2>Section {{ i }}</h2><p>Lorem ipsum dolor sit amet.</p>{% endfor %}</body></html>",
+    "data": {},
+    "options": { "paper_format": "A4", "pdf_a_level": "2b", "pdf_a_validation": "strict", "linearize": true }
+  }' --output output_linearized.pdf
+head -c 1024 output_linearized.pdf | grep -a -q "/Linearized"
+echo "Generated output_linearized.pdf"
+
+echo "25. Testing /render (CMYK output intent)..."
+STATUS=$(curl -s -o /dev/null -w "%{http_code}" -X POST "$BASE_URL/render" \
+  -H "Content-Type: application/json" \
+  -d '{"template_html": "<h1>x</h1>", "data": {}, "options": { "paper_format": "A4", "color_mode": "cmyk" }}')
+if [ "$STATUS" != "400" ]; then
+    echo "Expected 400 for cmyk without an ICC profile, got $STATUS"
+    exit 1
+fi
+if [ -n "$E2E_CMYK_PROFILE" ]; then
+    PROFILE=$(base64 < "$E2E_CMYK_PROFILE" | tr -d '\n')
+    curl -f -X POST "$BASE_URL/render" \
+      -H "Content-Type: application/json" \
+      -d '{
+        "template_html": "<html><body><h1 style=\"color: #c00\">Flyer</h1></body></html>",
+        "data": {},
+        "options": { "paper_format": "A4", "pdf_a_level": "2b", "color_mode": "cmyk", "icc_profile": { "icc_base64": "'"$PROFILE"'", "output_condition_identifier": "E2E press" } }
+      }' --output output_cmyk.pdf
+    grep -a -q "/OutputIntents" output_cmyk.pdf
+    grep -a -q "E2E press" output_cmyk.pdf
+    echo "Generated output_cmyk.pdf"
+    curl -f -X POST "$BASE_URL/render" \
+      -H "Content-Type: application/json" \
+      -d '{
+        "template_html": "<html><body><h1 style=\"color: #c00\">Flyer</h1></body></html>",
+        "data": {},
+        "options": { "paper_format": "A4", "color_mode": "cmyk", "icc_profile": { "icc_base64": "'"$PROFILE"'", "output_condition_identifier": "E2E press" } }
+      }' --output output_cmyk_plain.pdf
+    if grep -a -q "/OutputIntents" output_cmyk_plain.pdf; then
+        echo "Plain CMYK output must not carry an OutputIntent"
+        exit 1
+    fi
+else
+    echo "Set E2E_CMYK_PROFILE to a CMYK .icc file to test CMYK conversion"
+fi
+
+echo "26. Testing /render (grayscale and monochrome)..."
+curl -f -X POST "$BASE_URL/render" \
+  -H "Content-Type: application/json" \
+  -d '{
+    "template_html": "<html><body><h1 style=\"color: #06c\">Internal report</h1></body></html>",
+    "data": {},
+    "options": { "paper_format": "A4", "pdf_a_level": "2b", "color_mode": "gray" }
+  }' --output output_gray.pdf
+echo "Generated output_gray.pdf"
+curl -f -X POST "$BASE_URL/render" \
+  -H "Content-Type: application/json" \
+  -d '{
+    "template_html": "<html><body><h1>Petition</h1><p>Filed by fax.</p></body></html>",
+    "data": {},
+    "options": { "paper_format": "A4", "color_mode": "mono" }
+  }' --output output_mono.pdf
+grep -a -q "/BitsPerComponent 1" output_mono.pdf
+STATUS=$(curl -s -o /dev/null -w "%{http_code}" -X POST "$BASE_URL/render" \
+  -H "Content-Type: application/json" \
+  -d '{"template_html": "<h1>x</h1>", "data": {}, "options": { "paper_format": "A4", "color_mode": "gray", "tagged": true }}')
+if [ "$STATUS" != "400" ]; then
+    echo "Expected 400 for gray with tagged output, got $STATUS"
+    exit 1
+fi
+echo "Generated output_mono.pdf"
+
 echo "Tests completed."
@@ -0,0 +1,91 @@
+#!/bin/bash
+# Starts the service image with non-default settings and checks how it
+# behaves. Unlike e2e.sh, each case runs its own container.
+#
+#   docker build -t pdfsynth .
+#   E2E_IMAGE=pdfsynth ./tests/e2e_config.sh
+set -e
+
+IMAGE="${E2E_IMAGE:?Set E2E_IMAGE to the service image, e.g. docker build -t pdfsynth .}"
+PORT="${E2E_CONFIG_PORT:-8090}"
+BASE_URL="http://localhost:$PORT"
+CONTAINER=""
+
+stop() {
+    if [ -n "$CONTAINER" ]; then
+        docker rm -f "$CONTAINER" > /dev/null
+        CONTAINER=""
+    fi
+}
+trap stop EXIT
+
+# Runs the image with extra `docker run` arguments and waits for /health.
+start() {
+    stop
+    CONTAINER=$(docker run -d -p "$PORT:8080" "$@" "$IMAGE")
+    for i in {1..30}; do
+        if curl -s "$BASE_URL/health" | grep -q "ok"; then
+            return 0
+        fi
+        sleep 1
+    done
+    docker logs "$CONTAINER"
+    echo "Service did not start"
+    exit 1
+}
+
+echo "1. Testing GS_TIMEOUT_SECS (slow PDF/A conversion)..."
+start -e GS_TIMEOUT_SECS=1
+STATUS=$(curl -s -o gs_timeout.json -w "%{http_code}" -X POST "$BASE_URL/render" \
+  -H "Content-Type: application/json" \
+  -d '{
+    "template_html": "<html><body>{% for i in range(end=800) %}<section style=\"page-break-after: always\"><h1>Page {{ i }}</h1><p>Lorem ipsum dolor sit amet, consectetur adipiscing elit.</p><p>Sed do eiusmod tempor incididunt ut labore et dolore magna aliqua.</p></section>{% endfor %}</body></html>",
+    "data": {},
+    "options": { "pdf_a": true, "paper_format": "A4" }
+  }')
+cat gs_timeout.json
+echo ""
+if [ "$STATUS" != "500" ] || ! grep -q '"error_type":"ghostscript_error"' gs_timeout.json || ! grep -q "timed out" gs_timeout.json; then
+    echo "Expected 500 ghostscript_error for a conversion past the deadline, got $STATUS"
+    exit 1
+fi
+sleep 1
+if docker top "$CONTAINER" | grep -q "gs "; then
+    docker top "$CONTAINER"
+    echo "Ghostscript still running after the deadline"
+    exit 1
+fi
+curl -f -s -X POST "$BASE_URL/render" \
+  -H "Content-Type: application/json" \
+  -d '{"template_html": "<h1>Still up</h1>", "data": {}, "options": { "pdf_a": false, "paper_format": "A4" }}' \
+  --output /dev/null
+echo "Slow conversion killed with ghostscript_error"
+
+echo "2. Testing ASSETS_DIR that cannot be used..."
+ASSETS_TMP=$(mktemp -d)
+cp assets/PDFA_def.ps "$ASSETS_TMP/"
+chmod -R a+rX "$ASSETS_TMP"
+for ARGS in "-e ASSETS_DIR=/nonexistent" "-e ASSETS_DIR=/assets -v $ASSETS_TMP:/assets:ro"; do
+    set +e
+    OUTPUT=$(timeout 60 docker run --rm $ARGS "$IMAGE" 2>&1)
+    CODE=$?
+    set -e
+    if [ "$CODE" = "0" ] || [ "$CODE" = "124" ] || ! echo "$OUTPUT" | grep -q "assets_init_failed"; then
+        echo "$OUTPUT"
+        echo "Expected startup to fail with $ARGS, exit code $CODE"
+        exit 1
+    fi
+done
+rm -rf "$ASSETS_TMP"
+echo "Startup fails without usable assets"
+
+echo "3. Testing a working directory other than /app..."
+start -w /tmp
+curl -f -X POST "$BASE_URL/render" \
+  -H "Content-Type: application/json" \
+  -d '{"template_html": "<h1>Elsewhere</h1>", "data": {}, "options": { "pdf_a": true, "paper_format": "A4" }}' \
+  --output output_workdir.pdf
+grep -a -q "pdfaid:part" output_workdir.pdf
+echo "Generated output_workdir.pdf"
+
+echo "Tests completed."
@@ -0,0 +1,119 @@
+#!/usr/bin/env python3
+"""Local RFC 3161 time-stamp authority for tests.
+
+Creates a throwaway CA and TSA certificate, then answers time-stamp
+requests with `openssl ts -reply`. With --keys-dir it also writes an
+`e2e.p12` signing keystore (empty password) issued by the same CA.
+
+    python3 tests/mock_tsa.py --keys-dir ./signing-keys
+    SIGNING_TSA_URL=http://127.0.0.1:8318/ E2E_SIGNING_KEY=e2e ./tests/e2e.sh
+"""
+
+import argparse
+import http.server
+import os
+import subprocess
+import tempfile
+
+TSA_CONFIG = """
+[ tsa ]
+default_tsa = tsa_config
+[ tsa_config ]
+dir = {dir}
+serial = {dir}/serial
+signer_cert = {dir}/tsa.crt
+signer_key = {dir}/tsa.key
+certs = {dir}/ca.crt
+signer_digest = sha256
+default_policy = 1.2.3.4.1
+digests = sha256
+accuracy = secs:1
+ordering = no
+tsa_name = no
+ess_cert_id_alg = sha256
+ess_cert_id_chain = no
+[ tsa_ext ]
+extendedKeyUsage = critical,timeStamping
+[ signer_ext ]
+keyUsage = critical,digitalSignature,nonRepudiation
+"""
+
+
+def openssl(*args):
+    subprocess.run(["openssl", *args], check=True, capture_output=True)
+
+
+def issue(directory, name, subject, extensions):
+    """Key and certificate for `subject`, issued by the test CA."""
+    key = os.path.join(directory, name + ".key")
+    csr = os.path.join(directory, name + ".csr")
+    openssl("req", "-new", "-newkey", "rsa:2048", "-nodes", "-keyout", key, "-subj", subject, "-out", csr)
+    openssl(
+        "x509", "-req", "-in", csr, "-days", "30",
+        "-CA", os.path.join(directory, "ca.crt"), "-CAkey", os.path.join(directory, "ca.key"), "-CAcreateserial",
+        "-extfile", os.path.join(directory, "tsa.cnf"), "-extensions", extensions,
+        "-out", os.path.join(directory, name + ".crt"),
+    )
+
+
+def setup(directory, keys_dir):
+    with open(os.path.join(directory, "tsa.cnf"), "w") as config:
+        config.write(TSA_CONFIG.format(dir=directory))
+    with open(os.path.join(directory, "serial"), "w") as serial:
+        serial.write("01\n")
+    openssl(
+        "req", "-x509", "-newkey", "rsa:2048", "-nodes", "-days", "30", "-subj", "/CN=pdfsynth test CA",
+        "-keyout", os.path.join(directory, "ca.key"), "-out", os.path.join(directory, "ca.crt"),
+    )
+    issue(directory, "tsa", "/CN=pdfsynth test TSA", "tsa_ext")
+    if keys_dir:
+        os.makedirs(keys_dir, exist_ok=True)
+        issue(directory, "signer", "/CN=pdfsynth test signer", "signer_ext")
+        openssl(
+            "pkcs12", "-export", "-passout", "pass:",
+            "-inkey", os.path.join(directory, "signer.key"), "-in", os.path.join(directory, "signer.crt"),
+            "-certfile", os.path.join(directory, "ca.crt"), "-out", os.path.join(keys_dir, "e2e.p12"),
+        )
+        print("Wrote", os.path.join(keys_dir, "e2e.p12"), flush=True)
+
+
+def handler(directory):
+    class Handler(http.server.BaseHTTPRequestHandler):
+        def do_POST(self):
+            query = self.rfile.read(int(self.headers.get("Content-Length", 0)))
+            reply = subprocess.run(
+                ["openssl", "ts", "-reply", "-config", os.path.join(directory, "tsa.cnf"),
+                 "-queryfile", "/dev/stdin", "-out", "/dev/stdout"],
+                input=query, capture_output=True,
+            )
+            if reply.returncode != 0 or not reply.stdout:
+                self.send_error(500, reply.stderr.decode(errors="replace").strip())
+                return
+            self.send_response(200)
+            self.send_header("Content-Type", "application/timestamp-reply")
+            self.send_header("Content-Length", str(len(reply.stdout)))
+            self.end_headers()
+            self.wfile.write(reply.stdout)
+
+    return Handler
+
+
+def main():
+    parser = argparse.ArgumentParser(description=__doc__.splitlines()[0])
+    parser.add_argument("--port", type=int, default=int(os.environ.get("MOCK_TSA_PORT", "8318")))
+    parser.add_argument("--bind", default="127.0.0.1")
+    parser.add_argument("--keys-dir", help="also write an e2e.p12 signing keystore here")
+    args = parser.parse_args()
+
+    with tempfile.TemporaryDirectory(prefix="mock-tsa-") as directory:
+        setup(directory, args.keys_dir)
+        server = http.server.HTTPServer((args.bind, args.port), handler(directory))
+        print("Mock TSA listening on http://{}:{}/".format(args.bind, args.port), flush=True)
+        try:
+            server.serve_forever()
+        except KeyboardInterrupt:
+            pass
+
+
+if __name__ == "__main__":
+    main()